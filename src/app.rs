@@ -1,7 +1,17 @@
-use crate::{geo::Coord, map::MainMap, route::GpxFile};
+use crate::{
+    clean_panel::{CleanPanel, CleanPreview},
+    edit_panel::{EditPanel, Selection},
+    geo::Coord,
+    layers::{FileAction, LayerList, LoadedFiles},
+    map::MainMap,
+    position::PositionDisplay,
+    privacy::PrivacySettings,
+    privacy_panel::PrivacyPanel,
+    route::GpxFile,
+    track::{TrackDocument, TrackPointId},
+};
 
 use gloo_utils::window;
-use gpx::Gpx;
 use log::info;
 use web_sys::{
     wasm_bindgen::{closure::Closure, JsCast},
@@ -11,7 +21,15 @@ use yew::prelude::*;
 #[function_component(App)]
 pub fn app() -> Html {
     let pos = use_state(Coord::default); // Use state hook trigger re-rendering when state changes.
-    let gpx_state = use_state(Gpx::default); // Use state hook trigger re-rendering when state changes.
+
+    // A reducer rather than a state hook, so files that finish loading together are all kept.
+    let files_state = use_reducer(LoadedFiles::default);
+    // File whose tracks are being cleaned, and the result shown on the map meanwhile.
+    let cleaning = use_state(|| None::<usize>);
+    let clean_preview = use_state(|| None::<CleanPreview>);
+    // Track point clicked for editing.
+    let selection = use_state(|| None::<Selection>);
+    let privacy = use_state(PrivacySettings::load);
 
     {
         let pos = pos.clone();
@@ -58,19 +76,129 @@ pub fn app() -> Html {
         });
     }
 
-    let gpx_state_clone = gpx_state.clone();
-    let on_gpx_update = Callback::from(move |gpx: Option<Gpx>| {
-        // info!("GpxFile on_gpx_update: {:?}", gpx);
-        if let Some(gpx) = gpx {
-            gpx_state_clone.set(gpx);
-        }
+    let files_dispatcher = files_state.dispatcher();
+    let on_gpx_update = Callback::from(move |(name, tracks): (String, TrackDocument)| {
+        files_dispatcher.dispatch(FileAction::Add(name, tracks));
+    });
+    let on_file_action = {
+        let (files_dispatcher, selection) = (files_state.dispatcher(), selection.clone());
+        Callback::from(move |action: FileAction| {
+            // Points are selected by position, which these may change.
+            if matches!(
+                action,
+                FileAction::Remove(_) | FileAction::Undo(_) | FileAction::Redo(_)
+            ) {
+                selection.set(None);
+            }
+            files_dispatcher.dispatch(action)
+        })
+    };
+    let on_clean = {
+        let cleaning = cleaning.clone();
+        Callback::from(move |id| cleaning.set(Some(id)))
+    };
+    let on_clean_preview = {
+        let clean_preview = clean_preview.clone();
+        Callback::from(move |preview| clean_preview.set(preview))
+    };
+    let cleaning_file = cleaning.and_then(|id| files_state.get(id)).cloned();
+    let on_clean_done = {
+        let (cleaning, files_dispatcher) = (cleaning.clone(), files_state.dispatcher());
+        let selection = selection.clone();
+        Callback::from(move |tracks: Option<TrackDocument>| {
+            if let (Some(id), Some(tracks)) = (*cleaning, tracks) {
+                files_dispatcher.dispatch(FileAction::Replace(id, tracks));
+                selection.set(None);
+            }
+            cleaning.set(None);
+        })
+    };
+    let on_track_click = {
+        let selection = selection.clone();
+        Callback::from(move |(file, point): (usize, TrackPointId)| {
+            // Keep a marked range start while clicking along the same file.
+            let range_start = selection
+                .filter(|selected| selected.file == file)
+                .and_then(|selected| selected.range_start);
+            selection.set(Some(Selection {
+                file,
+                point,
+                range_start,
+            }));
+        })
+    };
+    let on_select = {
+        let selection = selection.clone();
+        Callback::from(move |selected| selection.set(selected))
+    };
+    let editing = (*selection).and_then(|selected| {
+        let file = files_state.get(selected.file)?;
+        Some((file.clone(), selected))
     });
+    let selected_coords = editing
+        .as_ref()
+        .map(|(file, selected)| selected.coords(&file.tracks))
+        .unwrap_or_default();
+    let on_edit = {
+        let (files_dispatcher, selection) = (files_state.dispatcher(), selection.clone());
+        Callback::from(move |tracks| {
+            if let Some(selected) = *selection {
+                files_dispatcher.dispatch(FileAction::Replace(selected.file, tracks));
+            }
+            selection.set(None);
+        })
+    };
+    let on_privacy_change = {
+        let privacy = privacy.clone();
+        Callback::from(move |settings: PrivacySettings| {
+            settings.save();
+            privacy.set(settings);
+        })
+    };
 
     html! {
         <main>
-            <MainMap pos={*pos} gpx={(*gpx_state).clone()}/>
-            <GpxFile on_gpx_update={on_gpx_update}/>
-            // <p>{ format!("gpx: {:?}", (*gpx_state).clone()s) }</p>
+            <GpxFile on_gpx_update={on_gpx_update}>
+                <MainMap
+                    pos={*pos}
+                    files={(*files_state).clone()}
+                    preview={(*clean_preview).clone()}
+                    on_track_click={on_track_click}
+                    selected={selected_coords.clone()}
+                    zones={privacy.zones.clone()}
+                />
+            </GpxFile>
+            <PositionDisplay pos={*pos}/>
+            <LayerList
+                files={files_state.files.clone()}
+                on_action={on_file_action}
+                on_clean={on_clean}
+                privacy={(*privacy).clone()}
+            />
+            if let Some(file) = cleaning_file {
+                <CleanPanel
+                    key={file.id}
+                    file={file.clone()}
+                    on_preview={on_clean_preview}
+                    on_done={on_clean_done}
+                />
+            }
+            if let Some((file, selected)) = editing {
+                <EditPanel
+                    key={file.id}
+                    file={file.clone()}
+                    selection={selected}
+                    {on_edit}
+                    {on_select}
+                />
+            }
+            <PrivacyPanel
+                settings={(*privacy).clone()}
+                on_change={on_privacy_change}
+                pos={*pos}
+                selected={selected_coords.first().copied()}
+            />
+            // <p>{ format!("tracks: {:?}", (*tracks_state).clone()) }</p>
         </main>
     }
 }
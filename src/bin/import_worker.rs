@@ -0,0 +1,9 @@
+//! Web Worker that parses imported files, so large ones don't freeze the map.
+//! Trunk builds it alongside the app; see `index.html`.
+
+use log::Level;
+
+fn main() {
+    let _ = console_log::init_with_level(Level::Info);
+    wasmyroute::worker::serve();
+}
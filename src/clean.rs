@@ -0,0 +1,310 @@
+//! Cleaning recorded tracks: dropping duplicate points, GPS spikes and the jitter recorded
+//! while standing still. Each filter runs over every track segment in turn, and reports how
+//! many points and how much distance it took out. Routes are planned, so they are left alone.
+
+use crate::geo::{path_length, Coord};
+use crate::track::{TrackDocument, TrackPoint};
+
+/// Thresholds for the filters.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CleanOptions {
+    /// Fastest believable speed, in metres per second. Points only reachable faster are spikes.
+    pub max_speed: f64,
+    /// Points staying within this many metres of each other...
+    pub stationary_radius: f64,
+    /// ...for at least this many seconds were recorded while stationary.
+    pub stationary_time: f64,
+}
+
+impl Default for CleanOptions {
+    fn default() -> Self {
+        CleanOptions {
+            // 180 km/h, well beyond anything on a bike or on foot.
+            max_speed: 50.0,
+            stationary_radius: 10.0,
+            stationary_time: 30.0,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CleanFilter {
+    /// Points at the same place or time as the one before.
+    Duplicates,
+    /// Points that would mean moving faster than [`CleanOptions::max_speed`].
+    Spikes,
+    /// Runs of points recorded while standing still, reduced to their first and last.
+    Stationary,
+}
+
+impl CleanFilter {
+    /// The filters, in the order they run.
+    pub const ALL: [CleanFilter; 3] = [
+        CleanFilter::Duplicates,
+        CleanFilter::Spikes,
+        CleanFilter::Stationary,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CleanFilter::Duplicates => "Duplicate points",
+            CleanFilter::Spikes => "GPS spikes",
+            CleanFilter::Stationary => "Stationary clusters",
+        }
+    }
+
+    /// Which of `points` to keep.
+    fn keep(&self, points: &[TrackPoint], options: &CleanOptions) -> Vec<bool> {
+        match self {
+            CleanFilter::Duplicates => keep_distinct(points),
+            CleanFilter::Spikes => keep_believable(points, options.max_speed),
+            CleanFilter::Stationary => {
+                keep_moving(points, options.stationary_radius, options.stationary_time)
+            }
+        }
+    }
+}
+
+/// What one filter removed.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct FilterReport {
+    pub filter: CleanFilter,
+    pub points: usize,
+    /// Metres the tracks became shorter by.
+    pub metres: f64,
+}
+
+/// A cleaned copy of a document, with what was taken out of it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Cleaned {
+    pub document: TrackDocument,
+    /// One report per filter, in the order they ran.
+    pub reports: Vec<FilterReport>,
+    /// Where the removed points were.
+    pub removed: Vec<Coord>,
+}
+
+/// Seconds from `a` to `b`, if both have a time.
+fn seconds_between(a: &TrackPoint, b: &TrackPoint) -> Option<f64> {
+    Some((b.time? - a.time?).as_seconds_f64())
+}
+
+fn keep_distinct(points: &[TrackPoint]) -> Vec<bool> {
+    let mut keep = vec![true; points.len()];
+    let mut last: Option<&TrackPoint> = None;
+    for (i, point) in points.iter().enumerate() {
+        if let Some(last) = last {
+            if point.coord == last.coord || (point.time.is_some() && point.time == last.time) {
+                keep[i] = false;
+                continue;
+            }
+        }
+        last = Some(point);
+    }
+    keep
+}
+
+/// Drop a point when getting to it from the last point kept is too fast, but skipping it is
+/// not, so that one bad fix doesn't take the rest of the track with it. Untimed points are
+/// never spikes.
+fn keep_believable(points: &[TrackPoint], max_speed: f64) -> Vec<bool> {
+    let too_fast = |a: &TrackPoint, b: &TrackPoint| {
+        seconds_between(a, b)
+            .filter(|&seconds| seconds > 0.0)
+            .is_some_and(|seconds| a.coord.haversine_distance(&b.coord) / seconds > max_speed)
+    };
+    let mut keep = vec![true; points.len()];
+    let mut last: Option<usize> = None;
+    for i in 0..points.len() {
+        let spike = match last {
+            Some(last) => {
+                too_fast(&points[last], &points[i])
+                    && points
+                        .get(i + 1)
+                        .is_none_or(|next| !too_fast(&points[last], next))
+            }
+            // A bad first fix shows as a jump to the second point that the third doesn't repeat.
+            None => match (points.get(i + 1), points.get(i + 2)) {
+                (Some(next), Some(after)) => too_fast(&points[i], next) && !too_fast(next, after),
+                _ => false,
+            },
+        };
+        if spike {
+            keep[i] = false;
+        } else {
+            last = Some(i);
+        }
+    }
+    keep
+}
+
+/// Reduce each run of points that stay within `radius` metres of its first point for at least
+/// `min_seconds` to its first and last points, keeping the time spent there.
+fn keep_moving(points: &[TrackPoint], radius: f64, min_seconds: f64) -> Vec<bool> {
+    let mut keep = vec![true; points.len()];
+    let mut start = 0;
+    while start < points.len() {
+        let anchor = &points[start];
+        let end = points[start + 1..]
+            .iter()
+            .position(|point| anchor.coord.haversine_distance(&point.coord) > radius)
+            .map_or(points.len() - 1, |offset| start + offset);
+        let stopped = seconds_between(anchor, &points[end]).is_some_and(|s| s >= min_seconds);
+        if stopped && end > start + 1 {
+            keep[start + 1..end]
+                .iter_mut()
+                .for_each(|keep| *keep = false);
+        }
+        start = end.max(start + 1);
+    }
+    keep
+}
+
+/// Run every filter over the tracks of `document`.
+pub fn clean(document: &TrackDocument, options: &CleanOptions) -> Cleaned {
+    let mut document = document.clone();
+    let mut removed = Vec::new();
+    let reports = CleanFilter::ALL
+        .iter()
+        .map(|filter| {
+            let mut report = FilterReport {
+                filter: *filter,
+                points: 0,
+                metres: 0.0,
+            };
+            let segments = document
+                .tracks
+                .iter_mut()
+                .flat_map(|track| track.segments.iter_mut());
+            for segment in segments {
+                let before = path_length(&segment.coords());
+                let keep = filter.keep(&segment.points, options);
+                let (kept, dropped): (Vec<_>, Vec<_>) = std::mem::take(&mut segment.points)
+                    .into_iter()
+                    .zip(keep)
+                    .partition(|(_, keep)| *keep);
+                segment.points = kept.into_iter().map(|(point, _)| point).collect();
+                report.points += dropped.len();
+                report.metres += before - path_length(&segment.coords());
+                removed.extend(dropped.into_iter().map(|(point, _)| point.coord));
+            }
+            report
+        })
+        .collect();
+    Cleaned {
+        document,
+        reports,
+        removed,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::{Segment, Track};
+    use time::{macros::datetime, Duration};
+
+    /// Points heading north, about 11 m and one second apart, with the given offsets in
+    /// metres north of where they would otherwise be.
+    fn walk(offsets: &[f64]) -> Vec<TrackPoint> {
+        let start = Coord::new(52.2, 0.12);
+        offsets
+            .iter()
+            .enumerate()
+            .map(|(i, offset)| TrackPoint {
+                coord: start.destination(0.0, i as f64 * 11.0 + offset),
+                time: Some(datetime!(2024-05-01 09:30 UTC) + Duration::seconds(i as i64)),
+                ..TrackPoint::default()
+            })
+            .collect()
+    }
+
+    fn document(points: Vec<TrackPoint>) -> TrackDocument {
+        TrackDocument {
+            tracks: vec![Track {
+                segments: vec![Segment { points }],
+                ..Track::default()
+            }],
+            ..TrackDocument::default()
+        }
+    }
+
+    #[test]
+    fn test_duplicates() {
+        let mut points = walk(&[0.0, 0.0, 0.0]);
+        points.insert(1, points[0].clone());
+        let mut same_time = points[2].clone();
+        same_time.coord = Coord::new(52.3, 0.12);
+        points.insert(3, same_time);
+        assert_eq!(keep_distinct(&points), vec![true, false, true, false, true]);
+    }
+
+    #[test]
+    fn test_spikes() {
+        // A 500 m jump one second out and back.
+        let points = walk(&[0.0, 0.0, 500.0, 0.0, 0.0]);
+        assert_eq!(
+            keep_believable(&points, 50.0),
+            vec![true, true, false, true, true]
+        );
+        let points = walk(&[-500.0, 0.0, 0.0, 0.0]);
+        assert_eq!(
+            keep_believable(&points, 50.0),
+            vec![false, true, true, true]
+        );
+        let last = walk(&[0.0, 0.0, 500.0]);
+        assert_eq!(keep_believable(&last, 50.0), vec![true, true, false]);
+
+        // Without times there is no speed to judge by.
+        let mut untimed = walk(&[0.0, 500.0, 0.0]);
+        untimed.iter_mut().for_each(|point| point.time = None);
+        assert_eq!(keep_believable(&untimed, 50.0), vec![true; 3]);
+    }
+
+    #[test]
+    fn test_stationary() {
+        // Ride, stop for a minute jittering within a few metres, then ride on.
+        let mut points = walk(&[0.0; 3]);
+        let stop = points[2].clone();
+        points.extend((1..=60).map(|i| TrackPoint {
+            coord: stop.coord.destination(i as f64 * 6.0, 3.0),
+            time: stop.time.map(|time| time + Duration::seconds(i)),
+            ..TrackPoint::default()
+        }));
+        let last = points.last().unwrap().clone();
+        points.extend((1..=3).map(|i| TrackPoint {
+            coord: last.coord.destination(0.0, i as f64 * 11.0),
+            time: last.time.map(|time| time + Duration::seconds(i)),
+            ..TrackPoint::default()
+        }));
+
+        let keep = keep_moving(&points, 10.0, 30.0);
+        assert_eq!(keep.iter().filter(|keep| !**keep).count(), 59);
+        assert!(keep[2] && keep[62]);
+
+        // A short pause is left as it was.
+        assert!(keep_moving(&points, 10.0, 120.0).iter().all(|keep| *keep));
+    }
+
+    #[test]
+    fn test_clean() {
+        let mut points = walk(&[0.0, 0.0, 0.0, 500.0, 0.0, 0.0]);
+        points.insert(1, points[0].clone());
+        let cleaned = clean(&document(points), &CleanOptions::default());
+
+        assert_eq!(cleaned.document.tracks[0].points().count(), 5);
+        assert_eq!(cleaned.removed.len(), 2);
+        let [duplicates, spikes, stationary] = cleaned.reports[..] else {
+            panic!("{:?}", cleaned.reports);
+        };
+        assert_eq!((duplicates.points, duplicates.metres), (1, 0.0));
+        assert_eq!(spikes.points, 1);
+        // 511 m out and 489 m back, instead of 22 m straight on.
+        assert!((spikes.metres - 978.0).abs() < 0.01, "{:?}", spikes);
+        assert_eq!(stationary.points, 0);
+
+        // The sample route has no times, and no repeated points.
+        let sample = crate::track::tests::sample_document();
+        assert_eq!(clean(&sample, &CleanOptions::default()).document, sample);
+    }
+}
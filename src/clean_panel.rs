@@ -0,0 +1,121 @@
+use std::rc::Rc;
+
+use web_sys::HtmlInputElement;
+use yew::prelude::*;
+
+use crate::clean::{clean, CleanOptions, Cleaned};
+use crate::geo::path_length;
+use crate::layers::LoadedFile;
+use crate::track::TrackDocument;
+
+/// A cleaning result shown on the map before it is applied.
+#[derive(Clone, Debug)]
+pub struct CleanPreview(pub Rc<Cleaned>);
+
+// A new result is computed whenever the options change, so comparing the pointer is enough,
+// as for `LoadedFile`.
+impl PartialEq for CleanPreview {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct CleanPanelProps {
+    pub file: LoadedFile,
+    /// Called with each new result, and with `None` when the panel closes.
+    pub on_preview: Callback<Option<CleanPreview>>,
+    /// Called with the cleaned tracks, or `None` if cleaning was cancelled.
+    pub on_done: Callback<Option<TrackDocument>>,
+}
+
+/// Number of track points in `document`, and their length in metres.
+fn track_totals(document: &TrackDocument) -> (usize, f64) {
+    let segments = document
+        .tracks
+        .iter()
+        .flat_map(|track| track.segments.iter());
+    segments.fold((0, 0.0), |(points, metres), segment| {
+        (
+            points + segment.points.len(),
+            metres + path_length(&segment.coords()),
+        )
+    })
+}
+
+/// Panel for cleaning a file's tracks: the filter thresholds, what each filter would remove,
+/// and the totals before and after. The result is previewed on the map until applied.
+#[function_component(CleanPanel)]
+pub fn clean_panel(props: &CleanPanelProps) -> Html {
+    let options = use_state(CleanOptions::default);
+    let preview = use_memo((props.file.clone(), *options), |(file, options)| {
+        CleanPreview(Rc::new(clean(&file.tracks, options)))
+    });
+    {
+        let on_preview = props.on_preview.clone();
+        use_effect_with((*preview).clone(), move |preview| {
+            on_preview.emit(Some(preview.clone()));
+            move || on_preview.emit(None)
+        });
+    }
+    // Each input shows its option in friendlier units, `scale` of them to the option's unit.
+    let input = |label: &'static str, value: f64, scale: f64, set: fn(&mut CleanOptions, f64)| {
+        let options = options.clone();
+        let onchange = Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let value = input.value_as_number();
+            if value.is_finite() && value > 0.0 {
+                let mut changed = *options;
+                set(&mut changed, value / scale);
+                options.set(changed);
+            }
+        });
+        html! {
+            <label>
+                { label }
+                <input type="number" min="1" value={(value * scale).round().to_string()} {onchange}/>
+            </label>
+        }
+    };
+    let cleaned = &preview.0;
+    let (before_points, before_metres) = track_totals(&props.file.tracks);
+    let (after_points, after_metres) = track_totals(&cleaned.document);
+    let on_apply = {
+        let (on_done, cleaned) = (props.on_done.clone(), cleaned.clone());
+        Callback::from(move |_: MouseEvent| on_done.emit(Some(cleaned.document.clone())))
+    };
+    let on_cancel = props.on_done.reform(|_: MouseEvent| None);
+
+    html! {
+        <div class="clean-panel">
+            <h3>{ format!("Clean {}", props.file.name) }</h3>
+            { input("Fastest speed (km/h)", options.max_speed, 3.6, |o, v| o.max_speed = v) }
+            { input("Stationary within (m)", options.stationary_radius, 1.0, |o, v| {
+                o.stationary_radius = v
+            }) }
+            { input("for at least (s)", options.stationary_time, 1.0, |o, v| o.stationary_time = v) }
+            <table class="clean-report">
+                <tr><th>{ "Filter" }</th><th>{ "Points" }</th><th>{ "Metres" }</th></tr>
+                { for cleaned.reports.iter().map(|report| html! {
+                    <tr>
+                        <td>{ report.filter.label() }</td>
+                        <td>{ report.points }</td>
+                        <td>{ format!("{:.0}", report.metres) }</td>
+                    </tr>
+                }) }
+                <tr>
+                    <td>{ "Before" }</td>
+                    <td>{ before_points }</td>
+                    <td>{ format!("{:.0}", before_metres) }</td>
+                </tr>
+                <tr>
+                    <td>{ "After" }</td>
+                    <td>{ after_points }</td>
+                    <td>{ format!("{:.0}", after_metres) }</td>
+                </tr>
+            </table>
+            <button onclick={on_apply} disabled={cleaned.removed.is_empty()}>{ "Apply" }</button>
+            <button onclick={on_cancel}>{ "Cancel" }</button>
+        </div>
+    }
+}
@@ -0,0 +1,150 @@
+use std::rc::Rc;
+
+use web_sys::HtmlSelectElement;
+use yew::prelude::*;
+
+use crate::csv::{ColumnMapping, CsvRows, CsvTable};
+
+/// Rows of the table shown while choosing columns.
+const PREVIEW_ROWS: usize = 5;
+
+#[derive(Properties, PartialEq)]
+pub struct ColumnDialogProps {
+    /// Name of the file being imported.
+    pub name: String,
+    pub table: Rc<CsvTable>,
+    /// Called with the chosen columns, or `None` if the import was cancelled.
+    pub on_done: Callback<Option<ColumnMapping>>,
+}
+
+#[derive(Properties, PartialEq)]
+struct ColumnSelectProps {
+    label: &'static str,
+    headers: Vec<String>,
+    selected: Option<usize>,
+    /// Offer "None", for columns that need not be imported.
+    optional: bool,
+    on_change: Callback<Option<usize>>,
+}
+
+/// Drop-down list of a table's columns.
+#[function_component(ColumnSelect)]
+fn column_select(props: &ColumnSelectProps) -> Html {
+    let optional = props.optional;
+    let onchange = props.on_change.reform(move |e: Event| {
+        let select: HtmlSelectElement = e.target_unchecked_into();
+        let index = select.selected_index() as usize;
+        match optional {
+            true => index.checked_sub(1),
+            false => Some(index),
+        }
+    });
+    html! {
+        <label>
+            { props.label }
+            <select {onchange}>
+                if props.optional {
+                    <option selected={props.selected.is_none()}>{ "None" }</option>
+                }
+                { for props.headers.iter().enumerate().map(|(i, header)| html! {
+                    <option selected={props.selected == Some(i)}>{ header }</option>
+                }) }
+            </select>
+        </label>
+    }
+}
+
+/// Dialog for choosing which columns of a CSV file hold the coordinates, with a preview of
+/// its first rows.
+#[function_component(ColumnDialog)]
+pub fn column_dialog(props: &ColumnDialogProps) -> Html {
+    let table = props.table.clone();
+    let mapping = use_state(move || {
+        ColumnMapping::guess(&table.headers).unwrap_or(ColumnMapping {
+            longitude: table.headers.len().min(2).saturating_sub(1),
+            ..ColumnMapping::default()
+        })
+    });
+    let headers = &props.table.headers;
+    let update = |set: fn(&mut ColumnMapping, Option<usize>)| {
+        let mapping = mapping.clone();
+        Callback::from(move |column: Option<usize>| {
+            let mut changed = *mapping;
+            set(&mut changed, column);
+            mapping.set(changed);
+        })
+    };
+    let on_rows_change = {
+        let mapping = mapping.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            if let Some(&rows) = CsvRows::ALL.get(select.selected_index() as usize) {
+                mapping.set(ColumnMapping { rows, ..*mapping });
+            }
+        })
+    };
+    let on_import = {
+        let (on_done, mapping) = (props.on_done.clone(), *mapping);
+        Callback::from(move |_: MouseEvent| on_done.emit(Some(mapping)))
+    };
+    let on_cancel = props.on_done.reform(|_: MouseEvent| None);
+
+    html! {
+        <div class="column-dialog">
+            <h3>{ format!("Choose the columns of {}", props.name) }</h3>
+            <ColumnSelect
+                label="Latitude"
+                headers={headers.clone()}
+                selected={Some(mapping.latitude)}
+                optional={false}
+                on_change={update(|m, column| m.latitude = column.unwrap_or_default())}
+            />
+            <ColumnSelect
+                label="Longitude"
+                headers={headers.clone()}
+                selected={Some(mapping.longitude)}
+                optional={false}
+                on_change={update(|m, column| m.longitude = column.unwrap_or_default())}
+            />
+            <ColumnSelect
+                label="Elevation"
+                headers={headers.clone()}
+                selected={mapping.elevation}
+                optional={true}
+                on_change={update(|m, column| m.elevation = column)}
+            />
+            <ColumnSelect
+                label="Time"
+                headers={headers.clone()}
+                selected={mapping.time}
+                optional={true}
+                on_change={update(|m, column| m.time = column)}
+            />
+            <label>
+                { "Import rows as" }
+                <select onchange={on_rows_change}>
+                    { for CsvRows::ALL.iter().map(|rows| html! {
+                        <option selected={*rows == mapping.rows}>{ rows.label() }</option>
+                    }) }
+                </select>
+            </label>
+            if mapping.rows == CsvRows::Waypoints {
+                <ColumnSelect
+                    label="Name"
+                    headers={headers.clone()}
+                    selected={mapping.name}
+                    optional={true}
+                    on_change={update(|m, column| m.name = column)}
+                />
+            }
+            <table class="column-preview">
+                <tr>{ for headers.iter().map(|header| html! { <th>{ header }</th> }) }</tr>
+                { for props.table.rows.iter().take(PREVIEW_ROWS).map(|(_, row)| html! {
+                    <tr>{ for row.iter().map(|field| html! { <td>{ field }</td> }) }</tr>
+                }) }
+            </table>
+            <button onclick={on_import}>{ "Import" }</button>
+            <button onclick={on_cancel}>{ "Cancel" }</button>
+        </div>
+    }
+}
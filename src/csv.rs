@@ -0,0 +1,415 @@
+//! Delimited text import, for coordinates kept in a spreadsheet. The text is first read into a
+//! [`CsvTable`], then a [`ColumnMapping`] picked by the user, or guessed from the headers,
+//! says which columns hold the latitude, longitude and anything else worth keeping.
+
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+use time::{
+    format_description::well_known::Rfc3339, macros::format_description, OffsetDateTime,
+    PrimitiveDateTime,
+};
+
+use crate::geo::Coord;
+use crate::progress::Progress;
+use crate::track::{Segment, Track, TrackDocument, TrackPoint, Waypoint};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum CsvError {
+    /// The text is not well-formed delimited text. Lines count from 1.
+    Syntax { line: usize, message: String },
+    /// No columns could be found for the latitude and longitude.
+    NoColumns,
+    /// A row has a value that cannot be read.
+    Invalid { line: usize, message: String },
+}
+
+impl fmt::Display for CsvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            CsvError::Syntax { line, message } => {
+                write!(f, "CSV error at line {}: {}", line, message)
+            }
+            CsvError::NoColumns => write!(f, "no latitude and longitude columns were found"),
+            CsvError::Invalid { line, message } => {
+                write!(f, "CSV row at line {} is not valid ({})", line, message)
+            }
+        }
+    }
+}
+
+impl std::error::Error for CsvError {}
+
+/// Delimited text split into fields.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct CsvTable {
+    /// Column names, from the header row, or numbered if the text has none.
+    pub headers: Vec<String>,
+    /// Each row, with the line it starts on.
+    pub rows: Vec<(usize, Vec<String>)>,
+}
+
+/// What the rows of a table become.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum CsvRows {
+    /// The points of one track, in order.
+    #[default]
+    Track,
+    /// Separate waypoints, such as a list of places.
+    Waypoints,
+}
+
+impl CsvRows {
+    pub const ALL: [CsvRows; 2] = [CsvRows::Track, CsvRows::Waypoints];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            CsvRows::Track => "One track",
+            CsvRows::Waypoints => "Waypoints",
+        }
+    }
+}
+
+/// Which column of a table holds each value, by index.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct ColumnMapping {
+    pub latitude: usize,
+    pub longitude: usize,
+    pub elevation: Option<usize>,
+    pub time: Option<usize>,
+    /// Waypoint names; ignored for tracks.
+    pub name: Option<usize>,
+    pub rows: CsvRows,
+}
+
+/// A header reduced to lower-case letters and digits, so `Latitude (deg)` reads `latitudedeg`.
+fn normalise(header: &str) -> String {
+    header
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .map(|c| c.to_ascii_lowercase())
+        .collect()
+}
+
+impl ColumnMapping {
+    /// A mapping from the usual column names, if there are latitude and longitude columns.
+    pub fn guess(headers: &[String]) -> Option<ColumnMapping> {
+        let headers: Vec<String> = headers.iter().map(|header| normalise(header)).collect();
+        let find = |prefixes: &[&str]| {
+            headers
+                .iter()
+                .position(|header| prefixes.iter().any(|prefix| header.starts_with(prefix)))
+        };
+        let name = find(&["name", "title", "label"]);
+        Some(ColumnMapping {
+            latitude: find(&["lat"])?,
+            longitude: find(&["lon", "lng"])?,
+            elevation: find(&["ele", "alt"]),
+            time: find(&["time", "date"]),
+            name,
+            // Named rows are more likely places than the points of a recording.
+            rows: match name {
+                Some(_) => CsvRows::Waypoints,
+                None => CsvRows::Track,
+            },
+        })
+    }
+}
+
+/// Whether `line` is a header naming latitude and longitude columns.
+pub fn is_csv_header(line: &str) -> bool {
+    CsvTable::parse(line)
+        .map(|table| ColumnMapping::guess(&table.headers).is_some())
+        .unwrap_or(false)
+}
+
+impl CsvTable {
+    /// Split `text` into rows, with fields separated by whichever of comma, semicolon or tab
+    /// is most common in the first line. Fields may be quoted with `"`.
+    pub fn parse(text: &str) -> Result<CsvTable, CsvError> {
+        Self::parse_with_progress(text, &mut |_| {})
+    }
+
+    /// [`CsvTable::parse`], passing the fraction of `text` read so far to `on_progress`.
+    pub fn parse_with_progress(
+        text: &str,
+        on_progress: &mut dyn FnMut(f64),
+    ) -> Result<CsvTable, CsvError> {
+        let text = text.trim_start_matches('\u{feff}');
+        let mut progress = Progress::new(text.len(), on_progress);
+        let first_line = text.lines().next().unwrap_or_default();
+        let delimiter = [',', ';', '\t']
+            .into_iter()
+            .max_by_key(|&delimiter| first_line.matches(delimiter).count())
+            .unwrap_or(',');
+
+        let mut records: Vec<(usize, Vec<String>)> = Vec::new();
+        let mut record: Vec<String> = Vec::new();
+        let mut field = String::new();
+        let mut line = 1;
+        let mut start = 1;
+        let mut quoted = false;
+        let mut chars = text.char_indices().peekable();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' if quoted => {
+                    if chars.peek().map(|&(_, c)| c) == Some('"') {
+                        chars.next();
+                        field.push('"');
+                    } else {
+                        quoted = false;
+                    }
+                }
+                '"' if field.trim().is_empty() => {
+                    field.clear();
+                    quoted = true;
+                }
+                '\n' if quoted => {
+                    line += 1;
+                    field.push(c);
+                }
+                '\r' if !quoted => {}
+                '\n' => {
+                    progress.update(i);
+                    record.push(std::mem::take(&mut field));
+                    records.push((start, std::mem::take(&mut record)));
+                    line += 1;
+                    start = line;
+                }
+                c if c == delimiter && !quoted => record.push(std::mem::take(&mut field)),
+                c => field.push(c),
+            }
+        }
+        progress.update(text.len());
+        if quoted {
+            return Err(CsvError::Syntax {
+                line: start,
+                message: "quoted field is never closed".to_string(),
+            });
+        }
+        if !field.is_empty() || !record.is_empty() {
+            record.push(field);
+            records.push((start, record));
+        }
+        records.retain(|(_, record)| record.iter().any(|field| !field.trim().is_empty()));
+
+        let mut rows = records.into_iter();
+        let Some((first_start, first)) = rows.next() else {
+            return Ok(CsvTable::default());
+        };
+        // Text that starts with numbers has no header row.
+        if first
+            .iter()
+            .all(|field| field.trim().parse::<f64>().is_ok())
+        {
+            return Ok(CsvTable {
+                headers: (1..=first.len()).map(|i| format!("Column {}", i)).collect(),
+                rows: std::iter::once((first_start, first)).chain(rows).collect(),
+            });
+        }
+        Ok(CsvTable {
+            headers: first
+                .into_iter()
+                .map(|header| header.trim().to_string())
+                .collect(),
+            rows: rows.collect(),
+        })
+    }
+
+    /// Build a track, or waypoints, from the rows, reading the columns given by `mapping`.
+    pub fn to_document(&self, mapping: &ColumnMapping) -> Result<TrackDocument, CsvError> {
+        let mut points = Vec::new();
+        let mut waypoints = Vec::new();
+        for (line, row) in &self.rows {
+            let invalid = |message: String| CsvError::Invalid {
+                line: *line,
+                message,
+            };
+            let value = |column: usize| row.get(column).map(|value| value.trim());
+            let number = |column: usize, what: &str| match value(column) {
+                None | Some("") => Err(invalid(format!("no {}", what))),
+                Some(value) => value
+                    .parse::<f64>()
+                    .map_err(|_| invalid(format!("{} \"{}\" is not a number", what, value))),
+            };
+            let optional =
+                |column: Option<usize>| column.and_then(value).filter(|value| !value.is_empty());
+
+            let lat = number(mapping.latitude, "latitude")?;
+            let lon = number(mapping.longitude, "longitude")?;
+            if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+                return Err(invalid(format!("{}, {} is not a position", lat, lon)));
+            }
+            let elevation = match mapping.elevation {
+                Some(column) if optional(Some(column)).is_some() => {
+                    Some(number(column, "elevation")?)
+                }
+                _ => None,
+            };
+            let time = optional(mapping.time)
+                .map(|value| {
+                    parse_time(value)
+                        .ok_or_else(|| invalid(format!("time \"{}\" is not recognised", value)))
+                })
+                .transpose()?;
+
+            let coord = Coord::new(lat, lon);
+            match mapping.rows {
+                CsvRows::Track => points.push(TrackPoint {
+                    coord,
+                    elevation,
+                    time,
+                    ..TrackPoint::default()
+                }),
+                CsvRows::Waypoints => waypoints.push(Waypoint {
+                    coord,
+                    elevation,
+                    time,
+                    name: optional(mapping.name).map(str::to_string),
+                    ..Waypoint::default()
+                }),
+            }
+        }
+
+        let mut document = TrackDocument {
+            waypoints,
+            ..TrackDocument::default()
+        };
+        if !points.is_empty() {
+            document.tracks.push(Track {
+                segments: vec![Segment { points }],
+                ..Track::default()
+            });
+        }
+        Ok(document)
+    }
+}
+
+/// A time written as RFC 3339, as a date and time without an offset (taken as UTC), or as
+/// seconds or milliseconds since the Unix epoch.
+fn parse_time(value: &str) -> Option<OffsetDateTime> {
+    if let Ok(time) = OffsetDateTime::parse(value, &Rfc3339) {
+        return Some(time);
+    }
+    let local = format_description!(
+        "[year]-[month]-[day][first [T][ ]][hour]:[minute]:[second][optional [.[subsecond]]]"
+    );
+    if let Ok(time) = PrimitiveDateTime::parse(value, local) {
+        return Some(time.assume_utc());
+    }
+    let seconds: f64 = value.parse().ok()?;
+    // Anything past the year 5138 in seconds is more likely milliseconds.
+    let seconds = if seconds > 1e11 {
+        seconds / 1000.0
+    } else {
+        seconds
+    };
+    OffsetDateTime::from_unix_timestamp_nanos((seconds * 1e9) as i128).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_parse_csv() {
+        let text = "\u{feff}Name;Latitude;Longitude\r\n\
+                    \"Caf\u{e9}; \"\"The Hub\"\"\";52.2;0.12\r\n\
+                    \r\n\
+                    \"Two\nlines\";52.3;0.13\r\n";
+        let table = CsvTable::parse(text).unwrap();
+        assert_eq!(table.headers, vec!["Name", "Latitude", "Longitude"]);
+        assert_eq!(table.rows.len(), 2);
+        assert_eq!(table.rows[0].1[0], "Caf\u{e9}; \"The Hub\"");
+        assert_eq!(
+            table.rows[1],
+            (
+                4,
+                vec![
+                    "Two\nlines".to_string(),
+                    "52.3".to_string(),
+                    "0.13".to_string()
+                ]
+            )
+        );
+
+        let numbers = CsvTable::parse("52.2\t0.12\n52.3\t0.13").unwrap();
+        assert_eq!(numbers.headers, vec!["Column 1", "Column 2"]);
+        assert_eq!(numbers.rows.len(), 2);
+
+        assert_eq!(
+            CsvTable::parse("lat,lon\n\"52.2,0.1\n"),
+            Err(CsvError::Syntax {
+                line: 2,
+                message: "quoted field is never closed".to_string()
+            })
+        );
+    }
+
+    #[test]
+    fn test_guess_columns() {
+        let headers = |names: &[&str]| {
+            names
+                .iter()
+                .map(|name| name.to_string())
+                .collect::<Vec<_>>()
+        };
+        let mapping =
+            ColumnMapping::guess(&headers(&["Time (UTC)", "Lat", "Lng", "Altitude (m)"])).unwrap();
+        assert_eq!(
+            mapping,
+            ColumnMapping {
+                latitude: 1,
+                longitude: 2,
+                elevation: Some(3),
+                time: Some(0),
+                name: None,
+                rows: CsvRows::Track,
+            }
+        );
+        let places = ColumnMapping::guess(&headers(&["name", "latitude", "longitude"])).unwrap();
+        assert_eq!(places.rows, CsvRows::Waypoints);
+        assert_eq!(ColumnMapping::guess(&headers(&["x", "y"])), None);
+        assert!(is_csv_header("time,lat,lon"));
+        assert!(!is_csv_header("<gpx>"));
+    }
+
+    #[test]
+    fn test_csv_to_document() {
+        let text = "time,lat,lon,ele\n\
+                    2024-05-01T09:30:00Z,52.2,0.12,15\n\
+                    2024-05-01 09:30:01.5,52.3,0.13,\n\
+                    1714555802,52.4,0.14,16.5\n";
+        let table = CsvTable::parse(text).unwrap();
+        let mapping = ColumnMapping::guess(&table.headers).unwrap();
+        let document = table.to_document(&mapping).unwrap();
+        let points = &document.tracks[0].segments[0].points;
+        assert_eq!(points.len(), 3);
+        assert_eq!(points[0].coord, Coord::new(52.2, 0.12));
+        assert_eq!(points[0].time, Some(datetime!(2024-05-01 09:30 UTC)));
+        assert_eq!(points[1].elevation, None);
+        assert_eq!(points[1].time, Some(datetime!(2024-05-01 09:30:01.5 UTC)));
+        assert_eq!(points[2].time, Some(datetime!(2024-05-01 09:30:02 UTC)));
+
+        let waypoints = table
+            .to_document(&ColumnMapping {
+                name: Some(0),
+                rows: CsvRows::Waypoints,
+                time: None,
+                ..mapping
+            })
+            .unwrap();
+        assert!(waypoints.tracks.is_empty());
+        assert_eq!(waypoints.waypoints[2].name.as_deref(), Some("1714555802"));
+
+        let bad = CsvTable::parse("lat,lon\n52.2,0.1\n52.3,east\n").unwrap();
+        assert_eq!(
+            bad.to_document(&ColumnMapping::guess(&bad.headers).unwrap()),
+            Err(CsvError::Invalid {
+                line: 3,
+                message: "longitude \"east\" is not a number".to_string()
+            })
+        );
+    }
+}
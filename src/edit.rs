@@ -0,0 +1,272 @@
+//! Editing a document's tracks: splitting, trimming, joining, reversing and deleting points.
+//! Each edit returns a new document, so the one it was applied to can be kept for undo.
+
+use std::fmt;
+
+use crate::track::{Segment, Track, TrackDocument, TrackPointId};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum TrackEdit {
+    /// Split the track in two at a point, which ends the first track and starts the second.
+    Split(TrackPointId),
+    /// Remove every point of the track before a point.
+    TrimStart(TrackPointId),
+    /// Remove every point of the track after a point.
+    TrimEnd(TrackPointId),
+    /// Append the next track to a track, continuing its last segment.
+    Join(usize),
+    /// Reverse the direction of a track.
+    Reverse(usize),
+    /// Remove the points of one track from the first point to the second, inclusive.
+    DeleteRange(TrackPointId, TrackPointId),
+}
+
+#[derive(Debug, PartialEq)]
+pub enum EditError {
+    /// The document has no such track or point, usually because it was edited since.
+    NoSuchPoint,
+    /// Splitting at the first or last point would leave a track of one point.
+    SplitAtEnd,
+    /// The track to join is the last one.
+    NoNextTrack,
+    /// The ends of a range to delete are on different tracks.
+    DifferentTracks,
+}
+
+impl fmt::Display for EditError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            EditError::NoSuchPoint => write!(f, "the point is no longer in the track"),
+            EditError::SplitAtEnd => write!(f, "cannot split a track at its first or last point"),
+            EditError::NoNextTrack => write!(f, "there is no next track to join"),
+            EditError::DifferentTracks => write!(f, "the range must be within one track"),
+        }
+    }
+}
+
+impl std::error::Error for EditError {}
+
+impl TrackEdit {
+    /// A copy of `document` with this edit made.
+    pub fn apply(&self, document: &TrackDocument) -> Result<TrackDocument, EditError> {
+        let mut document = document.clone();
+        match *self {
+            TrackEdit::Split(id) => {
+                let track = point_track(&document, id)?;
+                if is_first(id) || is_last(track, id) {
+                    return Err(EditError::SplitAtEnd);
+                }
+                let mut first = track.clone();
+                let mut second = Track {
+                    segments: first.segments.split_off(id.segment),
+                    ..track.clone()
+                };
+                first.segments.push(Segment {
+                    points: second.segments[0].points[..=id.point].to_vec(),
+                });
+                second.segments[0].points.drain(..id.point);
+                document.tracks[id.track] = first;
+                document.tracks.insert(id.track + 1, second);
+            }
+            TrackEdit::TrimStart(id) => {
+                point_track(&document, id)?;
+                let track = &mut document.tracks[id.track];
+                track.segments.drain(..id.segment);
+                track.segments[0].points.drain(..id.point);
+            }
+            TrackEdit::TrimEnd(id) => {
+                point_track(&document, id)?;
+                let track = &mut document.tracks[id.track];
+                track.segments.truncate(id.segment + 1);
+                track.segments[id.segment].points.truncate(id.point + 1);
+            }
+            TrackEdit::Join(index) => {
+                if index >= document.tracks.len() {
+                    return Err(EditError::NoSuchPoint);
+                }
+                if index + 1 >= document.tracks.len() {
+                    return Err(EditError::NoNextTrack);
+                }
+                let next = document.tracks.remove(index + 1);
+                let track = &mut document.tracks[index];
+                let mut next_segments = next.segments.into_iter();
+                match (track.segments.last_mut(), next_segments.next()) {
+                    (Some(last), Some(mut first)) => {
+                        // Joining the halves of a split shouldn't repeat the point it was split at.
+                        if last.points.last() == first.points.first() {
+                            first.points.remove(0);
+                        }
+                        last.points.append(&mut first.points);
+                    }
+                    (None, first) => track.segments.extend(first),
+                    (Some(_), None) => {}
+                }
+                track.segments.extend(next_segments);
+            }
+            TrackEdit::Reverse(index) => {
+                let track = document
+                    .tracks
+                    .get_mut(index)
+                    .ok_or(EditError::NoSuchPoint)?;
+                track.segments.reverse();
+                track
+                    .segments
+                    .iter_mut()
+                    .for_each(|segment| segment.points.reverse());
+            }
+            TrackEdit::DeleteRange(a, b) => {
+                point_track(&document, a)?;
+                point_track(&document, b)?;
+                if a.track != b.track {
+                    return Err(EditError::DifferentTracks);
+                }
+                let (from, to) = match (a.segment, a.point) <= (b.segment, b.point) {
+                    true => (a, b),
+                    false => (b, a),
+                };
+                let track = &mut document.tracks[a.track];
+                for (s, segment) in track.segments.iter_mut().enumerate() {
+                    if s < from.segment || s > to.segment {
+                        continue;
+                    }
+                    let start = if s == from.segment { from.point } else { 0 };
+                    let end = if s == to.segment {
+                        to.point + 1
+                    } else {
+                        segment.points.len()
+                    };
+                    segment.points.drain(start..end);
+                }
+                track.segments.retain(|segment| !segment.points.is_empty());
+                if track.segments.is_empty() {
+                    document.tracks.remove(a.track);
+                }
+            }
+        }
+        Ok(document)
+    }
+}
+
+/// The track holding point `id`, if there is such a point.
+fn point_track(document: &TrackDocument, id: TrackPointId) -> Result<&Track, EditError> {
+    document
+        .point(id)
+        .map(|_| &document.tracks[id.track])
+        .ok_or(EditError::NoSuchPoint)
+}
+
+fn is_first(id: TrackPointId) -> bool {
+    id.segment == 0 && id.point == 0
+}
+
+fn is_last(track: &Track, id: TrackPointId) -> bool {
+    id.segment + 1 == track.segments.len()
+        && id.point + 1 == track.segments[id.segment].points.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::geo::Coord;
+    use crate::track::TrackPoint;
+
+    /// A track of two segments, with points at latitudes 0 to 2 and then 3 to 4.
+    fn document() -> TrackDocument {
+        let segment = |lats: &[f64]| Segment {
+            points: lats
+                .iter()
+                .map(|&lat| TrackPoint {
+                    coord: Coord::new(lat, 0.0),
+                    ..TrackPoint::default()
+                })
+                .collect(),
+        };
+        TrackDocument {
+            tracks: vec![Track {
+                name: Some("Ride".to_string()),
+                segments: vec![segment(&[0.0, 1.0, 2.0]), segment(&[3.0, 4.0])],
+                ..Track::default()
+            }],
+            ..TrackDocument::default()
+        }
+    }
+
+    /// Latitudes of each segment of each track.
+    fn lats(document: &TrackDocument) -> Vec<Vec<Vec<f64>>> {
+        let segment = |segment: &Segment| segment.points.iter().map(|p| p.coord.lat).collect();
+        let track = |track: &Track| track.segments.iter().map(segment).collect();
+        document.tracks.iter().map(track).collect()
+    }
+
+    fn id(track: usize, segment: usize, point: usize) -> TrackPointId {
+        TrackPointId {
+            track,
+            segment,
+            point,
+        }
+    }
+
+    #[test]
+    fn test_split_and_join() {
+        let split = TrackEdit::Split(id(0, 0, 1)).apply(&document()).unwrap();
+        assert_eq!(
+            lats(&split),
+            vec![vec![vec![0.0, 1.0]], vec![vec![1.0, 2.0], vec![3.0, 4.0]]]
+        );
+        assert_eq!(split.tracks[1].name.as_deref(), Some("Ride"));
+        assert_eq!(TrackEdit::Join(0).apply(&split).unwrap(), document());
+
+        assert_eq!(
+            TrackEdit::Split(id(0, 1, 1)).apply(&document()),
+            Err(EditError::SplitAtEnd)
+        );
+        assert_eq!(
+            TrackEdit::Join(0).apply(&document()),
+            Err(EditError::NoNextTrack)
+        );
+    }
+
+    #[test]
+    fn test_trim_and_reverse() {
+        let trimmed = TrackEdit::TrimStart(id(0, 0, 2))
+            .apply(&document())
+            .unwrap();
+        assert_eq!(lats(&trimmed), vec![vec![vec![2.0], vec![3.0, 4.0]]]);
+        let trimmed = TrackEdit::TrimEnd(id(0, 0, 1)).apply(&document()).unwrap();
+        assert_eq!(lats(&trimmed), vec![vec![vec![0.0, 1.0]]]);
+
+        let reversed = TrackEdit::Reverse(0).apply(&document()).unwrap();
+        assert_eq!(
+            lats(&reversed),
+            vec![vec![vec![4.0, 3.0], vec![2.0, 1.0, 0.0]]]
+        );
+        assert_eq!(
+            TrackEdit::Reverse(1).apply(&document()),
+            Err(EditError::NoSuchPoint)
+        );
+    }
+
+    #[test]
+    fn test_delete_range() {
+        // The ends can be given either way round, and may span segments.
+        let deleted = TrackEdit::DeleteRange(id(0, 1, 0), id(0, 0, 1))
+            .apply(&document())
+            .unwrap();
+        assert_eq!(lats(&deleted), vec![vec![vec![0.0], vec![4.0]]]);
+
+        // Deleting a whole segment drops it, and deleting every point drops the track.
+        let deleted = TrackEdit::DeleteRange(id(0, 1, 0), id(0, 1, 1))
+            .apply(&document())
+            .unwrap();
+        assert_eq!(lats(&deleted), vec![vec![vec![0.0, 1.0, 2.0]]]);
+        let deleted = TrackEdit::DeleteRange(id(0, 0, 0), id(0, 1, 1))
+            .apply(&document())
+            .unwrap();
+        assert!(deleted.tracks.is_empty());
+
+        assert_eq!(
+            TrackEdit::DeleteRange(id(0, 0, 0), id(0, 0, 5)).apply(&document()),
+            Err(EditError::NoSuchPoint)
+        );
+    }
+}
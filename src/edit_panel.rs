@@ -0,0 +1,95 @@
+use yew::prelude::*;
+
+use crate::edit::TrackEdit;
+use crate::geo::Coord;
+use crate::layers::LoadedFile;
+use crate::track::{TrackDocument, TrackPointId};
+
+/// A track point clicked on the map, and the first end of a range to delete if one was marked.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Selection {
+    /// Id of the file the point is in.
+    pub file: usize,
+    pub point: TrackPointId,
+    pub range_start: Option<TrackPointId>,
+}
+
+impl Selection {
+    /// Where the selected point and range start are in `document`, to highlight on the map.
+    pub fn coords(&self, document: &TrackDocument) -> Vec<Coord> {
+        let ids = std::iter::once(self.point).chain(self.range_start);
+        ids.filter_map(|id| document.point(id))
+            .map(|point| point.coord)
+            .collect()
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct EditPanelProps {
+    pub file: LoadedFile,
+    pub selection: Selection,
+    /// Called with the edited tracks.
+    pub on_edit: Callback<TrackDocument>,
+    /// Called to change the selection, or with `None` to close the panel.
+    pub on_select: Callback<Option<Selection>>,
+}
+
+/// Buttons for editing the track at the selected point.
+#[function_component(EditPanel)]
+pub fn edit_panel(props: &EditPanelProps) -> Html {
+    let error = use_state(|| None::<String>);
+    {
+        let error = error.clone();
+        use_effect_with(props.selection, move |_| {
+            error.set(None);
+            || {}
+        });
+    }
+    let selection = props.selection;
+    let id = selection.point;
+    let edit = |edit: TrackEdit| {
+        let (file, on_edit, error) = (props.file.clone(), props.on_edit.clone(), error.clone());
+        Callback::from(move |_: MouseEvent| match edit.apply(&file.tracks) {
+            Ok(tracks) => on_edit.emit(tracks),
+            Err(e) => error.set(Some(e.to_string())),
+        })
+    };
+    let select = |range_start: Option<TrackPointId>| {
+        props.on_select.reform(move |_: MouseEvent| {
+            Some(Selection {
+                range_start,
+                ..selection
+            })
+        })
+    };
+    let on_close = props.on_select.reform(|_: MouseEvent| None);
+
+    html! {
+        <div class="edit-panel">
+            <h3>{ format!(
+                "{}: track {}, segment {}, point {}",
+                props.file.name,
+                id.track + 1,
+                id.segment + 1,
+                id.point + 1,
+            ) }</h3>
+            <button onclick={edit(TrackEdit::Split(id))}>{ "Split here" }</button>
+            <button onclick={edit(TrackEdit::TrimStart(id))}>{ "Trim start" }</button>
+            <button onclick={edit(TrackEdit::TrimEnd(id))}>{ "Trim end" }</button>
+            <button onclick={edit(TrackEdit::Reverse(id.track))}>{ "Reverse track" }</button>
+            <button onclick={edit(TrackEdit::Join(id.track))}>{ "Join next track" }</button>
+            if let Some(start) = selection.range_start {
+                <button onclick={edit(TrackEdit::DeleteRange(start, id))}>
+                    { "Delete from marked point to here" }
+                </button>
+                <button onclick={select(None)}>{ "Unmark" }</button>
+            } else {
+                <button onclick={select(Some(id))}>{ "Mark start of range" }</button>
+            }
+            <button onclick={on_close}>{ "Close" }</button>
+            if let Some(error) = &*error {
+                <p class="edit-error">{ error }</p>
+            }
+        </div>
+    }
+}
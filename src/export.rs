@@ -0,0 +1,302 @@
+//! Writing the track model back out to files, and handing them to the browser as downloads.
+
+use gloo_timers::callback::Timeout;
+use gpx::{Gpx, GpxVersion, Metadata};
+use log::error;
+use time::OffsetDateTime;
+use web_sys::{
+    js_sys::{Array, Uint8Array},
+    wasm_bindgen::{JsCast, JsValue},
+    Blob, BlobPropertyBag, HtmlAnchorElement, Url,
+};
+
+use crate::extensions::insert_point_extensions;
+use crate::geo::{limit_track_points, visvalingam_to_count};
+use crate::track::{PointExtensions, Route, Segment, Track, TrackDocument};
+
+/// Written as the `creator` of exported files.
+const CREATOR: &str = "wasmyroute";
+
+/// How long a download's object URL is kept, in milliseconds. Browsers fetch it after the
+/// click has returned, so it can't be revoked straight away.
+const DOWNLOAD_URL_LIFETIME: u32 = 40_000;
+
+/// Whether lines are written as recorded tracks (`<trk>`) or planned routes (`<rte>`).
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum GpxOutput {
+    /// Tracks stay tracks and routes stay routes.
+    AsLoaded,
+    /// Routes become single-segment tracks.
+    Tracks,
+    /// Each track becomes one route, its segments joined end to end.
+    Routes,
+}
+
+impl GpxOutput {
+    pub const ALL: [GpxOutput; 3] = [GpxOutput::AsLoaded, GpxOutput::Tracks, GpxOutput::Routes];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            GpxOutput::AsLoaded => "As loaded",
+            GpxOutput::Tracks => "Tracks",
+            GpxOutput::Routes => "Routes",
+        }
+    }
+}
+
+/// Point budgets offered for older GPS units, which cap the points of a course.
+pub const POINT_LIMITS: [usize; 4] = [500, 1_000, 2_000, 10_000];
+
+/// Copy of `document` with at most `max_points` points in each track and each route, keeping
+/// the ones that matter most to the shape. Waypoints are left alone.
+pub fn limit_points(document: &TrackDocument, max_points: usize) -> TrackDocument {
+    let tracks = document
+        .tracks
+        .iter()
+        .map(|track| limit_track_points(track, max_points))
+        .collect();
+    let routes = document
+        .routes
+        .iter()
+        .map(|route| {
+            let coords: Vec<_> = route.points.iter().map(|point| point.coord).collect();
+            Route {
+                points: visvalingam_to_count(&coords, max_points)
+                    .into_iter()
+                    .map(|i| route.points[i].clone())
+                    .collect(),
+                ..route.clone()
+            }
+        })
+        .collect();
+    TrackDocument {
+        tracks,
+        routes,
+        ..document.clone()
+    }
+}
+
+/// The tracks and routes of `document`, converted as `output` says.
+fn lines(document: &TrackDocument, output: GpxOutput) -> (Vec<Track>, Vec<Route>) {
+    match output {
+        GpxOutput::AsLoaded => (document.tracks.clone(), document.routes.clone()),
+        GpxOutput::Tracks => {
+            let route_tracks = document.routes.iter().map(|route| Track {
+                name: route.name.clone(),
+                description: route.description.clone(),
+                kind: None,
+                segments: vec![Segment {
+                    points: route.points.clone(),
+                }],
+            });
+            (
+                document
+                    .tracks
+                    .iter()
+                    .cloned()
+                    .chain(route_tracks)
+                    .collect(),
+                Vec::new(),
+            )
+        }
+        GpxOutput::Routes => {
+            let track_routes = document.tracks.iter().map(|track| Route {
+                name: track.name.clone(),
+                description: track.description.clone(),
+                points: track.points().cloned().collect(),
+            });
+            (
+                Vec::new(),
+                track_routes
+                    .chain(document.routes.iter().cloned())
+                    .collect(),
+            )
+        }
+    }
+}
+
+/// Convert `document` to GPX 1.1, stamping the metadata with `time`. The gpx crate has no
+/// room for sensor readings; [`write_gpx`] adds them.
+pub fn to_gpx(document: &TrackDocument, output: GpxOutput, time: OffsetDateTime) -> Gpx {
+    let (tracks, routes) = lines(document, output);
+    Gpx {
+        version: GpxVersion::Gpx11,
+        creator: Some(CREATOR.to_string()),
+        metadata: Some(Metadata {
+            name: document.name.clone(),
+            time: Some(time.into()),
+            bounds: document.bbox().map(|bbox| {
+                geo_types::Rect::new(
+                    geo_types::coord! { x: bbox.west, y: bbox.south },
+                    geo_types::coord! { x: bbox.east, y: bbox.north },
+                )
+            }),
+            ..Metadata::default()
+        }),
+        waypoints: document.waypoints.iter().map(gpx::Waypoint::from).collect(),
+        tracks: tracks
+            .iter()
+            .map(|track| gpx::Track {
+                name: track.name.clone(),
+                description: track.description.clone(),
+                type_: track.kind.clone(),
+                segments: track
+                    .segments
+                    .iter()
+                    .map(|segment| gpx::TrackSegment {
+                        points: segment.points.iter().map(gpx::Waypoint::from).collect(),
+                    })
+                    .collect(),
+                ..gpx::Track::new()
+            })
+            .collect(),
+        routes: routes
+            .iter()
+            .map(|route| gpx::Route {
+                name: route.name.clone(),
+                description: route.description.clone(),
+                points: route.points.iter().map(gpx::Waypoint::from).collect(),
+                ..gpx::Route::new()
+            })
+            .collect(),
+    }
+}
+
+/// Serialise `document` as a GPX 1.1 file, with any sensor readings as point extensions.
+pub fn write_gpx(
+    document: &TrackDocument,
+    output: GpxOutput,
+    time: OffsetDateTime,
+) -> Result<Vec<u8>, gpx::errors::GpxError> {
+    let mut bytes = Vec::new();
+    gpx::write(&to_gpx(document, output, time), &mut bytes)?;
+    let (tracks, routes) = lines(document, output);
+    let track_points: Vec<_> = tracks
+        .iter()
+        .flat_map(|track| track.points().map(|point| point.extensions))
+        .collect();
+    let route_points: Vec<_> = routes
+        .iter()
+        .flat_map(|route| route.points.iter().map(|point| point.extensions))
+        .collect();
+    if track_points
+        .iter()
+        .chain(&route_points)
+        .all(PointExtensions::is_empty)
+    {
+        return Ok(bytes);
+    }
+    Ok(insert_point_extensions(&bytes, track_points, route_points)?)
+}
+
+/// `name` with its extension replaced by `extension`, for naming an exported copy of a file.
+pub fn export_file_name(name: &str, extension: &str) -> String {
+    let stem = match name.rsplit_once('.') {
+        Some((stem, _)) if !stem.is_empty() => stem,
+        _ => name,
+    };
+    format!("{}.{}", stem, extension)
+}
+
+/// Offer `bytes` to the user as a file download.
+pub fn download(file_name: &str, mime_type: &str, bytes: &[u8]) -> Result<(), JsValue> {
+    let parts = Array::of1(&Uint8Array::from(bytes));
+    let mut options = BlobPropertyBag::new();
+    options.type_(mime_type);
+    let blob = Blob::new_with_u8_array_sequence_and_options(&parts, &options)?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+
+    let anchor: HtmlAnchorElement = gloo_utils::document()
+        .create_element("a")?
+        .dyn_into()
+        .map_err(JsValue::from)?;
+    anchor.set_href(&url);
+    anchor.set_download(file_name);
+    anchor.click();
+
+    let file_name = file_name.to_string();
+    Timeout::new(DOWNLOAD_URL_LIFETIME, move || {
+        if let Err(e) = Url::revoke_object_url(&url) {
+            error!("Could not release the download of {}: {:?}", file_name, e);
+        }
+    })
+    .forget();
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::tests::sample_document;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_limit_points() {
+        let document = sample_document();
+        let limited = limit_points(&document, 500);
+        assert_eq!(limited.tracks[0].points().count(), 500);
+        assert_eq!(
+            limited.tracks[0].points().next(),
+            document.tracks[0].points().next()
+        );
+        assert_eq!(limited.waypoints, document.waypoints);
+
+        // Routes are capped too, as every line of the file counts against the unit's limit.
+        let as_route = TrackDocument {
+            routes: lines(&document, GpxOutput::Routes).1,
+            ..TrackDocument::default()
+        };
+        assert_eq!(limit_points(&as_route, 500).routes[0].points.len(), 500);
+
+        // Lines already within the budget are unchanged.
+        assert_eq!(limit_points(&document, usize::MAX), document);
+    }
+
+    #[test]
+    fn test_gpx_round_trip() {
+        let time = datetime!(2024-05-01 09:30 UTC);
+        for entry in std::fs::read_dir("src/data").unwrap() {
+            let path = entry.unwrap().path();
+            if path.extension().and_then(|e| e.to_str()) != Some("gpx") {
+                continue;
+            }
+            let original = gpx::read(std::fs::read(&path).unwrap().as_slice()).unwrap();
+            let document = TrackDocument::from(&original);
+            let bytes = write_gpx(&document, GpxOutput::AsLoaded, time).unwrap();
+            let written = gpx::read(bytes.as_slice()).unwrap();
+
+            assert_eq!(written.version, GpxVersion::Gpx11, "{:?}", path);
+            assert_eq!(written.creator.as_deref(), Some(CREATOR));
+            let metadata = written.metadata.as_ref().unwrap();
+            assert_eq!(metadata.time, Some(time.into()));
+            assert!(metadata.bounds.is_some());
+            assert_eq!(TrackDocument::from(&written), document, "{:?}", path);
+        }
+    }
+
+    #[test]
+    fn test_gpx_output() {
+        let mut document = crate::track::tests::sample_document();
+        let points = document.tracks[0].points().count();
+        let time = datetime!(2024-05-01 09:30 UTC);
+
+        let routes = to_gpx(&document, GpxOutput::Routes, time);
+        assert!(routes.tracks.is_empty());
+        assert_eq!(routes.routes.len(), 1);
+        assert_eq!(routes.routes[0].points.len(), points);
+
+        document.routes = TrackDocument::from(&routes).routes;
+        document.tracks.clear();
+        let tracks = to_gpx(&document, GpxOutput::Tracks, time);
+        assert!(tracks.routes.is_empty());
+        assert_eq!(tracks.tracks[0].segments[0].points.len(), points);
+    }
+
+    #[test]
+    fn test_export_file_name() {
+        assert_eq!(export_file_name("ride.gpx", "gpx"), "ride.gpx");
+        assert_eq!(export_file_name("ride.fit", "gpx"), "ride.gpx");
+        assert_eq!(export_file_name("Pasted text", "gpx"), "Pasted text.gpx");
+        assert_eq!(export_file_name(".hidden", "kml"), ".hidden.kml");
+    }
+}
@@ -0,0 +1,260 @@
+//! Sensor data in GPX `<extensions>`, which the gpx crate skips. Readings are taken from
+//! Garmin's TrackPointExtension (`gpxtpx:hr`, `cad`, `atemp`), the similar Cluetrust
+//! `gpxdata` elements, and the bare `<power>` element Strava writes, then written back out as
+//! TrackPointExtension v2 plus `<power>`.
+
+use std::io::Read;
+
+use xml::reader::{EventReader, XmlEvent};
+use xml::writer::{EmitterConfig, XmlEvent as WriteEvent};
+
+use crate::track::{PointExtensions, TrackDocument};
+use crate::xml_writer::{write_event, write_text_element, XmlWriter};
+
+/// Namespace of Garmin's TrackPointExtension, version 2.
+const GPXTPX_NS: &str = "http://www.garmin.com/xmlschemas/TrackPointExtension/v2";
+
+/// Readings of every `<trkpt>` and every `<rtept>` in a file, in file order.
+#[derive(Debug, Default, PartialEq)]
+struct FileExtensions {
+    track_points: Vec<PointExtensions>,
+    route_points: Vec<PointExtensions>,
+}
+
+fn read_extensions(input: impl Read) -> Result<FileExtensions, xml::reader::Error> {
+    let mut extensions = FileExtensions::default();
+    let mut path: Vec<String> = Vec::new();
+    let mut point: Option<PointExtensions> = None;
+    let mut text_buffer = String::new();
+    for event in EventReader::new(input) {
+        match event? {
+            XmlEvent::StartElement { name, .. } => {
+                if matches!(name.local_name.as_str(), "trkpt" | "rtept") {
+                    point = Some(PointExtensions::default());
+                }
+                path.push(name.local_name);
+                text_buffer.clear();
+            }
+            XmlEvent::Characters(text) => text_buffer.push_str(&text),
+            XmlEvent::EndElement { name } => {
+                path.pop();
+                let in_extensions = path.iter().any(|element| element == "extensions");
+                match name.local_name.as_str() {
+                    "trkpt" => extensions.track_points.extend(point.take()),
+                    "rtept" => extensions.route_points.extend(point.take()),
+                    element if in_extensions => {
+                        if let Some(point) = point.as_mut() {
+                            read_reading(point, element, text_buffer.trim());
+                        }
+                    }
+                    _ => {}
+                }
+                text_buffer.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok(extensions)
+}
+
+/// Store the value of extension element `element`, if it is a reading we keep.
+fn read_reading(point: &mut PointExtensions, element: &str, value: &str) {
+    let Ok(number) = value.parse::<f64>() else {
+        return;
+    };
+    // Some devices write whole-number readings with a fraction, such as `121.0`.
+    let whole = || {
+        (0.0..=u16::MAX as f64)
+            .contains(&number)
+            .then(|| number.round() as u16)
+    };
+    match element {
+        "hr" | "heartrate" => point.heart_rate = whole(),
+        "cad" | "cadence" => point.cadence = whole(),
+        "power" | "PowerInWatts" => point.power = whole(),
+        "atemp" | "temp" => point.temperature = Some(number),
+        _ => {}
+    }
+}
+
+/// Copy the sensor readings in the GPX read from `input` into `document`, which was read
+/// from the same file. Nothing is copied if the points do not line up, as the readings could
+/// end up on the wrong ones.
+pub fn add_point_extensions(document: &mut TrackDocument, input: impl Read) {
+    let Ok(extensions) = read_extensions(input) else {
+        return;
+    };
+    let track_points = document
+        .tracks
+        .iter_mut()
+        .flat_map(|track| track.segments.iter_mut())
+        .flat_map(|segment| segment.points.iter_mut());
+    let route_points = document
+        .routes
+        .iter_mut()
+        .flat_map(|route| route.points.iter_mut());
+    let mut points: Vec<_> = track_points.collect();
+    if points.len() == extensions.track_points.len() {
+        points
+            .iter_mut()
+            .zip(extensions.track_points)
+            .for_each(|(point, extensions)| point.extensions = extensions);
+    }
+    let mut points: Vec<_> = route_points.collect();
+    if points.len() == extensions.route_points.len() {
+        points
+            .iter_mut()
+            .zip(extensions.route_points)
+            .for_each(|(point, extensions)| point.extensions = extensions);
+    }
+}
+
+fn write_point_extensions(writer: &mut XmlWriter, point: &PointExtensions) {
+    write_event(writer, WriteEvent::start_element("extensions"));
+    if let Some(power) = point.power {
+        write_text_element(writer, "power", &power.to_string());
+    }
+    if point.heart_rate.is_some() || point.cadence.is_some() || point.temperature.is_some() {
+        // The schema requires this order.
+        write_event(
+            writer,
+            WriteEvent::start_element("gpxtpx:TrackPointExtension"),
+        );
+        if let Some(temperature) = point.temperature {
+            write_text_element(writer, "gpxtpx:atemp", &temperature.to_string());
+        }
+        if let Some(heart_rate) = point.heart_rate {
+            write_text_element(writer, "gpxtpx:hr", &heart_rate.to_string());
+        }
+        if let Some(cadence) = point.cadence {
+            write_text_element(writer, "gpxtpx:cad", &cadence.to_string());
+        }
+        write_event(writer, WriteEvent::end_element());
+    }
+    write_event(writer, WriteEvent::end_element());
+}
+
+/// Add `<extensions>` to the points of `gpx`, a file written by the gpx crate, taking the
+/// readings for its `<trkpt>`s from `track_points` and its `<rtept>`s from `route_points`.
+pub fn insert_point_extensions(
+    gpx: &[u8],
+    track_points: impl IntoIterator<Item = PointExtensions>,
+    route_points: impl IntoIterator<Item = PointExtensions>,
+) -> Result<Vec<u8>, xml::reader::Error> {
+    let (mut track_points, mut route_points) = (track_points.into_iter(), route_points.into_iter());
+    let mut writer = EmitterConfig::new()
+        .perform_indent(true)
+        .create_writer(Vec::new());
+    for event in EventReader::new(gpx) {
+        match event? {
+            // The writer indents for itself.
+            XmlEvent::Whitespace(_) => {}
+            XmlEvent::StartElement {
+                name,
+                attributes,
+                mut namespace,
+            } if name.local_name == "gpx" => {
+                namespace.put("gpxtpx", GPXTPX_NS);
+                let event = XmlEvent::StartElement {
+                    name,
+                    attributes,
+                    namespace,
+                };
+                write_event(&mut writer, event.as_writer_event().unwrap());
+            }
+            XmlEvent::EndElement { name } => {
+                let point = match name.local_name.as_str() {
+                    "trkpt" => track_points.next(),
+                    "rtept" => route_points.next(),
+                    _ => None,
+                };
+                if let Some(point) = point.filter(|point| !point.is_empty()) {
+                    write_point_extensions(&mut writer, &point);
+                }
+                write_event(&mut writer, WriteEvent::end_element());
+            }
+            event => {
+                if let Some(event) = event.as_writer_event() {
+                    write_event(&mut writer, event);
+                }
+            }
+        }
+    }
+    Ok(writer.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::export::{write_gpx, GpxOutput};
+    use time::OffsetDateTime;
+
+    const GARMIN: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+        <gpx version="1.1" creator="Garmin Connect" xmlns="http://www.topografix.com/GPX/1/1"
+          xmlns:ns3="http://www.garmin.com/xmlschemas/TrackPointExtension/v1">
+          <rte>
+            <rtept lat="52.0" lon="0.0"/>
+          </rte>
+          <trk>
+            <trkseg>
+              <trkpt lat="52.1350720" lon="0.1298080">
+                <ele>23.6</ele>
+                <extensions>
+                  <power>212</power>
+                  <ns3:TrackPointExtension>
+                    <ns3:atemp>18.5</ns3:atemp>
+                    <ns3:hr>121.0</ns3:hr>
+                    <ns3:cad>88</ns3:cad>
+                  </ns3:TrackPointExtension>
+                </extensions>
+              </trkpt>
+              <trkpt lat="52.1351360" lon="0.1297770"><ele>23.8</ele></trkpt>
+            </trkseg>
+          </trk>
+        </gpx>"#;
+
+    #[test]
+    fn test_read_extensions() {
+        let extensions = read_extensions(GARMIN.as_bytes()).unwrap();
+        assert_eq!(extensions.route_points, vec![PointExtensions::default()]);
+        assert_eq!(
+            extensions.track_points,
+            vec![
+                PointExtensions {
+                    heart_rate: Some(121),
+                    cadence: Some(88),
+                    power: Some(212),
+                    temperature: Some(18.5),
+                },
+                PointExtensions::default(),
+            ]
+        );
+
+        let mut document = TrackDocument::from(&gpx::read(GARMIN.as_bytes()).unwrap());
+        add_point_extensions(&mut document, GARMIN.as_bytes());
+        let points = &document.tracks[0].segments[0].points;
+        assert_eq!(points[0].extensions.heart_rate, Some(121));
+        assert!(points[1].extensions.is_empty());
+    }
+
+    #[test]
+    fn test_insert_point_extensions() {
+        let mut document = TrackDocument::from(&gpx::read(GARMIN.as_bytes()).unwrap());
+        add_point_extensions(&mut document, GARMIN.as_bytes());
+        let written = write_gpx(&document, GpxOutput::AsLoaded, OffsetDateTime::UNIX_EPOCH);
+        let written = String::from_utf8(written.unwrap()).unwrap();
+
+        assert!(written.contains(GPXTPX_NS));
+        assert!(written.contains("<gpxtpx:hr>121</gpxtpx:hr>"));
+        let mut read = TrackDocument::from(&gpx::read(written.as_bytes()).unwrap());
+        add_point_extensions(&mut read, written.as_bytes());
+        assert_eq!(read, document);
+
+        // Files without readings are left as the gpx crate wrote them.
+        let plain = crate::track::tests::sample_document();
+        let written = write_gpx(&plain, GpxOutput::AsLoaded, OffsetDateTime::UNIX_EPOCH);
+        assert!(!String::from_utf8(written.unwrap())
+            .unwrap()
+            .contains("gpxtpx"));
+    }
+}
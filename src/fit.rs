@@ -0,0 +1,560 @@
+//! Garmin FIT reader, decoding the binary protocol directly so it runs in the browser.
+//! Activities become a track with one segment per lap, courses become a route, and
+//! course points become waypoints. Messages other than those are skipped.
+
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::geo::Coord;
+use crate::progress::Progress;
+use crate::track::{PointExtensions, Route, Segment, Track, TrackDocument, TrackPoint, Waypoint};
+
+/// Seconds from the Unix epoch to the FIT epoch, 1989-12-31T00:00:00Z.
+const FIT_EPOCH: i64 = 631_065_600;
+
+/// Global message numbers from the FIT profile.
+const FILE_ID: u16 = 0;
+const SESSION: u16 = 18;
+const LAP: u16 = 19;
+const RECORD: u16 = 20;
+const COURSE: u16 = 31;
+const COURSE_POINT: u16 = 32;
+
+/// Field number of `timestamp`, the same in every message.
+const TIMESTAMP: u8 = 253;
+
+/// `file_id.type` of a course file.
+const FILE_TYPE_COURSE: f64 = 6.0;
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum FitError {
+    /// The file does not start with a FIT header.
+    NotFit,
+    /// The file ends before the size given in its header, or part-way through a message.
+    Truncated,
+    /// The header or file checksum does not match, so the file is corrupt.
+    BadChecksum,
+    /// A data message uses a local message type that has not been defined.
+    UndefinedMessage(u8),
+}
+
+impl fmt::Display for FitError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FitError::NotFit => write!(f, "not a FIT file"),
+            FitError::Truncated => write!(f, "the FIT file is incomplete"),
+            FitError::BadChecksum => write!(f, "the FIT file is corrupt (checksum mismatch)"),
+            FitError::UndefinedMessage(local) => {
+                write!(f, "the FIT file uses undefined message type {}", local)
+            }
+        }
+    }
+}
+
+impl std::error::Error for FitError {}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Value {
+    Number(f64),
+    Text(String),
+}
+
+/// A decoded data message, holding only the fields with valid values.
+#[derive(Debug)]
+struct Message {
+    global: u16,
+    fields: Vec<(u8, Value)>,
+}
+
+impl Message {
+    fn number(&self, field: u8) -> Option<f64> {
+        self.fields.iter().find_map(|(number, value)| match value {
+            Value::Number(n) if *number == field => Some(*n),
+            _ => None,
+        })
+    }
+
+    fn text(&self, field: u8) -> Option<&str> {
+        self.fields.iter().find_map(|(number, value)| match value {
+            Value::Text(text) if *number == field => Some(text.as_str()),
+            _ => None,
+        })
+    }
+
+    fn time(&self, field: u8) -> Option<OffsetDateTime> {
+        let seconds = self.number(field)? as i64;
+        OffsetDateTime::from_unix_timestamp(FIT_EPOCH + seconds).ok()
+    }
+
+    /// Position from a pair of latitude and longitude fields in semicircles.
+    fn coord(&self, lat_field: u8, lon_field: u8) -> Option<Coord> {
+        let degrees = |semicircles: f64| semicircles * 180.0 / 2f64.powi(31);
+        Some(Coord::new(
+            degrees(self.number(lat_field)?),
+            degrees(self.number(lon_field)?),
+        ))
+    }
+}
+
+struct FieldDefinition {
+    number: u8,
+    size: usize,
+    base_type: u8,
+}
+
+/// Layout of the data messages that follow, for one local message type.
+struct Definition {
+    global: u16,
+    big_endian: bool,
+    fields: Vec<FieldDefinition>,
+    /// Total size of developer fields, which are skipped.
+    developer_size: usize,
+}
+
+/// Reads bytes from the data section, failing with [`FitError::Truncated`] at its end.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, n: usize) -> Result<&'a [u8], FitError> {
+        let bytes = self
+            .bytes
+            .get(self.pos..self.pos + n)
+            .ok_or(FitError::Truncated)?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn byte(&mut self) -> Result<u8, FitError> {
+        Ok(self.take(1)?[0])
+    }
+}
+
+pub fn read_fit(bytes: &[u8]) -> Result<TrackDocument, FitError> {
+    read_fit_with_progress(bytes, &mut |_| {})
+}
+
+/// [`read_fit`], passing the fraction of the data section read so far to `on_progress`.
+pub fn read_fit_with_progress(
+    bytes: &[u8],
+    on_progress: &mut dyn FnMut(f64),
+) -> Result<TrackDocument, FitError> {
+    Ok(to_document(&decode(bytes, on_progress)?))
+}
+
+/// FIT's CRC-16, as given in the FIT SDK.
+fn crc(bytes: &[u8]) -> u16 {
+    const TABLE: [u16; 16] = [
+        0x0000, 0xCC01, 0xD801, 0x1400, 0xF001, 0x3C00, 0x2800, 0xE401, 0xA001, 0x6C00, 0x7800,
+        0xB401, 0x5000, 0x9C01, 0x8801, 0x4400,
+    ];
+    bytes.iter().fold(0, |crc, &byte| {
+        let crc = (crc >> 4) ^ TABLE[(crc & 0xF) as usize] ^ TABLE[(byte & 0xF) as usize];
+        (crc >> 4) ^ TABLE[(crc & 0xF) as usize] ^ TABLE[(byte >> 4) as usize]
+    })
+}
+
+fn decode(bytes: &[u8], on_progress: &mut dyn FnMut(f64)) -> Result<Vec<Message>, FitError> {
+    let header_size = *bytes.first().ok_or(FitError::NotFit)? as usize;
+    if header_size < 12 || bytes.get(8..12) != Some(b".FIT") {
+        return Err(FitError::NotFit);
+    }
+    let data_size = u32::from_le_bytes([bytes[4], bytes[5], bytes[6], bytes[7]]) as usize;
+    // The size comes from the file, so may be large enough to overflow on 32-bit targets.
+    let end = header_size
+        .checked_add(data_size)
+        .ok_or(FitError::Truncated)?;
+    let crc_end = end.checked_add(2).ok_or(FitError::Truncated)?;
+    let file_crc = bytes.get(end..crc_end).ok_or(FitError::Truncated)?;
+    if u16::from_le_bytes([file_crc[0], file_crc[1]]) != crc(&bytes[..end]) {
+        return Err(FitError::BadChecksum);
+    }
+    // A zero header checksum means it was not computed.
+    if header_size >= 14 {
+        let header_crc = u16::from_le_bytes([bytes[12], bytes[13]]);
+        if header_crc != 0 && header_crc != crc(&bytes[..12]) {
+            return Err(FitError::BadChecksum);
+        }
+    }
+
+    let mut reader = Reader {
+        bytes: &bytes[header_size..end],
+        pos: 0,
+    };
+    let mut progress = Progress::new(reader.bytes.len(), on_progress);
+    let mut definitions: [Option<Definition>; 16] = Default::default();
+    let mut messages = Vec::new();
+    // Compressed timestamps count on from the last full timestamp in any message.
+    let mut last_timestamp: Option<u32> = None;
+
+    while reader.pos < reader.bytes.len() {
+        progress.update(reader.pos);
+        let header = reader.byte()?;
+        if header & 0x80 != 0 {
+            let local = (header >> 5) & 0x03;
+            let offset = (header & 0x1F) as u32;
+            let timestamp = last_timestamp
+                .map(|last| last.wrapping_add(offset.wrapping_sub(last & 0x1F) & 0x1F));
+            let definition = definitions[local as usize]
+                .as_ref()
+                .ok_or(FitError::UndefinedMessage(local))?;
+            let mut message = read_message(&mut reader, definition, &mut last_timestamp)?;
+            if let Some(timestamp) = timestamp {
+                last_timestamp = Some(timestamp);
+                message
+                    .fields
+                    .push((TIMESTAMP, Value::Number(timestamp as f64)));
+            }
+            messages.push(message);
+        } else if header & 0x40 != 0 {
+            let local = header & 0x0F;
+            let has_developer_fields = header & 0x20 != 0;
+            reader.byte()?; // reserved
+            let big_endian = reader.byte()? == 1;
+            let global = reader.take(2)?;
+            let global = if big_endian {
+                u16::from_be_bytes([global[0], global[1]])
+            } else {
+                u16::from_le_bytes([global[0], global[1]])
+            };
+            let field_count = reader.byte()?;
+            let fields = (0..field_count)
+                .map(|_| {
+                    let field = reader.take(3)?;
+                    Ok(FieldDefinition {
+                        number: field[0],
+                        size: field[1] as usize,
+                        base_type: field[2],
+                    })
+                })
+                .collect::<Result<_, FitError>>()?;
+            let mut developer_size = 0;
+            if has_developer_fields {
+                let count = reader.byte()?;
+                for _ in 0..count {
+                    developer_size += reader.take(3)?[1] as usize;
+                }
+            }
+            definitions[local as usize] = Some(Definition {
+                global,
+                big_endian,
+                fields,
+                developer_size,
+            });
+        } else {
+            let local = header & 0x0F;
+            let definition = definitions[local as usize]
+                .as_ref()
+                .ok_or(FitError::UndefinedMessage(local))?;
+            messages.push(read_message(&mut reader, definition, &mut last_timestamp)?);
+        }
+    }
+    progress.update(reader.pos);
+    Ok(messages)
+}
+
+fn read_message(
+    reader: &mut Reader,
+    definition: &Definition,
+    last_timestamp: &mut Option<u32>,
+) -> Result<Message, FitError> {
+    let mut fields = Vec::new();
+    for field in &definition.fields {
+        let bytes = reader.take(field.size)?;
+        if let Some(value) = read_value(bytes, field.base_type, definition.big_endian) {
+            if let (TIMESTAMP, Value::Number(timestamp)) = (field.number, &value) {
+                *last_timestamp = Some(*timestamp as u32);
+            }
+            fields.push((field.number, value));
+        }
+    }
+    reader.take(definition.developer_size)?;
+    Ok(Message {
+        global: definition.global,
+        fields,
+    })
+}
+
+/// The first value of a field, or `None` if it holds the base type's invalid value.
+fn read_value(bytes: &[u8], base_type: u8, big_endian: bool) -> Option<Value> {
+    // (width in bytes, signed, invalid value) for each integer base type.
+    let (width, signed, invalid): (usize, bool, u64) = match base_type & 0x1F {
+        0x07 => {
+            let end = bytes.iter().position(|&b| b == 0).unwrap_or(bytes.len());
+            let text = String::from_utf8_lossy(&bytes[..end]).into_owned();
+            return (!text.is_empty()).then_some(Value::Text(text));
+        }
+        0x08 | 0x09 => {
+            let value = match *bytes {
+                [a, b, c, d, ..] if base_type & 0x1F == 0x08 => {
+                    let bits = [a, b, c, d];
+                    f32::from_bits(if big_endian {
+                        u32::from_be_bytes(bits)
+                    } else {
+                        u32::from_le_bytes(bits)
+                    }) as f64
+                }
+                [a, b, c, d, e, f, g, h, ..] => {
+                    let bits = [a, b, c, d, e, f, g, h];
+                    f64::from_bits(if big_endian {
+                        u64::from_be_bytes(bits)
+                    } else {
+                        u64::from_le_bytes(bits)
+                    })
+                }
+                _ => return None,
+            };
+            // The invalid float is all ones, which is a NaN.
+            return value.is_finite().then_some(Value::Number(value));
+        }
+        0x00 | 0x02 | 0x0D => (1, false, 0xFF),
+        0x01 => (1, true, 0x7F),
+        0x03 => (2, true, 0x7FFF),
+        0x04 => (2, false, 0xFFFF),
+        0x05 => (4, true, 0x7FFF_FFFF),
+        0x06 => (4, false, 0xFFFF_FFFF),
+        0x0A => (1, false, 0),
+        0x0B => (2, false, 0),
+        0x0C => (4, false, 0),
+        0x10 => (8, false, 0),
+        0x0E => (8, true, 0x7FFF_FFFF_FFFF_FFFF),
+        0x0F => (8, false, u64::MAX),
+        _ => return None,
+    };
+    let bytes = bytes.get(..width)?;
+    let raw = if big_endian {
+        bytes.iter().fold(0u64, |acc, &b| (acc << 8) | b as u64)
+    } else {
+        bytes
+            .iter()
+            .rev()
+            .fold(0u64, |acc, &b| (acc << 8) | b as u64)
+    };
+    if raw == invalid {
+        return None;
+    }
+    let value = if signed {
+        // Sign-extend from the field's width.
+        let shift = 64 - 8 * width as u32;
+        ((raw << shift) as i64 >> shift) as f64
+    } else {
+        raw as f64
+    };
+    Some(Value::Number(value))
+}
+
+/// Name of a FIT `sport`, for the track's activity type.
+fn sport_name(sport: f64) -> Option<&'static str> {
+    Some(match sport as u8 {
+        0 => "generic",
+        1 => "running",
+        2 => "cycling",
+        5 => "swimming",
+        11 => "walking",
+        12 => "cross_country_skiing",
+        13 => "alpine_skiing",
+        15 => "rowing",
+        16 => "mountaineering",
+        17 => "hiking",
+        19 => "paddling",
+        _ => return None,
+    })
+}
+
+/// Name of a FIT `course_point` type, spelt as TCX's `PointType` so both formats agree.
+fn course_point_name(kind: f64) -> Option<&'static str> {
+    Some(match kind as u8 {
+        0 => "Generic",
+        1 => "Summit",
+        2 => "Valley",
+        3 => "Water",
+        4 => "Food",
+        5 => "Danger",
+        6 => "Left",
+        7 => "Right",
+        8 => "Straight",
+        9 => "First Aid",
+        10 => "4th Category",
+        11 => "3rd Category",
+        12 => "2nd Category",
+        13 => "1st Category",
+        14 => "Hors Category",
+        15 => "Sprint",
+        _ => return None,
+    })
+}
+
+fn to_document(messages: &[Message]) -> TrackDocument {
+    let of_type = |global: u16| messages.iter().filter(move |m| m.global == global);
+    let is_course = of_type(FILE_ID)
+        .next()
+        .and_then(|file_id| file_id.number(0))
+        == Some(FILE_TYPE_COURSE);
+
+    let records: Vec<(Option<f64>, TrackPoint)> = of_type(RECORD)
+        .filter_map(|record| {
+            // enhanced_altitude replaces altitude where the range of a u16 is not enough.
+            let altitude = record.number(78).or_else(|| record.number(2));
+            let point = TrackPoint {
+                coord: record.coord(0, 1)?,
+                elevation: altitude.map(|altitude| altitude / 5.0 - 500.0),
+                time: record.time(TIMESTAMP),
+                extensions: PointExtensions {
+                    heart_rate: record.number(3).map(|n| n as u16),
+                    cadence: record.number(4).map(|n| n as u16),
+                    power: record.number(7).map(|n| n as u16),
+                    temperature: record.number(13),
+                },
+            };
+            Some((record.number(TIMESTAMP), point))
+        })
+        .collect();
+
+    let mut document = TrackDocument {
+        waypoints: of_type(COURSE_POINT)
+            .filter_map(|point| {
+                Some(Waypoint {
+                    coord: point.coord(2, 3)?,
+                    time: point.time(1),
+                    name: point.text(6).map(str::to_string),
+                    symbol: point
+                        .number(5)
+                        .and_then(course_point_name)
+                        .map(str::to_string),
+                    ..Waypoint::default()
+                })
+            })
+            .collect(),
+        ..TrackDocument::default()
+    };
+
+    if is_course {
+        let course = of_type(COURSE).next();
+        document.routes.push(Route {
+            name: course.and_then(|c| c.text(5)).map(str::to_string),
+            description: None,
+            points: records.into_iter().map(|(_, point)| point).collect(),
+        });
+    } else if !records.is_empty() {
+        // Start a new segment after each lap's end time.
+        let mut lap_ends: Vec<f64> = of_type(LAP)
+            .filter_map(|lap| lap.number(TIMESTAMP))
+            .collect();
+        lap_ends.sort_by(f64::total_cmp);
+        let mut segments = vec![Segment::default()];
+        let mut lap = 0;
+        for (timestamp, point) in records {
+            while let (Some(timestamp), Some(&end)) = (timestamp, lap_ends.get(lap)) {
+                if timestamp <= end {
+                    break;
+                }
+                lap += 1;
+                segments.push(Segment::default());
+            }
+            segments.last_mut().unwrap().points.push(point);
+        }
+        segments.retain(|segment| !segment.points.is_empty());
+        document.tracks.push(Track {
+            kind: of_type(SESSION)
+                .find_map(|session| session.number(5))
+                .and_then(sport_name)
+                .map(str::to_string),
+            segments,
+            ..Track::default()
+        });
+    }
+    document
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    fn assert_near(coord: Coord, lat: f64, lon: f64) {
+        // Semicircles resolve to about 1e-7 degrees.
+        assert!((coord.lat - lat).abs() < 1e-6, "{:?}", coord);
+        assert!((coord.lon - lon).abs() < 1e-6, "{:?}", coord);
+    }
+
+    /// `ride.fit` is a cycling activity of six records over two laps. The first three have
+    /// every field and a developer field; the last three use compressed timestamps that
+    /// roll over the 5-bit offset, and the final one has no position. Laps are big-endian.
+    #[test]
+    fn test_read_activity() {
+        let document = read_fit(&std::fs::read("src/data/ride.fit").unwrap()).unwrap();
+        assert!(document.routes.is_empty());
+        assert_eq!(document.tracks.len(), 1);
+        let track = &document.tracks[0];
+        assert_eq!(track.kind.as_deref(), Some("cycling"));
+        assert_eq!(track.segments.len(), 2);
+        assert_eq!(track.segments[0].points.len(), 3);
+        assert_eq!(track.segments[1].points.len(), 2);
+
+        let first = &track.segments[0].points[0];
+        assert_near(first.coord, 52.2053, 0.1218);
+        assert!((first.elevation.unwrap() - 12.4).abs() < 1e-9);
+        assert_eq!(first.time, Some(datetime!(2024-05-01 09:30:06 UTC)));
+        assert_eq!(
+            first.extensions,
+            PointExtensions {
+                heart_rate: Some(128),
+                cadence: Some(85),
+                power: Some(210),
+                temperature: Some(18.0),
+            }
+        );
+        // An invalid value is left out rather than read as 65535 W.
+        assert_eq!(track.segments[0].points[2].extensions.power, None);
+
+        let compressed = &track.segments[1].points[0];
+        assert_near(compressed.coord, 52.2070, 0.1240);
+        assert_eq!(compressed.time, Some(datetime!(2024-05-01 09:30:09 UTC)));
+        assert_eq!(compressed.extensions.heart_rate, Some(133));
+        assert_eq!(
+            track.segments[1].points[1].time,
+            Some(datetime!(2024-05-01 09:30:10 UTC))
+        );
+    }
+
+    /// `course.fit` is a three-point course called "Loop" with one course point, and has
+    /// a 12-byte header without a header checksum.
+    #[test]
+    fn test_read_course() {
+        let document = read_fit(&std::fs::read("src/data/course.fit").unwrap()).unwrap();
+        assert!(document.tracks.is_empty());
+        assert_eq!(document.routes.len(), 1);
+        let route = &document.routes[0];
+        assert_eq!(route.name.as_deref(), Some("Loop"));
+        assert_eq!(route.points.len(), 3);
+        assert_near(route.points[2].coord, 52.1, 0.1);
+        assert!((route.points[2].elevation.unwrap() - 30.0).abs() < 1e-9);
+
+        assert_eq!(document.waypoints.len(), 1);
+        let waypoint = &document.waypoints[0];
+        assert_eq!(waypoint.name.as_deref(), Some("Bridleway"));
+        assert_eq!(waypoint.symbol.as_deref(), Some("Left"));
+        assert_near(waypoint.coord, 52.05, 0.05);
+    }
+
+    #[test]
+    fn test_read_fit_errors() {
+        let bytes = std::fs::read("src/data/ride.fit").unwrap();
+        assert_eq!(read_fit(b"<gpx></gpx>"), Err(FitError::NotFit));
+        assert_eq!(
+            read_fit(&bytes[..bytes.len() - 10]),
+            Err(FitError::Truncated)
+        );
+        let mut oversized = bytes.clone();
+        oversized[4..8].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(read_fit(&oversized), Err(FitError::Truncated));
+
+        let mut corrupt = bytes.clone();
+        corrupt[40] ^= 0xFF;
+        assert_eq!(read_fit(&corrupt), Err(FitError::BadChecksum));
+    }
+}
@@ -1,6 +1,41 @@
+use core::fmt;
+
 use leaflet::LatLng;
+use serde::{Deserialize, Serialize};
+
+mod bbox;
+mod encoding;
+mod index;
+mod osgb;
+mod plus_code;
+mod projection;
+mod resample;
+mod simplify;
+pub mod tiles;
+mod transverse_mercator;
+mod utm;
+
+pub use bbox::BBox;
+pub use encoding::{decode_binary, decode_polyline, encode_binary, encode_polyline, DecodeError};
+pub use index::SpatialIndex;
+pub use projection::{project_onto_line, LineMatch};
+pub use resample::{
+    point_at_distance, point_at_time, resample_by_distance, resample_by_time, SegmentSample,
+};
+pub use simplify::{
+    douglas_peucker, limit_track_points, visvalingam, visvalingam_areas, visvalingam_to_count,
+};
+pub use utm::Utm;
+
+/// Mean Earth radius in metres (IUGG), used by the spherical formulas.
+pub const EARTH_RADIUS: f64 = 6_371_008.8;
 
-#[derive(Default, Clone, Copy, Debug)]
+// WGS84 ellipsoid parameters, used by the Vincenty formula.
+const WGS84_A: f64 = 6_378_137.0;
+const WGS84_F: f64 = 1.0 / 298.257_223_563;
+const WGS84_B: f64 = WGS84_A * (1.0 - WGS84_F);
+
+#[derive(Default, Clone, Copy, Debug, Serialize, Deserialize)]
 pub struct Coord {
     pub lat: f64,
     pub lon: f64,
@@ -17,3 +52,260 @@ impl PartialEq for Coord {
         self.lat == other.lat && self.lon == other.lon
     }
 }
+
+/// Error returned for positions or references that a grid system cannot represent.
+#[derive(Debug, PartialEq)]
+pub enum GridRefError {
+    /// Reference is not in the expected format.
+    Invalid(String),
+    /// Position lies outside the area covered by the grid.
+    OutOfRange,
+}
+
+impl fmt::Display for GridRefError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GridRefError::Invalid(reference) => write!(f, "invalid grid reference {:?}", reference),
+            GridRefError::OutOfRange => write!(f, "position is outside the grid"),
+        }
+    }
+}
+
+impl std::error::Error for GridRefError {}
+
+impl Coord {
+    pub fn new(lat: f64, lon: f64) -> Self {
+        Coord { lat, lon }
+    }
+
+    /// Great-circle distance in metres to `other`, using the haversine formula on a sphere.
+    pub fn haversine_distance(&self, other: &Coord) -> f64 {
+        let (phi1, phi2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_phi = phi2 - phi1;
+        let d_lambda = (other.lon - self.lon).to_radians();
+        let a =
+            (d_phi / 2.0).sin().powi(2) + phi1.cos() * phi2.cos() * (d_lambda / 2.0).sin().powi(2);
+        2.0 * EARTH_RADIUS * a.sqrt().atan2((1.0 - a).sqrt())
+    }
+
+    /// Distance in metres to `other` on the WGS84 ellipsoid, using Vincenty's inverse formula.
+    /// Returns `None` if the iteration fails to converge (nearly antipodal points).
+    pub fn vincenty_distance(&self, other: &Coord) -> Option<f64> {
+        let l = (other.lon - self.lon).to_radians();
+        let u1 = ((1.0 - WGS84_F) * self.lat.to_radians().tan()).atan();
+        let u2 = ((1.0 - WGS84_F) * other.lat.to_radians().tan()).atan();
+        let (sin_u1, cos_u1) = u1.sin_cos();
+        let (sin_u2, cos_u2) = u2.sin_cos();
+
+        let mut lambda = l;
+        for _ in 0..200 {
+            let (sin_lambda, cos_lambda) = lambda.sin_cos();
+            let sin_sigma = ((cos_u2 * sin_lambda).powi(2)
+                + (cos_u1 * sin_u2 - sin_u1 * cos_u2 * cos_lambda).powi(2))
+            .sqrt();
+            if sin_sigma == 0.0 {
+                // Coincident points
+                return Some(0.0);
+            }
+            let cos_sigma = sin_u1 * sin_u2 + cos_u1 * cos_u2 * cos_lambda;
+            let sigma = sin_sigma.atan2(cos_sigma);
+            let sin_alpha = cos_u1 * cos_u2 * sin_lambda / sin_sigma;
+            let cos_sq_alpha = 1.0 - sin_alpha * sin_alpha;
+            // Both points on the equator makes cos_sq_alpha zero
+            let cos_2sigma_m = if cos_sq_alpha != 0.0 {
+                cos_sigma - 2.0 * sin_u1 * sin_u2 / cos_sq_alpha
+            } else {
+                0.0
+            };
+            let c = WGS84_F / 16.0 * cos_sq_alpha * (4.0 + WGS84_F * (4.0 - 3.0 * cos_sq_alpha));
+            let lambda_prev = lambda;
+            lambda = l
+                + (1.0 - c)
+                    * WGS84_F
+                    * sin_alpha
+                    * (sigma
+                        + c * sin_sigma
+                            * (cos_2sigma_m
+                                + c * cos_sigma * (-1.0 + 2.0 * cos_2sigma_m * cos_2sigma_m)));
+
+            if (lambda - lambda_prev).abs() < 1e-12 {
+                let u_sq =
+                    cos_sq_alpha * (WGS84_A * WGS84_A - WGS84_B * WGS84_B) / (WGS84_B * WGS84_B);
+                let a = 1.0
+                    + u_sq / 16384.0 * (4096.0 + u_sq * (-768.0 + u_sq * (320.0 - 175.0 * u_sq)));
+                let b = u_sq / 1024.0 * (256.0 + u_sq * (-128.0 + u_sq * (74.0 - 47.0 * u_sq)));
+                let delta_sigma = b
+                    * sin_sigma
+                    * (cos_2sigma_m
+                        + b / 4.0
+                            * (cos_sigma * (-1.0 + 2.0 * cos_2sigma_m * cos_2sigma_m)
+                                - b / 6.0
+                                    * cos_2sigma_m
+                                    * (-3.0 + 4.0 * sin_sigma * sin_sigma)
+                                    * (-3.0 + 4.0 * cos_2sigma_m * cos_2sigma_m)));
+                return Some(WGS84_B * a * (sigma - delta_sigma));
+            }
+        }
+        None
+    }
+
+    /// Initial bearing (forward azimuth) towards `other`, in degrees clockwise from north, 0..360.
+    pub fn initial_bearing(&self, other: &Coord) -> f64 {
+        let (phi1, phi2) = (self.lat.to_radians(), other.lat.to_radians());
+        let d_lambda = (other.lon - self.lon).to_radians();
+        let y = d_lambda.sin() * phi2.cos();
+        let x = phi1.cos() * phi2.sin() - phi1.sin() * phi2.cos() * d_lambda.cos();
+        normalise_bearing(y.atan2(x).to_degrees())
+    }
+
+    /// Bearing on arrival at `other` when following the great circle from `self`, 0..360.
+    pub fn final_bearing(&self, other: &Coord) -> f64 {
+        normalise_bearing(other.initial_bearing(self) + 180.0)
+    }
+
+    /// Point reached by travelling `distance` metres along the great circle with initial `bearing` in degrees.
+    pub fn destination(&self, bearing: f64, distance: f64) -> Coord {
+        let delta = distance / EARTH_RADIUS;
+        let theta = bearing.to_radians();
+        let phi1 = self.lat.to_radians();
+        let lambda1 = self.lon.to_radians();
+        let phi2 = (phi1.sin() * delta.cos() + phi1.cos() * delta.sin() * theta.cos()).asin();
+        let lambda2 = lambda1
+            + (theta.sin() * delta.sin() * phi1.cos()).atan2(delta.cos() - phi1.sin() * phi2.sin());
+        Coord {
+            lat: phi2.to_degrees(),
+            lon: normalise_longitude(lambda2.to_degrees()),
+        }
+    }
+
+    /// Half-way point along the great circle between `self` and `other`.
+    pub fn midpoint(&self, other: &Coord) -> Coord {
+        let (phi1, phi2) = (self.lat.to_radians(), other.lat.to_radians());
+        let lambda1 = self.lon.to_radians();
+        let d_lambda = (other.lon - self.lon).to_radians();
+        let bx = phi2.cos() * d_lambda.cos();
+        let by = phi2.cos() * d_lambda.sin();
+        let phi3 = (phi1.sin() + phi2.sin()).atan2(((phi1.cos() + bx).powi(2) + by * by).sqrt());
+        let lambda3 = lambda1 + by.atan2(phi1.cos() + bx);
+        Coord {
+            lat: phi3.to_degrees(),
+            lon: normalise_longitude(lambda3.to_degrees()),
+        }
+    }
+}
+
+/// Total haversine length in metres of the polyline through `coords`.
+pub fn path_length(coords: &[Coord]) -> f64 {
+    coords
+        .windows(2)
+        .map(|pair| pair[0].haversine_distance(&pair[1]))
+        .sum()
+}
+
+/// Distance in metres from the first coordinate to each coordinate along the polyline.
+/// The result has the same length as `coords` and starts at zero.
+pub fn cumulative_distances(coords: &[Coord]) -> Vec<f64> {
+    let mut total = 0.0;
+    let mut distances = Vec::with_capacity(coords.len());
+    for (i, coord) in coords.iter().enumerate() {
+        if i > 0 {
+            total += coords[i - 1].haversine_distance(coord);
+        }
+        distances.push(total);
+    }
+    distances
+}
+
+/// Wrap a bearing in degrees into 0..360.
+fn normalise_bearing(degrees: f64) -> f64 {
+    degrees.rem_euclid(360.0)
+}
+
+/// Wrap a longitude in degrees into -180..180.
+fn normalise_longitude(degrees: f64) -> f64 {
+    (degrees + 540.0).rem_euclid(360.0) - 180.0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Convert degrees, minutes and seconds to decimal degrees.
+    fn dms(d: f64, m: f64, s: f64) -> f64 {
+        d.signum() * (d.abs() + m / 60.0 + s / 3600.0)
+    }
+
+    // Land's End to John o' Groats, reference values from movable-type.co.uk
+    fn lands_end() -> Coord {
+        Coord::new(dms(50.0, 3.0, 59.0), -dms(5.0, 42.0, 53.0))
+    }
+    fn john_o_groats() -> Coord {
+        Coord::new(dms(58.0, 38.0, 38.0), -dms(3.0, 4.0, 12.0))
+    }
+
+    #[test]
+    fn test_haversine_distance() {
+        let d = lands_end().haversine_distance(&john_o_groats());
+        assert!((d - 968_900.0).abs() < 100.0, "Testing haversine distance");
+        assert_eq!(lands_end().haversine_distance(&lands_end()), 0.0);
+    }
+
+    #[test]
+    fn test_vincenty_distance() {
+        // Flinders Peak to Buninyong, from Geoscience Australia
+        let flinders_peak = Coord::new(-dms(37.0, 57.0, 3.72030), dms(144.0, 25.0, 29.52440));
+        let buninyong = Coord::new(-dms(37.0, 39.0, 10.15610), dms(143.0, 55.0, 35.38390));
+        let d = flinders_peak.vincenty_distance(&buninyong).unwrap();
+        assert!((d - 54_972.271).abs() < 0.001, "Testing Vincenty distance");
+        assert_eq!(buninyong.vincenty_distance(&buninyong), Some(0.0));
+        // Nearly antipodal points fail to converge
+        assert_eq!(
+            Coord::new(0.0, 0.0).vincenty_distance(&Coord::new(0.5, 179.7)),
+            None
+        );
+    }
+
+    #[test]
+    fn test_bearings() {
+        let initial = lands_end().initial_bearing(&john_o_groats());
+        let fin = lands_end().final_bearing(&john_o_groats());
+        assert!((initial - dms(9.0, 7.0, 11.0)).abs() < 0.001);
+        assert!((fin - dms(11.0, 16.0, 31.0)).abs() < 0.001);
+        // Due west wraps into 0..360
+        let west = Coord::new(0.0, 1.0).initial_bearing(&Coord::new(0.0, 0.0));
+        assert!((west - 270.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_destination() {
+        let start = Coord::new(dms(53.0, 19.0, 14.0), -dms(1.0, 43.0, 47.0));
+        let dest = start.destination(dms(96.0, 1.0, 18.0), 124_800.0);
+        assert!((dest.lat - dms(53.0, 11.0, 18.0)).abs() < 0.001);
+        assert!((dest.lon - dms(0.0, 8.0, 0.0)).abs() < 0.001);
+        // Crossing the antimeridian wraps longitude
+        let east = Coord::new(0.0, 179.9).destination(90.0, 50_000.0);
+        assert!(east.lon < -179.0);
+    }
+
+    #[test]
+    fn test_path_length() {
+        let line = [
+            Coord::new(52.0, 0.0),
+            Coord::new(52.01, 0.0),
+            Coord::new(52.02, 0.0),
+        ];
+        let step = line[0].haversine_distance(&line[1]);
+        assert!((path_length(&line) - 2.0 * step).abs() < 1e-6);
+        assert_eq!(cumulative_distances(&line)[0], 0.0);
+        assert!((cumulative_distances(&line)[2] - path_length(&line)).abs() < 1e-9);
+        assert_eq!(path_length(&line[..1]), 0.0);
+        assert!(cumulative_distances(&[]).is_empty());
+    }
+
+    #[test]
+    fn test_midpoint() {
+        let mid = lands_end().midpoint(&john_o_groats());
+        assert!((mid.lat - dms(54.0, 21.0, 44.0)).abs() < 0.001);
+        assert!((mid.lon + dms(4.0, 31.0, 50.0)).abs() < 0.001);
+    }
+}
@@ -0,0 +1,188 @@
+use leaflet::{LatLng, LatLngBounds};
+
+use super::{Coord, EARTH_RADIUS};
+
+/// Axis-aligned latitude/longitude bounding box.
+/// Boxes spanning the antimeridian are not supported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BBox {
+    pub south: f64,
+    pub west: f64,
+    pub north: f64,
+    pub east: f64,
+}
+
+impl BBox {
+    pub fn new(south_west: Coord, north_east: Coord) -> Self {
+        BBox {
+            south: south_west.lat,
+            west: south_west.lon,
+            north: north_east.lat,
+            east: north_east.lon,
+        }
+    }
+
+    /// Zero-sized box around a single coordinate.
+    pub fn from_coord(coord: Coord) -> Self {
+        BBox::new(coord, coord)
+    }
+
+    /// Smallest box containing all `coords`, or `None` if there are none.
+    pub fn from_coords<I: IntoIterator<Item = Coord>>(coords: I) -> Option<Self> {
+        let mut coords = coords.into_iter();
+        let mut bbox = BBox::from_coord(coords.next()?);
+        coords.for_each(|coord| bbox.extend(coord));
+        Some(bbox)
+    }
+
+    pub fn south_west(&self) -> Coord {
+        Coord::new(self.south, self.west)
+    }
+
+    pub fn north_east(&self) -> Coord {
+        Coord::new(self.north, self.east)
+    }
+
+    pub fn center(&self) -> Coord {
+        Coord::new(
+            (self.south + self.north) / 2.0,
+            (self.west + self.east) / 2.0,
+        )
+    }
+
+    /// Grow the box in place so that it contains `coord`.
+    pub fn extend(&mut self, coord: Coord) {
+        self.south = self.south.min(coord.lat);
+        self.west = self.west.min(coord.lon);
+        self.north = self.north.max(coord.lat);
+        self.east = self.east.max(coord.lon);
+    }
+
+    /// Smallest box containing both `self` and `other`.
+    pub fn union(&self, other: &BBox) -> BBox {
+        BBox {
+            south: self.south.min(other.south),
+            west: self.west.min(other.west),
+            north: self.north.max(other.north),
+            east: self.east.max(other.east),
+        }
+    }
+
+    /// Whether `coord` lies inside the box or on its edge.
+    pub fn contains(&self, coord: &Coord) -> bool {
+        (self.south..=self.north).contains(&coord.lat)
+            && (self.west..=self.east).contains(&coord.lon)
+    }
+
+    /// Whether `other` lies entirely inside the box.
+    pub fn contains_bbox(&self, other: &BBox) -> bool {
+        self.contains(&other.south_west()) && self.contains(&other.north_east())
+    }
+
+    pub fn intersects(&self, other: &BBox) -> bool {
+        self.south <= other.north
+            && other.south <= self.north
+            && self.west <= other.east
+            && other.west <= self.east
+    }
+
+    /// Overlapping area of `self` and `other`, or `None` if they are disjoint.
+    pub fn intersection(&self, other: &BBox) -> Option<BBox> {
+        if !self.intersects(other) {
+            return None;
+        }
+        Some(BBox {
+            south: self.south.max(other.south),
+            west: self.west.max(other.west),
+            north: self.north.min(other.north),
+            east: self.east.min(other.east),
+        })
+    }
+
+    /// Box grown by at least `metres` on every side, clamped to valid latitudes and longitudes.
+    pub fn buffer(&self, metres: f64) -> BBox {
+        let d_lat = (metres / EARTH_RADIUS).to_degrees();
+        // Longitude degrees shrink towards the poles, so widen using the latitude nearest a pole.
+        let max_abs_lat = self.south.abs().max(self.north.abs()).min(89.9);
+        let d_lon = d_lat / max_abs_lat.to_radians().cos();
+        BBox {
+            south: (self.south - d_lat).max(-90.0),
+            west: (self.west - d_lon).max(-180.0),
+            north: (self.north + d_lat).min(90.0),
+            east: (self.east + d_lon).min(180.0),
+        }
+    }
+}
+
+impl From<BBox> for LatLngBounds {
+    fn from(bbox: BBox) -> Self {
+        LatLngBounds::new(
+            &LatLng::from(bbox.south_west()),
+            &LatLng::from(bbox.north_east()),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn cambridge() -> BBox {
+        BBox::new(Coord::new(52.15, 0.05), Coord::new(52.25, 0.20))
+    }
+
+    #[test]
+    fn test_from_coords() {
+        assert_eq!(BBox::from_coords(Vec::<Coord>::new()), None);
+        let bbox = BBox::from_coords(vec![
+            Coord::new(52.2, 0.1),
+            Coord::new(52.15, 0.2),
+            Coord::new(52.25, 0.05),
+        ])
+        .unwrap();
+        assert_eq!(bbox, cambridge());
+    }
+
+    #[test]
+    fn test_union_and_intersection() {
+        let other = BBox::new(Coord::new(52.20, 0.15), Coord::new(52.30, 0.30));
+        let union = cambridge().union(&other);
+        assert_eq!(
+            union,
+            BBox::new(Coord::new(52.15, 0.05), Coord::new(52.30, 0.30))
+        );
+        assert!(union.contains_bbox(&cambridge()) && union.contains_bbox(&other));
+        assert_eq!(
+            cambridge().intersection(&other),
+            Some(BBox::new(Coord::new(52.20, 0.15), Coord::new(52.25, 0.20)))
+        );
+        let far = BBox::from_coord(Coord::new(51.5, -0.1));
+        assert!(!cambridge().intersects(&far));
+        assert_eq!(cambridge().intersection(&far), None);
+    }
+
+    #[test]
+    fn test_contains() {
+        assert!(cambridge().contains(&Coord::new(52.2, 0.12)));
+        assert!(
+            cambridge().contains(&Coord::new(52.15, 0.05)),
+            "Edges are inside"
+        );
+        assert!(!cambridge().contains(&Coord::new(52.3, 0.12)));
+    }
+
+    #[test]
+    fn test_buffer() {
+        let bbox = cambridge();
+        let buffered = bbox.buffer(1000.0);
+        assert!(buffered.contains_bbox(&bbox));
+        // Each side moves out by at least a kilometre
+        let south = Coord::new(buffered.south, bbox.west);
+        let west = Coord::new(bbox.north, buffered.west);
+        assert!((south.haversine_distance(&bbox.south_west()) - 1000.0).abs() < 1.0);
+        assert!(west.haversine_distance(&Coord::new(bbox.north, bbox.west)) >= 999.0);
+        // Clamped at the poles
+        let polar = BBox::from_coord(Coord::new(89.99, 0.0)).buffer(10_000.0);
+        assert_eq!(polar.north, 90.0);
+    }
+}
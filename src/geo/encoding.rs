@@ -0,0 +1,271 @@
+use core::fmt;
+
+use super::Coord;
+
+/// Error returned when decoding an encoded polyline or binary coordinate sequence.
+#[derive(Debug, PartialEq)]
+pub enum DecodeError {
+    /// Input ended in the middle of a value, or with a latitude but no longitude.
+    Truncated,
+    /// Character outside the polyline alphabet, at the given byte offset.
+    InvalidCharacter(usize),
+    /// Binary header names a precision that is not supported.
+    InvalidPrecision(u8),
+    /// A value does not fit in 64 bits.
+    Overflow,
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::Truncated => write!(f, "encoded coordinates are truncated"),
+            DecodeError::InvalidCharacter(at) => {
+                write!(f, "invalid polyline character at offset {}", at)
+            }
+            DecodeError::InvalidPrecision(p) => write!(f, "unsupported precision {}", p),
+            DecodeError::Overflow => write!(f, "encoded value is too large"),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// Highest number of decimal places we encode; 1e-9 degrees is well below GPS accuracy.
+const MAX_PRECISION: u32 = 9;
+
+fn scale(precision: u32) -> f64 {
+    10f64.powi(precision as i32)
+}
+
+/// Rounded fixed-point deltas between consecutive coordinates, as (lat, lon) pairs.
+fn deltas(coords: &[Coord], precision: u32) -> impl Iterator<Item = (i64, i64)> + '_ {
+    let factor = scale(precision);
+    let mut prev = (0i64, 0i64);
+    coords.iter().map(move |c| {
+        let current = (
+            (c.lat * factor).round() as i64,
+            (c.lon * factor).round() as i64,
+        );
+        let delta = (current.0 - prev.0, current.1 - prev.1);
+        prev = current;
+        delta
+    })
+}
+
+/// Sum (lat, lon) deltas read from `next` back into coordinates.
+fn undelta(
+    mut next: impl FnMut() -> Result<Option<i64>, DecodeError>,
+    factor: f64,
+) -> Result<Vec<Coord>, DecodeError> {
+    let mut coords = Vec::new();
+    let (mut lat, mut lon) = (0i64, 0i64);
+    while let Some(d_lat) = next()? {
+        let d_lon = next()?.ok_or(DecodeError::Truncated)?;
+        lat = lat.checked_add(d_lat).ok_or(DecodeError::Overflow)?;
+        lon = lon.checked_add(d_lon).ok_or(DecodeError::Overflow)?;
+        coords.push(Coord::new(lat as f64 / factor, lon as f64 / factor));
+    }
+    Ok(coords)
+}
+
+/// `value` with `bits` set at `shift`, or an error if any of them would fall off the top.
+fn push_bits(value: u64, bits: u64, shift: u32) -> Result<u64, DecodeError> {
+    let shifted = bits.checked_shl(shift).ok_or(DecodeError::Overflow)?;
+    if shifted >> shift != bits {
+        return Err(DecodeError::Overflow);
+    }
+    Ok(value | shifted)
+}
+
+fn zigzag(value: i64) -> u64 {
+    ((value << 1) ^ (value >> 63)) as u64
+}
+
+fn unzigzag(value: u64) -> i64 {
+    ((value >> 1) as i64) ^ -((value & 1) as i64)
+}
+
+/// Encode `coords` with the Google encoded polyline algorithm.
+/// Use a `precision` of 5 for Google Maps, or 6 for OSRM and Valhalla.
+pub fn encode_polyline(coords: &[Coord], precision: u32) -> String {
+    let mut encoded = String::new();
+    let mut push = |value: i64| {
+        let mut value = zigzag(value);
+        while value >= 0x20 {
+            encoded.push(char::from((0x20 | (value & 0x1f)) as u8 + 63));
+            value >>= 5;
+        }
+        encoded.push(char::from(value as u8 + 63));
+    };
+    deltas(coords, precision.min(MAX_PRECISION)).for_each(|(d_lat, d_lon)| {
+        push(d_lat);
+        push(d_lon);
+    });
+    encoded
+}
+
+/// Decode a Google encoded polyline written with `precision` decimal places.
+pub fn decode_polyline(encoded: &str, precision: u32) -> Result<Vec<Coord>, DecodeError> {
+    let bytes = encoded.as_bytes();
+    let mut pos = 0;
+    let next = || -> Result<Option<i64>, DecodeError> {
+        if pos == bytes.len() {
+            return Ok(None);
+        }
+        let (mut value, mut shift) = (0u64, 0);
+        loop {
+            let byte = *bytes.get(pos).ok_or(DecodeError::Truncated)?;
+            if !(63..127).contains(&byte) {
+                return Err(DecodeError::InvalidCharacter(pos));
+            }
+            let chunk = (byte - 63) as u64;
+            value = push_bits(value, chunk & 0x1f, shift)?;
+            shift += 5;
+            pos += 1;
+            if chunk < 0x20 {
+                return Ok(Some(unzigzag(value)));
+            }
+        }
+    };
+    undelta(next, scale(precision.min(MAX_PRECISION)))
+}
+
+/// Encode `coords` in a compact binary form: a precision byte followed by
+/// zigzag LEB128 varint deltas, as in the polyline algorithm but eight bits per byte.
+pub fn encode_binary(coords: &[Coord], precision: u32) -> Vec<u8> {
+    let precision = precision.min(MAX_PRECISION);
+    let mut encoded = vec![precision as u8];
+    let mut push = |value: i64| {
+        let mut value = zigzag(value);
+        while value >= 0x80 {
+            encoded.push(0x80 | (value & 0x7f) as u8);
+            value >>= 7;
+        }
+        encoded.push(value as u8);
+    };
+    deltas(coords, precision).for_each(|(d_lat, d_lon)| {
+        push(d_lat);
+        push(d_lon);
+    });
+    encoded
+}
+
+/// Decode coordinates written by [`encode_binary`].
+pub fn decode_binary(bytes: &[u8]) -> Result<Vec<Coord>, DecodeError> {
+    let (&precision, body) = bytes.split_first().ok_or(DecodeError::Truncated)?;
+    if precision as u32 > MAX_PRECISION {
+        return Err(DecodeError::InvalidPrecision(precision));
+    }
+    let mut pos = 0;
+    let next = || -> Result<Option<i64>, DecodeError> {
+        if pos == body.len() {
+            return Ok(None);
+        }
+        let (mut value, mut shift) = (0u64, 0);
+        loop {
+            let byte = *body.get(pos).ok_or(DecodeError::Truncated)?;
+            value = push_bits(value, (byte & 0x7f) as u64, shift)?;
+            shift += 7;
+            pos += 1;
+            if byte < 0x80 {
+                return Ok(Some(unzigzag(value)));
+            }
+        }
+    };
+    undelta(next, scale(precision as u32))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Example from Google's polyline algorithm documentation
+    const GOOGLE_EXAMPLE: &str = "_p~iF~ps|U_ulLnnqC_mqNvxq`@";
+
+    fn google_coords() -> Vec<Coord> {
+        vec![
+            Coord::new(38.5, -120.2),
+            Coord::new(40.7, -120.95),
+            Coord::new(43.252, -126.453),
+        ]
+    }
+
+    fn assert_close(a: &[Coord], b: &[Coord], epsilon: f64) {
+        assert_eq!(a.len(), b.len());
+        a.iter().zip(b).for_each(|(a, b)| {
+            assert!((a.lat - b.lat).abs() < epsilon && (a.lon - b.lon).abs() < epsilon);
+        });
+    }
+
+    #[test]
+    fn test_encode_polyline() {
+        assert_eq!(encode_polyline(&google_coords(), 5), GOOGLE_EXAMPLE);
+        assert_eq!(encode_polyline(&[], 5), "");
+    }
+
+    #[test]
+    fn test_decode_polyline() {
+        let decoded = decode_polyline(GOOGLE_EXAMPLE, 5).unwrap();
+        assert_close(&decoded, &google_coords(), 1e-9);
+        assert_eq!(decode_polyline("", 5), Ok(vec![]));
+        assert_eq!(decode_polyline("_p~iF", 5), Err(DecodeError::Truncated));
+        assert_eq!(decode_polyline("_p~i", 5), Err(DecodeError::Truncated));
+        assert_eq!(
+            decode_polyline("_p~iF ", 5),
+            Err(DecodeError::InvalidCharacter(5))
+        );
+    }
+
+    #[test]
+    fn test_polyline_precision_6_round_trip() {
+        let cambridge = vec![
+            Coord::new(52.135072, 0.129808),
+            Coord::new(52.135136, 0.129777),
+            Coord::new(52.1352, 0.129749),
+        ];
+        let encoded = encode_polyline(&cambridge, 6);
+        assert_close(&decode_polyline(&encoded, 6).unwrap(), &cambridge, 1e-9);
+        // Precision 5 rounds to about a metre
+        let encoded = encode_polyline(&cambridge, 5);
+        assert_close(&decode_polyline(&encoded, 5).unwrap(), &cambridge, 1e-5);
+    }
+
+    #[test]
+    fn test_binary_round_trip() {
+        let encoded = encode_binary(&google_coords(), 6);
+        assert_eq!(encoded[0], 6);
+        assert!(encoded.len() < GOOGLE_EXAMPLE.len() + 4);
+        assert_close(&decode_binary(&encoded).unwrap(), &google_coords(), 1e-9);
+        assert_eq!(decode_binary(&[5]), Ok(vec![]));
+        assert_eq!(decode_binary(&[]), Err(DecodeError::Truncated));
+        assert_eq!(decode_binary(&[12]), Err(DecodeError::InvalidPrecision(12)));
+        assert_eq!(decode_binary(&[5, 0x80]), Err(DecodeError::Truncated));
+        assert_eq!(decode_binary(&[5, 0x02]), Err(DecodeError::Truncated));
+    }
+
+    #[test]
+    fn test_decode_overflow() {
+        // Twelve full chunks leave room for four bits in the thirteenth.
+        let fits = format!("{}N", "~".repeat(12));
+        assert_eq!(decode_polyline(&fits, 5), Err(DecodeError::Truncated));
+        let too_long = format!("{}^", "~".repeat(12));
+        assert_eq!(decode_polyline(&too_long, 5), Err(DecodeError::Overflow));
+
+        // i64::MAX as a zigzag varint, whose last byte has room for one bit.
+        let max = [0xfe, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0x01];
+        let mut bytes = vec![5];
+        bytes.extend(max);
+        bytes.push(0);
+        assert_eq!(decode_binary(&bytes).unwrap().len(), 1);
+        // A second step of the same size runs past the largest coordinate.
+        bytes.extend(max);
+        bytes.push(0);
+        assert_eq!(decode_binary(&bytes), Err(DecodeError::Overflow));
+
+        let mut too_long = max;
+        too_long[9] = 0x02;
+        let mut bytes = vec![5];
+        bytes.extend(too_long);
+        assert_eq!(decode_binary(&bytes), Err(DecodeError::Overflow));
+    }
+}
@@ -0,0 +1,282 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use super::{BBox, Coord};
+
+/// Children per node. Sixteen keeps the tree shallow without making leaf scans slow.
+const NODE_SIZE: usize = 16;
+
+/// Static packed R-tree over points, for nearest-neighbour and radius queries.
+///
+/// Items are sorted along a Hilbert curve and grouped `NODE_SIZE` at a time at every level,
+/// as in the Flatbush library, so the tree is a handful of flat `Vec`s with no pointers.
+#[derive(Debug)]
+pub struct SpatialIndex<T> {
+    items: Vec<(Coord, T)>,
+    /// Bounding boxes per level, leaves first. Node `i` of level `l` covers
+    /// children `i * NODE_SIZE..(i + 1) * NODE_SIZE` of level `l - 1`, or items for level 0.
+    levels: Vec<Vec<BBox>>,
+}
+
+impl<T> SpatialIndex<T> {
+    pub fn new(mut items: Vec<(Coord, T)>) -> Self {
+        let mut levels = Vec::new();
+        if let Some(extent) = BBox::from_coords(items.iter().map(|(c, _)| *c)) {
+            items.sort_by_cached_key(|(c, _)| hilbert_index(c, &extent));
+            let mut boxes: Vec<BBox> = items
+                .chunks(NODE_SIZE)
+                .map(|chunk| BBox::from_coords(chunk.iter().map(|(c, _)| *c)).unwrap())
+                .collect();
+            while boxes.len() > 1 {
+                let parents = boxes
+                    .chunks(NODE_SIZE)
+                    .map(|chunk| chunk.iter().skip(1).fold(chunk[0], |acc, b| acc.union(b)))
+                    .collect();
+                levels.push(boxes);
+                boxes = parents;
+            }
+            levels.push(boxes);
+        }
+        SpatialIndex { items, levels }
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    /// Up to `k` items nearest to `pos`, closest first, with their distances in metres.
+    pub fn nearest(&self, pos: &Coord, k: usize) -> Vec<(f64, &T)> {
+        let mut found = Vec::with_capacity(k);
+        let Some(root_level) = self.levels.len().checked_sub(1) else {
+            return found;
+        };
+        if k == 0 {
+            return found;
+        }
+        // Best-first search: pop whichever node or item is closest. When an item comes off
+        // the queue nothing left can be closer, so it is the next nearest.
+        let mut queue = BinaryHeap::new();
+        queue.push(Entry {
+            distance: 0.0,
+            node: Node::Tree(root_level, 0),
+        });
+        while let Some(Entry { distance, node }) = queue.pop() {
+            match node {
+                Node::Item(i) => {
+                    found.push((distance, &self.items[i].1));
+                    if found.len() == k {
+                        break;
+                    }
+                }
+                Node::Tree(level, index) => self.children(level, index).for_each(|child| {
+                    let distance = match child {
+                        Node::Item(i) => pos.haversine_distance(&self.items[i].0),
+                        Node::Tree(l, i) => min_distance(pos, &self.levels[l][i]),
+                    };
+                    queue.push(Entry {
+                        distance,
+                        node: child,
+                    });
+                }),
+            }
+        }
+        found
+    }
+
+    /// All items within `radius` metres of `pos`, closest first, with their distances.
+    pub fn within_radius(&self, pos: &Coord, radius: f64) -> Vec<(f64, &T)> {
+        let mut found = Vec::new();
+        let Some(root_level) = self.levels.len().checked_sub(1) else {
+            return found;
+        };
+        let mut stack = vec![Node::Tree(root_level, 0)];
+        while let Some(node) = stack.pop() {
+            match node {
+                Node::Item(i) => {
+                    let distance = pos.haversine_distance(&self.items[i].0);
+                    if distance <= radius {
+                        found.push((distance, &self.items[i].1));
+                    }
+                }
+                Node::Tree(level, index) => {
+                    if min_distance(pos, &self.levels[level][index]) <= radius {
+                        stack.extend(self.children(level, index));
+                    }
+                }
+            }
+        }
+        found.sort_by(|a, b| a.0.total_cmp(&b.0));
+        found
+    }
+
+    fn children(&self, level: usize, index: usize) -> impl Iterator<Item = Node> {
+        let count = if level == 0 {
+            self.items.len()
+        } else {
+            self.levels[level - 1].len()
+        };
+        let range = index * NODE_SIZE..((index + 1) * NODE_SIZE).min(count);
+        range.map(move |i| {
+            if level == 0 {
+                Node::Item(i)
+            } else {
+                Node::Tree(level - 1, i)
+            }
+        })
+    }
+}
+
+#[derive(Clone, Copy)]
+enum Node {
+    Tree(usize, usize),
+    Item(usize),
+}
+
+struct Entry {
+    distance: f64,
+    node: Node,
+}
+
+impl PartialEq for Entry {
+    fn eq(&self, other: &Self) -> bool {
+        self.distance == other.distance
+    }
+}
+
+impl Eq for Entry {}
+
+impl Ord for Entry {
+    // Reversed so that BinaryHeap pops the closest entry first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other.distance.total_cmp(&self.distance)
+    }
+}
+
+impl PartialOrd for Entry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Distance in metres from `pos` to the nearest point of `bbox`, or zero if it is inside.
+fn min_distance(pos: &Coord, bbox: &BBox) -> f64 {
+    let nearest = Coord::new(
+        pos.lat.clamp(bbox.south, bbox.north),
+        pos.lon.clamp(bbox.west, bbox.east),
+    );
+    pos.haversine_distance(&nearest)
+}
+
+/// Position of `coord` along a Hilbert curve filling `extent`, so that nearby points sort together.
+fn hilbert_index(coord: &Coord, extent: &BBox) -> u64 {
+    const ORDER: u32 = 16;
+    let side = ((1u32 << ORDER) - 1) as f64;
+    let scale = |v: f64, min: f64, max: f64| {
+        if max > min {
+            ((v - min) / (max - min) * side) as u32
+        } else {
+            0
+        }
+    };
+    let mut x = scale(coord.lon, extent.west, extent.east);
+    let mut y = scale(coord.lat, extent.south, extent.north);
+    let mut d: u64 = 0;
+    let mut s = 1u32 << (ORDER - 1);
+    while s > 0 {
+        let rx = u32::from(x & s > 0);
+        let ry = u32::from(y & s > 0);
+        d += s as u64 * s as u64 * ((3 * rx) ^ ry) as u64;
+        // Rotate the quadrant so the curve stays continuous.
+        if ry == 0 {
+            if rx == 1 {
+                x = s.wrapping_mul(2).wrapping_sub(1).wrapping_sub(x) & ((1 << ORDER) - 1);
+                y = s.wrapping_mul(2).wrapping_sub(1).wrapping_sub(y) & ((1 << ORDER) - 1);
+            }
+            std::mem::swap(&mut x, &mut y);
+        }
+        s >>= 1;
+    }
+    d
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Points on a 0.001 degree grid around Cambridge, labelled by their position.
+    fn grid() -> Vec<(Coord, (usize, usize))> {
+        (0..50)
+            .flat_map(|i| {
+                (0..50).map(move |j| {
+                    (
+                        Coord::new(52.2 + i as f64 * 0.001, 0.1 + j as f64 * 0.001),
+                        (i, j),
+                    )
+                })
+            })
+            .collect()
+    }
+
+    fn brute_force(items: &[(Coord, (usize, usize))], pos: &Coord) -> Vec<(f64, (usize, usize))> {
+        let mut all: Vec<_> = items
+            .iter()
+            .map(|(c, id)| (pos.haversine_distance(c), *id))
+            .collect();
+        all.sort_by(|a, b| a.0.total_cmp(&b.0));
+        all
+    }
+
+    #[test]
+    fn test_nearest() {
+        let items = grid();
+        let index = SpatialIndex::new(items.clone());
+        assert_eq!(index.len(), 2500);
+        let pos = Coord::new(52.2213, 0.1371);
+        let nearest = index.nearest(&pos, 5);
+        let expected = brute_force(&items, &pos);
+        assert_eq!(nearest.len(), 5);
+        nearest.iter().zip(&expected).for_each(|(found, expected)| {
+            assert!((found.0 - expected.0).abs() < 1e-9);
+        });
+        assert_eq!(*nearest[0].1, (21, 37));
+
+        // Far outside the data, the nearest is the closest corner.
+        let outside = index.nearest(&Coord::new(51.0, -1.0), 1);
+        assert_eq!(*outside[0].1, (0, 0));
+        assert_eq!(index.nearest(&pos, 5000).len(), 2500);
+        assert!(index.nearest(&pos, 0).is_empty());
+    }
+
+    #[test]
+    fn test_within_radius() {
+        let items = grid();
+        let index = SpatialIndex::new(items.clone());
+        let pos = Coord::new(52.2213, 0.1371);
+        let found = index.within_radius(&pos, 250.0);
+        let expected: Vec<_> = brute_force(&items, &pos)
+            .into_iter()
+            .take_while(|(d, _)| *d <= 250.0)
+            .collect();
+        assert_eq!(found.len(), expected.len());
+        assert!(found.windows(2).all(|pair| pair[0].0 <= pair[1].0));
+        assert!(index
+            .within_radius(&Coord::new(51.0, -1.0), 1000.0)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_empty_and_single() {
+        let empty: SpatialIndex<()> = SpatialIndex::new(vec![]);
+        assert!(empty.is_empty());
+        assert!(empty.nearest(&Coord::new(52.0, 0.0), 3).is_empty());
+        assert!(empty
+            .within_radius(&Coord::new(52.0, 0.0), 100.0)
+            .is_empty());
+        let single = SpatialIndex::new(vec![(Coord::new(52.0, 0.0), "home")]);
+        assert_eq!(single.nearest(&Coord::new(52.1, 0.0), 3)[0].1, &"home");
+    }
+}
@@ -0,0 +1,169 @@
+use super::transverse_mercator::{TransverseMercator, AIRY_1830, WGS84, WGS84_TO_OSGB36};
+use super::{Coord, GridRefError};
+
+/// Ordnance Survey National Grid projection of OSGB36 coordinates.
+const NATIONAL_GRID: TransverseMercator = TransverseMercator {
+    ellipsoid: AIRY_1830,
+    f0: 0.999_601_271_7,
+    lat0: 49.0,
+    lon0: -2.0,
+    e0: 400_000.0,
+    n0: -100_000.0,
+};
+
+impl Coord {
+    /// Easting and northing in metres on the OS National Grid, after shifting from WGS84 to OSGB36.
+    pub fn to_os_grid(&self) -> (f64, f64) {
+        let (lat, lon) = WGS84_TO_OSGB36.convert(self.lat, self.lon, WGS84, AIRY_1830);
+        NATIONAL_GRID.forward(lat, lon)
+    }
+
+    /// WGS84 position of a National Grid easting and northing.
+    pub fn from_os_grid(easting: f64, northing: f64) -> Coord {
+        let (lat, lon) = NATIONAL_GRID.inverse(easting, northing);
+        let (lat, lon) = WGS84_TO_OSGB36
+            .inverse()
+            .convert(lat, lon, AIRY_1830, WGS84);
+        Coord { lat, lon }
+    }
+
+    /// National Grid reference such as `TL 44795 58425`, with `digits` in total (2 to 10, even).
+    pub fn to_os_grid_ref(&self, digits: usize) -> Result<String, GridRefError> {
+        let (easting, northing) = self.to_os_grid();
+        let (e100k, n100k) = (
+            (easting / 100_000.0).floor() as i64,
+            (northing / 100_000.0).floor() as i64,
+        );
+        if !(0..7).contains(&e100k) || !(0..13).contains(&n100k) {
+            return Err(GridRefError::OutOfRange);
+        }
+        // First letter picks the 500 km square, second the 100 km square within it.
+        let l1 = (19 - n100k) - (19 - n100k) % 5 + (e100k + 10) / 5;
+        let l2 = (19 - n100k) * 5 % 25 + e100k % 5;
+        let letter = |l: i64| char::from(b'A' + if l > 7 { l + 1 } else { l } as u8);
+
+        let half = (digits.clamp(2, 10) / 2) as u32;
+        let divisor = 10f64.powi(5 - half as i32);
+        let e = ((easting % 100_000.0) / divisor).floor() as u32;
+        let n = ((northing % 100_000.0) / divisor).floor() as u32;
+        Ok(format!(
+            "{}{} {:0w$} {:0w$}",
+            letter(l1),
+            letter(l2),
+            e,
+            n,
+            w = half as usize
+        ))
+    }
+
+    /// South-west corner of the square named by a National Grid reference like `TL4479558425`.
+    pub fn from_os_grid_ref(grid_ref: &str) -> Result<Coord, GridRefError> {
+        let invalid = || GridRefError::Invalid(grid_ref.to_string());
+        let cleaned: String = grid_ref
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_uppercase();
+        let mut chars = cleaned.chars();
+        let index = |c: Option<char>| match c {
+            Some(c @ 'A'..='H') => Ok(c as i64 - 'A' as i64),
+            Some(c @ 'J'..='Z') => Ok(c as i64 - 'A' as i64 - 1),
+            _ => Err(invalid()),
+        };
+        let (l1, l2) = (index(chars.next())?, index(chars.next())?);
+        let e100k = (l1 - 2).rem_euclid(5) * 5 + l2 % 5;
+        let n100k = (19 - (l1 / 5) * 5) - l2 / 5;
+        if !(0..7).contains(&e100k) || !(0..13).contains(&n100k) {
+            return Err(GridRefError::OutOfRange);
+        }
+
+        let numbers = chars.as_str();
+        if !numbers.len().is_multiple_of(2)
+            || numbers.len() > 10
+            || !numbers.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let (e, n) = numbers.split_at(numbers.len() / 2);
+        let metres = |digits: &str| {
+            // Pad on the right, so a short reference names the corner of its square.
+            format!("{:0<5}", digits).parse::<f64>().unwrap_or_default()
+        };
+        Ok(Coord::from_os_grid(
+            e100k as f64 * 100_000.0 + metres(e),
+            n100k as f64 * 100_000.0 + metres(n),
+        ))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_national_grid_projection() {
+        // Worked example from the Ordnance Survey guide, on OSGB36
+        let lat = 52.0 + 39.0 / 60.0 + 27.2531 / 3600.0;
+        let lon = 1.0 + 43.0 / 60.0 + 4.5177 / 3600.0;
+        let (e, n) = NATIONAL_GRID.forward(lat, lon);
+        assert!((e - 651_409.903).abs() < 0.001, "Testing easting {}", e);
+        assert!((n - 313_177.270).abs() < 0.001, "Testing northing {}", n);
+        let (lat2, lon2) = NATIONAL_GRID.inverse(e, n);
+        assert!((lat2 - lat).abs() < 1e-8 && (lon2 - lon).abs() < 1e-8);
+    }
+
+    #[test]
+    fn test_os_grid_ref_round_trip() {
+        // Start of the Where was the rain? ride, south of Cambridge
+        let start = Coord::new(52.135072, 0.129808);
+        let grid_ref = start.to_os_grid_ref(10).unwrap();
+        assert!(
+            grid_ref.starts_with("TL "),
+            "Testing grid square {}",
+            grid_ref
+        );
+        let back = Coord::from_os_grid_ref(&grid_ref).unwrap();
+        assert!(start.haversine_distance(&back) < 2.0);
+
+        // Datum shift moves positions by around a hundred metres, not kilometres
+        let (e, n) = start.to_os_grid();
+        let (lat, lon) = NATIONAL_GRID.inverse(e, n);
+        let shift = start.haversine_distance(&Coord::new(lat, lon));
+        assert!(
+            (50.0..200.0).contains(&shift),
+            "Testing datum shift {}",
+            shift
+        );
+    }
+
+    #[test]
+    fn test_os_grid_ref_format() {
+        let coord = Coord::from_os_grid(651_409.903, 313_177.270);
+        assert_eq!(coord.to_os_grid_ref(10).unwrap(), "TG 51409 13177");
+        assert_eq!(coord.to_os_grid_ref(6).unwrap(), "TG 514 131");
+        assert_eq!(coord.to_os_grid_ref(0).unwrap(), "TG 5 1");
+        let corner = Coord::from_os_grid_ref("tg 514 131").unwrap();
+        let (e, n) = corner.to_os_grid();
+        assert!((e - 651_400.0).abs() < 0.01 && (n - 313_100.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_os_grid_ref_errors() {
+        assert_eq!(
+            Coord::new(48.85, 2.35).to_os_grid_ref(10),
+            Err(GridRefError::OutOfRange)
+        );
+        assert!(matches!(
+            Coord::from_os_grid_ref("TL 123 4567"),
+            Err(GridRefError::Invalid(_))
+        ));
+        assert!(matches!(
+            Coord::from_os_grid_ref("IL 123 456"),
+            Err(GridRefError::Invalid(_))
+        ));
+        assert_eq!(
+            Coord::from_os_grid_ref("AA 123 456"),
+            Err(GridRefError::OutOfRange)
+        );
+    }
+}
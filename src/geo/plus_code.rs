@@ -0,0 +1,99 @@
+use super::{Coord, GridRefError};
+
+const ALPHABET: &[u8] = b"23456789CFGHJMPQRVWX";
+const SEPARATOR: char = '+';
+/// Digits in a full code, giving a cell of 1/8000 degree (about 14 m).
+const CODE_LENGTH: usize = 10;
+/// Cells per degree at full code length.
+const CELLS_PER_DEGREE: f64 = 8000.0;
+
+impl Coord {
+    /// Full ten-digit Open Location Code (Plus Code) such as `9F42442H+3P`.
+    pub fn to_plus_code(&self) -> String {
+        let max_lat = (180.0 * CELLS_PER_DEGREE) as i64 - 1;
+        let mut lat = (((self.lat + 90.0) * CELLS_PER_DEGREE).floor() as i64).clamp(0, max_lat);
+        let mut lon = (((self.lon + 180.0).rem_euclid(360.0)) * CELLS_PER_DEGREE).floor() as i64;
+
+        // Digits are produced least significant pair first, then reversed.
+        let mut digits = Vec::with_capacity(CODE_LENGTH);
+        for _ in 0..CODE_LENGTH / 2 {
+            digits.push(ALPHABET[(lon % 20) as usize]);
+            digits.push(ALPHABET[(lat % 20) as usize]);
+            lat /= 20;
+            lon /= 20;
+        }
+        digits.reverse();
+        let mut code: String = digits.into_iter().map(char::from).collect();
+        code.insert(8, SEPARATOR);
+        code
+    }
+
+    /// Centre of the area named by a full Plus Code. Short codes need a reference location
+    /// to recover and are rejected.
+    pub fn from_plus_code(code: &str) -> Result<Coord, GridRefError> {
+        let invalid = || GridRefError::Invalid(code.to_string());
+        let upper = code.trim().to_uppercase();
+        if upper.find(SEPARATOR) != Some(8) {
+            return Err(invalid());
+        }
+        let digits: Vec<usize> = upper
+            .chars()
+            .filter(|&c| c != SEPARATOR)
+            .map(|c| {
+                u8::try_from(c)
+                    .ok()
+                    .and_then(|b| ALPHABET.iter().position(|&a| a == b))
+                    .ok_or_else(invalid)
+            })
+            .collect::<Result<_, _>>()?;
+        if digits.len() < 8 || digits.len() > CODE_LENGTH || !digits.len().is_multiple_of(2) {
+            return Err(invalid());
+        }
+
+        let (mut lat, mut lon, mut resolution) = (0.0, 0.0, 20.0);
+        digits.chunks(2).for_each(|pair| {
+            lat += pair[0] as f64 * resolution;
+            lon += pair[1] as f64 * resolution;
+            resolution /= 20.0;
+        });
+        // The last pair used `resolution * 20`, so half of that is the middle of the cell.
+        let half_cell = resolution * 10.0;
+        if lat >= 180.0 || lon >= 360.0 {
+            return Err(GridRefError::OutOfRange);
+        }
+        Ok(Coord {
+            lat: lat - 90.0 + half_cell,
+            lon: lon - 180.0 + half_cell,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_to_plus_code() {
+        // Example from the Open Location Code documentation, in Zurich
+        assert_eq!(
+            Coord::new(47.365590, 8.524997).to_plus_code(),
+            "8FVC9G8F+6X"
+        );
+        assert_eq!(Coord::new(90.0, 180.0).to_plus_code().len(), 11);
+    }
+
+    #[test]
+    fn test_from_plus_code() {
+        let zurich = Coord::from_plus_code("8fvc9g8f+6x").unwrap();
+        assert!((zurich.lat - 47.3655625).abs() < 1e-9);
+        assert!((zurich.lon - 8.5249375).abs() < 1e-9);
+
+        let cambridge = Coord::new(52.2053, 0.1218);
+        let back = Coord::from_plus_code(&cambridge.to_plus_code()).unwrap();
+        assert!(cambridge.haversine_distance(&back) < 10.0);
+
+        assert!(Coord::from_plus_code("9G8F+6X").is_err(), "Short codes");
+        assert!(Coord::from_plus_code("8FVC9G8F+6A").is_err());
+        assert!(Coord::from_plus_code("8FVC9G8F6X").is_err());
+    }
+}
@@ -0,0 +1,158 @@
+use super::{cumulative_distances, Coord, EARTH_RADIUS};
+
+/// How far either side of a previous match, in metres along the track, to look for the next one.
+/// Keeps a position on a looping or self-overlapping track matched to the right lap.
+const HINT_WINDOW: f64 = 500.0;
+
+/// Result of projecting a position onto a polyline.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LineMatch {
+    /// Nearest point on the polyline.
+    pub point: Coord,
+    /// Index of the segment `line[segment]..line[segment + 1]` the point falls on.
+    pub segment: usize,
+    /// Distance in metres from the position to `point`.
+    pub cross_track: f64,
+    /// Distance in metres along the polyline from its start to `point`.
+    pub along_track: f64,
+}
+
+/// Find the point on `line` nearest to `pos`.
+///
+/// Pass the previous match as `hint` when tracking a moving position, so that only the part of
+/// the line around it is searched. If nothing within the hint window is close to `pos`, the
+/// whole line is searched again. Returns `None` if `line` is empty.
+pub fn project_onto_line(
+    pos: &Coord,
+    line: &[Coord],
+    hint: Option<&LineMatch>,
+) -> Option<LineMatch> {
+    if line.is_empty() {
+        return None;
+    }
+    if line.len() == 1 {
+        return Some(LineMatch {
+            point: line[0],
+            segment: 0,
+            cross_track: pos.haversine_distance(&line[0]),
+            along_track: 0.0,
+        });
+    }
+    let distances = cumulative_distances(line);
+    let all_segments = 0..line.len() - 1;
+
+    if let Some(hint) = hint {
+        let window = all_segments.clone().filter(|&i| {
+            distances[i + 1] >= hint.along_track - HINT_WINDOW
+                && distances[i] <= hint.along_track + HINT_WINDOW
+        });
+        if let Some(best) = nearest_on_segments(pos, line, &distances, window) {
+            if best.cross_track <= HINT_WINDOW {
+                return Some(best);
+            }
+        }
+    }
+    nearest_on_segments(pos, line, &distances, all_segments)
+}
+
+fn nearest_on_segments(
+    pos: &Coord,
+    line: &[Coord],
+    distances: &[f64],
+    segments: impl Iterator<Item = usize>,
+) -> Option<LineMatch> {
+    segments
+        .map(|i| project_onto_segment(pos, line, distances, i))
+        .min_by(|a, b| a.cross_track.total_cmp(&b.cross_track))
+}
+
+fn project_onto_segment(pos: &Coord, line: &[Coord], distances: &[f64], i: usize) -> LineMatch {
+    let (a, b) = (line[i], line[i + 1]);
+    // Equirectangular projection centred on `pos`, accurate enough over a single segment.
+    let to_local = |c: Coord| {
+        (
+            (c.lon - pos.lon).to_radians() * pos.lat.to_radians().cos() * EARTH_RADIUS,
+            (c.lat - pos.lat).to_radians() * EARTH_RADIUS,
+        )
+    };
+    let (ax, ay) = to_local(a);
+    let (bx, by) = to_local(b);
+    let (dx, dy) = (bx - ax, by - ay);
+    let len_sq = dx * dx + dy * dy;
+    let t = if len_sq > 0.0 {
+        ((-ax * dx - ay * dy) / len_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    let point = Coord::new(a.lat + t * (b.lat - a.lat), a.lon + t * (b.lon - a.lon));
+    LineMatch {
+        point,
+        segment: i,
+        cross_track: pos.haversine_distance(&point),
+        along_track: distances[i] + a.haversine_distance(&point),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_onto_straight_line() {
+        let line = [
+            Coord::new(52.0, 0.1),
+            Coord::new(52.01, 0.1),
+            Coord::new(52.02, 0.1),
+        ];
+        let pos = Coord::new(52.015, 0.1).destination(90.0, 30.0);
+        let m = project_onto_line(&pos, &line, None).unwrap();
+        assert_eq!(m.segment, 1);
+        assert!((m.cross_track - 30.0).abs() < 0.1);
+        assert!((m.point.lat - 52.015).abs() < 1e-6);
+        let expected = line[0].haversine_distance(&Coord::new(52.015, 0.1));
+        assert!((m.along_track - expected).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_project_beyond_ends() {
+        let line = [Coord::new(52.0, 0.1), Coord::new(52.01, 0.1)];
+        let before = project_onto_line(&Coord::new(51.99, 0.1), &line, None).unwrap();
+        assert_eq!(before.point, line[0]);
+        assert_eq!(before.along_track, 0.0);
+        let after = project_onto_line(&Coord::new(52.02, 0.1), &line, None).unwrap();
+        assert_eq!(after.point, line[1]);
+        assert!(project_onto_line(&line[0], &[], None).is_none());
+        let single = project_onto_line(&line[1], &line[..1], None).unwrap();
+        assert_eq!(single.segment, 0);
+    }
+
+    #[test]
+    fn test_project_with_hint_on_out_and_back() {
+        // Out along a road and back along the same road, slightly offset.
+        let out = [Coord::new(52.0, 0.1), Coord::new(52.02, 0.1)];
+        let back = [Coord::new(52.02, 0.10001), Coord::new(52.0, 0.10001)];
+        let line: Vec<Coord> = out.iter().chain(back.iter()).copied().collect();
+        let length = crate::geo::path_length(&line);
+
+        let pos = Coord::new(52.001, 0.100005);
+        // Without a hint the first pass is as good as any.
+        let first = project_onto_line(&pos, &line, None).unwrap();
+        assert!(first.along_track < length / 2.0);
+
+        // Near the end of the ride, the hint keeps the match on the return leg.
+        let hint = project_onto_line(&Coord::new(52.002, 0.10001), &line[2..], None)
+            .map(|m| LineMatch {
+                segment: m.segment + 2,
+                along_track: m.along_track + crate::geo::path_length(&line[..3]),
+                ..m
+            })
+            .unwrap();
+        let second = project_onto_line(&pos, &line, Some(&hint)).unwrap();
+        assert_eq!(second.segment, 2);
+        assert!(second.along_track > length / 2.0);
+
+        // A hint far from the current position falls back to a full search.
+        let lost = project_onto_line(&Coord::new(52.0, 0.0), &line, Some(&hint)).unwrap();
+        assert_eq!(lost.segment, 0);
+    }
+}
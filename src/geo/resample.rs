@@ -0,0 +1,245 @@
+use time::{Duration, OffsetDateTime};
+
+use super::{cumulative_distances, Coord};
+use crate::track::Segment;
+
+/// Position, elevation and time at some point along a segment, interpolated between track points.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SegmentSample {
+    pub coord: Coord,
+    pub elevation: Option<f64>,
+    pub time: Option<OffsetDateTime>,
+    /// Distance in metres along the segment from its first point.
+    pub distance: f64,
+}
+
+/// Track points of `segment` as samples, with their cumulative distances.
+fn track_samples(segment: &Segment) -> Vec<SegmentSample> {
+    cumulative_distances(&segment.coords())
+        .into_iter()
+        .zip(&segment.points)
+        .map(|(distance, point)| SegmentSample {
+            coord: point.coord,
+            elevation: point.elevation,
+            time: point.time,
+            distance,
+        })
+        .collect()
+}
+
+/// Linear interpolation between `a` and `b`, with `t` from 0 at `a` to 1 at `b`.
+/// Elevation and time are only interpolated when both ends have them.
+fn interpolate(a: &SegmentSample, b: &SegmentSample, t: f64) -> SegmentSample {
+    let lerp = |x: f64, y: f64| x + t * (y - x);
+    SegmentSample {
+        coord: Coord::new(
+            lerp(a.coord.lat, b.coord.lat),
+            lerp(a.coord.lon, b.coord.lon),
+        ),
+        elevation: a.elevation.zip(b.elevation).map(|(x, y)| lerp(x, y)),
+        time: a.time.zip(b.time).map(|(x, y)| x + (y - x) * t),
+        distance: lerp(a.distance, b.distance),
+    }
+}
+
+/// Sample at `distance` metres along `samples`, which must be in order of distance.
+fn sample_at_distance(samples: &[SegmentSample], distance: f64) -> Option<SegmentSample> {
+    let last = samples.last()?;
+    if !(0.0..=last.distance).contains(&distance) {
+        return None;
+    }
+    let i = samples
+        .partition_point(|s| s.distance <= distance)
+        .clamp(1, samples.len().max(2) - 1);
+    if samples.len() == 1 {
+        return Some(samples[0]);
+    }
+    let (a, b) = (&samples[i - 1], &samples[i]);
+    let span = b.distance - a.distance;
+    let t = if span > 0.0 {
+        (distance - a.distance) / span
+    } else {
+        0.0
+    };
+    Some(interpolate(a, b, t))
+}
+
+/// Interpolated position `distance` metres along `segment`, or `None` beyond either end.
+pub fn point_at_distance(segment: &Segment, distance: f64) -> Option<SegmentSample> {
+    sample_at_distance(&track_samples(segment), distance)
+}
+
+/// Track points of `segment` that have a time, as samples.
+fn timed_samples(segment: &Segment) -> Vec<SegmentSample> {
+    track_samples(segment)
+        .into_iter()
+        .filter(|s| s.time.is_some())
+        .collect()
+}
+
+/// Sample at `time` between `a` and `b`, which both have times.
+fn sample_between(a: &SegmentSample, b: &SegmentSample, time: OffsetDateTime) -> SegmentSample {
+    let (a_time, b_time) = (a.time.unwrap(), b.time.unwrap());
+    let span = (b_time - a_time).as_seconds_f64();
+    let t = if span > 0.0 {
+        (time - a_time).as_seconds_f64() / span
+    } else {
+        0.0
+    };
+    interpolate(a, b, t)
+}
+
+/// Interpolated position at `time`, or `None` outside the recorded times.
+/// Points without a timestamp are ignored.
+pub fn point_at_time(segment: &Segment, time: OffsetDateTime) -> Option<SegmentSample> {
+    let timed = timed_samples(segment);
+    let (first, last) = (timed.first()?.time?, timed.last()?.time?);
+    if time < first || time > last {
+        return None;
+    }
+    if timed.len() == 1 {
+        return Some(timed[0]);
+    }
+    let i = timed
+        .partition_point(|s| s.time <= Some(time))
+        .clamp(1, timed.len() - 1);
+    Some(sample_between(&timed[i - 1], &timed[i], time))
+}
+
+/// Samples every `spacing` metres from the start of `segment`, plus its final point.
+pub fn resample_by_distance(segment: &Segment, spacing: f64) -> Vec<SegmentSample> {
+    let samples = track_samples(segment);
+    let Some(last) = samples.last().copied() else {
+        return vec![];
+    };
+    if spacing <= 0.0 {
+        return samples;
+    }
+    let steps = (last.distance / spacing).floor() as usize;
+    let mut resampled: Vec<SegmentSample> = (0..=steps)
+        .filter_map(|i| sample_at_distance(&samples, i as f64 * spacing))
+        .collect();
+    if resampled.last().map(|s| s.distance) != Some(last.distance) {
+        resampled.push(last);
+    }
+    resampled
+}
+
+/// Samples every `interval` from the first timestamp of `segment`, plus its last timed point.
+pub fn resample_by_time(segment: &Segment, interval: Duration) -> Vec<SegmentSample> {
+    let timed = timed_samples(segment);
+    let (Some(first), Some(last)) = (timed.first().copied(), timed.last().copied()) else {
+        return vec![];
+    };
+    if !interval.is_positive() {
+        return vec![];
+    }
+    if timed.len() == 1 {
+        return vec![first];
+    }
+    let (first_time, last_time) = (first.time.unwrap(), last.time.unwrap());
+    let mut resampled = Vec::new();
+    // Times only increase, so the pair of points around each one is found by walking forward
+    // from the last pair rather than searching the whole segment.
+    let mut i = 1;
+    let mut time = first_time;
+    while time <= last_time {
+        while i < timed.len() - 1 && timed[i].time <= Some(time) {
+            i += 1;
+        }
+        resampled.push(sample_between(&timed[i - 1], &timed[i], time));
+        time += interval;
+    }
+    if resampled.last().and_then(|s| s.time) != Some(last_time) {
+        resampled.push(last);
+    }
+    resampled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::TrackPoint;
+    use time::macros::datetime;
+
+    /// Three points 1 km apart heading north, one every four minutes, climbing 10 m each time.
+    fn segment() -> Segment {
+        let start = Coord::new(52.0, 0.1);
+        Segment {
+            points: (0..3)
+                .map(|i| TrackPoint {
+                    coord: start.destination(0.0, i as f64 * 1000.0),
+                    elevation: Some(10.0 * i as f64),
+                    time: Some(datetime!(2024-03-01 09:00 UTC) + Duration::minutes(4 * i)),
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_point_at_distance() {
+        let segment = segment();
+        let middle = point_at_distance(&segment, 1500.0).unwrap();
+        assert!((middle.elevation.unwrap() - 15.0).abs() < 0.01);
+        assert_eq!(middle.time.unwrap(), datetime!(2024-03-01 09:06 UTC));
+        let expected = Coord::new(52.0, 0.1).destination(0.0, 1500.0);
+        assert!(middle.coord.haversine_distance(&expected) < 0.5);
+
+        assert_eq!(
+            point_at_distance(&segment, 0.0).unwrap().elevation,
+            Some(0.0)
+        );
+        assert!(point_at_distance(&segment, -1.0).is_none());
+        assert!(point_at_distance(&segment, 2001.0).is_none());
+        assert!(point_at_distance(&Segment::default(), 0.0).is_none());
+    }
+
+    #[test]
+    fn test_point_at_time() {
+        let segment = segment();
+        let sample = point_at_time(&segment, datetime!(2024-03-01 09:02 UTC)).unwrap();
+        assert!((sample.distance - 500.0).abs() < 0.5);
+        assert!((sample.elevation.unwrap() - 5.0).abs() < 0.01);
+        assert!(point_at_time(&segment, datetime!(2024-03-01 08:59 UTC)).is_none());
+        assert!(point_at_time(&segment, datetime!(2024-03-01 09:09 UTC)).is_none());
+    }
+
+    #[test]
+    fn test_resample_by_distance() {
+        let resampled = resample_by_distance(&segment(), 300.0);
+        // 0, 300, ..., 1800 and the end at about 2000 m
+        assert_eq!(resampled.len(), 8);
+        resampled.windows(2).take(6).for_each(|pair| {
+            let gap = pair[0].coord.haversine_distance(&pair[1].coord);
+            assert!((gap - 300.0).abs() < 0.5, "Testing spacing {}", gap);
+        });
+        assert!((resampled[7].distance - 2000.0).abs() < 0.5);
+        assert!(resample_by_distance(&Segment::default(), 10.0).is_empty());
+    }
+
+    #[test]
+    fn test_resample_by_time() {
+        let resampled = resample_by_time(&segment(), Duration::minutes(3));
+        let times: Vec<_> = resampled.iter().map(|s| s.time.unwrap()).collect();
+        assert_eq!(
+            times,
+            vec![
+                datetime!(2024-03-01 09:00 UTC),
+                datetime!(2024-03-01 09:03 UTC),
+                datetime!(2024-03-01 09:06 UTC),
+                datetime!(2024-03-01 09:08 UTC),
+            ]
+        );
+        assert!(resample_by_time(&segment(), Duration::ZERO).is_empty());
+
+        // Untimed points are skipped, and the result matches sampling each time on its own.
+        let mut gappy = segment();
+        gappy.points[1].time = None;
+        let resampled = resample_by_time(&gappy, Duration::minutes(1));
+        assert_eq!(resampled.len(), 9);
+        resampled.iter().for_each(|sample| {
+            assert_eq!(Some(*sample), point_at_time(&gappy, sample.time.unwrap()));
+        });
+    }
+}
@@ -0,0 +1,259 @@
+use std::cmp::Ordering;
+use std::collections::BinaryHeap;
+
+use super::{Coord, EARTH_RADIUS};
+use crate::track::Track;
+
+/// Project `coords` onto a plane in metres, using an equirectangular projection about their mean latitude.
+fn to_local_xy(coords: &[Coord]) -> Vec<(f64, f64)> {
+    let mean_lat = coords.iter().map(|c| c.lat).sum::<f64>() / coords.len().max(1) as f64;
+    let cos_lat = mean_lat.to_radians().cos();
+    coords
+        .iter()
+        .map(|c| {
+            (
+                c.lon.to_radians() * cos_lat * EARTH_RADIUS,
+                c.lat.to_radians() * EARTH_RADIUS,
+            )
+        })
+        .collect()
+}
+
+/// Distance from `p` to the segment `a`..`b` in the plane.
+fn segment_distance(p: (f64, f64), a: (f64, f64), b: (f64, f64)) -> f64 {
+    let (dx, dy) = (b.0 - a.0, b.1 - a.1);
+    let len_sq = dx * dx + dy * dy;
+    let t = if len_sq > 0.0 {
+        (((p.0 - a.0) * dx + (p.1 - a.1) * dy) / len_sq).clamp(0.0, 1.0)
+    } else {
+        0.0
+    };
+    ((p.0 - a.0 - t * dx).powi(2) + (p.1 - a.1 - t * dy).powi(2)).sqrt()
+}
+
+fn triangle_area(a: (f64, f64), b: (f64, f64), c: (f64, f64)) -> f64 {
+    ((b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1)).abs() / 2.0
+}
+
+/// Indices of the points kept by Douglas–Peucker simplification, in order.
+/// No dropped point is further than `tolerance` metres from the simplified line.
+pub fn douglas_peucker(coords: &[Coord], tolerance: f64) -> Vec<usize> {
+    if coords.len() < 3 {
+        return (0..coords.len()).collect();
+    }
+    let xy = to_local_xy(coords);
+    let mut keep = vec![false; coords.len()];
+    keep[0] = true;
+    keep[coords.len() - 1] = true;
+    // Iterative rather than recursive so long tracks cannot overflow the wasm stack.
+    let mut stack = vec![(0, coords.len() - 1)];
+    while let Some((first, last)) = stack.pop() {
+        let farthest = (first + 1..last)
+            .map(|i| (i, segment_distance(xy[i], xy[first], xy[last])))
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        if let Some((i, distance)) = farthest {
+            if distance > tolerance {
+                keep[i] = true;
+                stack.push((first, i));
+                stack.push((i, last));
+            }
+        }
+    }
+    (0..coords.len()).filter(|&i| keep[i]).collect()
+}
+
+#[derive(PartialEq)]
+struct Candidate {
+    area: f64,
+    index: usize,
+}
+
+impl Eq for Candidate {}
+
+impl Ord for Candidate {
+    // Reversed so that BinaryHeap pops the smallest area first.
+    fn cmp(&self, other: &Self) -> Ordering {
+        other
+            .area
+            .total_cmp(&self.area)
+            .then(other.index.cmp(&self.index))
+    }
+}
+
+impl PartialOrd for Candidate {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// Effective area in square metres of every point under Visvalingam–Whyatt elimination.
+/// The endpoints are never eliminated and get `f64::INFINITY`.
+pub fn visvalingam_areas(coords: &[Coord]) -> Vec<f64> {
+    let n = coords.len();
+    let mut areas = vec![f64::INFINITY; n];
+    if n < 3 {
+        return areas;
+    }
+    let xy = to_local_xy(coords);
+    let mut prev: Vec<usize> = (0..n).map(|i| i.saturating_sub(1)).collect();
+    let mut next: Vec<usize> = (0..n).map(|i| (i + 1).min(n - 1)).collect();
+    let mut current: Vec<f64> = (0..n)
+        .map(|i| {
+            if i == 0 || i == n - 1 {
+                f64::INFINITY
+            } else {
+                triangle_area(xy[i - 1], xy[i], xy[i + 1])
+            }
+        })
+        .collect();
+    let mut heap: BinaryHeap<Candidate> = (1..n - 1)
+        .map(|index| Candidate {
+            area: current[index],
+            index,
+        })
+        .collect();
+
+    let mut last_area: f64 = 0.0;
+    while let Some(Candidate { area, index }) = heap.pop() {
+        // Skip stale heap entries left behind when a neighbour was re-scored.
+        if areas[index].is_finite() || area != current[index] {
+            continue;
+        }
+        // An eliminated point never counts as less significant than one eliminated before it.
+        last_area = last_area.max(area);
+        areas[index] = last_area;
+
+        let (p, q) = (prev[index], next[index]);
+        next[p] = q;
+        prev[q] = p;
+        for neighbour in [p, q] {
+            if neighbour != 0 && neighbour != n - 1 {
+                current[neighbour] =
+                    triangle_area(xy[prev[neighbour]], xy[neighbour], xy[next[neighbour]]);
+                heap.push(Candidate {
+                    area: current[neighbour],
+                    index: neighbour,
+                });
+            }
+        }
+    }
+    areas
+}
+
+/// Indices of the points kept by Visvalingam–Whyatt simplification, in order.
+/// Points whose effective area is below `min_area` square metres are dropped.
+pub fn visvalingam(coords: &[Coord], min_area: f64) -> Vec<usize> {
+    visvalingam_areas(coords)
+        .iter()
+        .enumerate()
+        .filter(|(_, &area)| area >= min_area)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Indices of at most `max_points` of the most significant points, in order.
+/// The endpoints are always kept, so at least two points are returned for a line.
+pub fn visvalingam_to_count(coords: &[Coord], max_points: usize) -> Vec<usize> {
+    let areas = visvalingam_areas(coords);
+    let mut ranked: Vec<usize> = (0..coords.len()).collect();
+    ranked.sort_by(|&a, &b| areas[b].total_cmp(&areas[a]));
+    ranked.truncate(max_points.max(2));
+    ranked.sort_unstable();
+    ranked
+}
+
+/// Copy of `track` with at most `max_points` track points over all of its segments, for GPS units
+/// that cap the number of course points. Elevation, time and other point data are kept.
+pub fn limit_track_points(track: &Track, max_points: usize) -> Track {
+    // Rank every point of every segment together, so the budget goes where the detail is.
+    let mut ranked: Vec<(f64, usize, usize)> = track
+        .segments
+        .iter()
+        .enumerate()
+        .flat_map(|(s, segment)| {
+            visvalingam_areas(&segment.coords())
+                .into_iter()
+                .enumerate()
+                .map(move |(i, area)| (area, s, i))
+        })
+        .collect();
+    ranked.sort_by(|a, b| b.0.total_cmp(&a.0));
+    ranked.truncate(max_points);
+    ranked.sort_by_key(|&(_, s, i)| (s, i));
+
+    let mut limited = track.clone();
+    limited
+        .segments
+        .iter_mut()
+        .for_each(|segment| segment.points.clear());
+    ranked.iter().for_each(|&(_, s, i)| {
+        limited.segments[s]
+            .points
+            .push(track.segments[s].points[i].clone())
+    });
+    limited
+        .segments
+        .retain(|segment| !segment.points.is_empty());
+    limited
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A zig-zag with small wobbles along a straight northward line.
+    fn wobbly_line() -> Vec<Coord> {
+        (0..=100)
+            .map(|i| {
+                let along = Coord::new(52.0, 0.1).destination(0.0, i as f64 * 10.0);
+                let wobble = if i % 2 == 0 { 1.0 } else { -1.0 };
+                along.destination(90.0, wobble)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_douglas_peucker() {
+        let line = wobbly_line();
+        assert_eq!(douglas_peucker(&line, 5.0), vec![0, 100]);
+        assert_eq!(douglas_peucker(&line, 0.1).len(), line.len());
+        assert_eq!(douglas_peucker(&line[..2], 100.0), vec![0, 1]);
+
+        // A right-angled corner survives a large tolerance.
+        let corner = [
+            Coord::new(52.0, 0.1),
+            Coord::new(52.01, 0.1),
+            Coord::new(52.01, 0.12),
+        ];
+        assert_eq!(douglas_peucker(&corner, 50.0), vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn test_visvalingam() {
+        let line = wobbly_line();
+        let areas = visvalingam_areas(&line);
+        assert!(areas[0].is_infinite() && areas[100].is_infinite());
+        assert!(areas[1..100].iter().all(|a| a.is_finite()));
+        assert_eq!(visvalingam(&line, 1_000.0), vec![0, 100]);
+        assert_eq!(visvalingam(&line, 0.0).len(), line.len());
+
+        let limited = visvalingam_to_count(&line, 10);
+        assert_eq!(limited.len(), 10);
+        assert_eq!((limited[0], limited[9]), (0, 100));
+        assert!(limited.windows(2).all(|pair| pair[0] < pair[1]));
+    }
+
+    #[test]
+    fn test_limit_track_points() {
+        let doc = crate::track::tests::sample_document();
+        let track = &doc.tracks[0];
+        let limited = limit_track_points(track, 200);
+        let count: usize = limited.segments.iter().map(|s| s.points.len()).sum();
+        assert_eq!(count, 200);
+        let original = &track.segments[0].points;
+        let kept = &limited.segments[0].points;
+        assert_eq!(kept.first(), original.first());
+        assert_eq!(kept.last(), original.last());
+        assert_eq!(limited.name, track.name);
+    }
+}
@@ -0,0 +1,206 @@
+//! Web Mercator ("slippy map") tile arithmetic, as used by OpenStreetMap and Leaflet.
+
+use std::collections::BTreeSet;
+use std::f64::consts::PI;
+use std::ops::RangeInclusive;
+
+use super::{BBox, Coord};
+
+/// Tile server used for the base map, in Leaflet's URL template syntax.
+pub const OSM_TILE_URL: &str = "https://{s}.tile.openstreetmap.org/{z}/{x}/{y}.png";
+/// Subdomains Leaflet substitutes for `{s}` by default.
+const SUBDOMAINS: [char; 3] = ['a', 'b', 'c'];
+/// Web Mercator cannot show the poles; latitudes are clamped to this.
+pub const MAX_LATITUDE: f64 = 85.051_128_779_806_59;
+/// Length of the equator on the WGS84 ellipsoid, in metres.
+const EQUATOR_LENGTH: f64 = 40_075_016.686;
+const TILE_SIZE: f64 = 256.0;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Tile {
+    pub z: u8,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl Tile {
+    /// Tile containing `coord` at zoom level `z`.
+    pub fn from_coord(coord: Coord, z: u8) -> Tile {
+        let n = 2f64.powi(z as i32);
+        let max = n as u32 - 1;
+        let lat = coord.lat.clamp(-MAX_LATITUDE, MAX_LATITUDE).to_radians();
+        let x = ((coord.lon + 180.0) / 360.0 * n).floor();
+        let y = ((1.0 - (lat.tan() + 1.0 / lat.cos()).ln() / PI) / 2.0 * n).floor();
+        Tile {
+            z,
+            x: (x.max(0.0) as u32).min(max),
+            y: (y.max(0.0) as u32).min(max),
+        }
+    }
+
+    /// North-west corner of the tile.
+    pub fn north_west(&self) -> Coord {
+        corner(self.x, self.y, self.z)
+    }
+
+    pub fn bbox(&self) -> BBox {
+        let south_east = corner(self.x + 1, self.y + 1, self.z);
+        let north_west = self.north_west();
+        BBox::new(
+            Coord::new(south_east.lat, north_west.lon),
+            Coord::new(north_west.lat, south_east.lon),
+        )
+    }
+
+    /// URL of the tile, filling in `{s}`, `{z}`, `{x}` and `{y}` in a Leaflet-style template.
+    pub fn url(&self, template: &str) -> String {
+        let subdomain = SUBDOMAINS[(self.x + self.y) as usize % SUBDOMAINS.len()];
+        template
+            .replace("{s}", &subdomain.to_string())
+            .replace("{z}", &self.z.to_string())
+            .replace("{x}", &self.x.to_string())
+            .replace("{y}", &self.y.to_string())
+    }
+}
+
+fn corner(x: u32, y: u32, z: u8) -> Coord {
+    let n = 2f64.powi(z as i32);
+    let lat = (PI * (1.0 - 2.0 * y as f64 / n)).sinh().atan();
+    Coord::new(lat.to_degrees(), x as f64 / n * 360.0 - 180.0)
+}
+
+/// Ground distance covered by one pixel of a 256 px tile at latitude `lat` and zoom level `zoom`.
+pub fn metres_per_pixel(lat: f64, zoom: f64) -> f64 {
+    EQUATOR_LENGTH * lat.to_radians().cos() / (TILE_SIZE * 2f64.powf(zoom))
+}
+
+/// All tiles at zoom level `z` that overlap `bbox`, row by row from the north-west.
+pub fn tiles_in_bbox(bbox: &BBox, z: u8) -> Vec<Tile> {
+    let north_west = Tile::from_coord(Coord::new(bbox.north, bbox.west), z);
+    let south_east = Tile::from_coord(Coord::new(bbox.south, bbox.east), z);
+    (north_west.y..=south_east.y)
+        .flat_map(|y| (north_west.x..=south_east.x).map(move |x| Tile { z, x, y }))
+        .collect()
+}
+
+/// Number of tiles needed to cover `bbox` at every zoom level in `zooms`, without listing them.
+pub fn count_tiles_in_bbox(bbox: &BBox, zooms: RangeInclusive<u8>) -> u64 {
+    zooms
+        .map(|z| {
+            let north_west = Tile::from_coord(Coord::new(bbox.north, bbox.west), z);
+            let south_east = Tile::from_coord(Coord::new(bbox.south, bbox.east), z);
+            (south_east.x - north_west.x + 1) as u64 * (south_east.y - north_west.y + 1) as u64
+        })
+        .sum()
+}
+
+/// Tiles at zoom level `z` within `buffer` metres of the polyline through `line`, in tile order.
+pub fn tiles_along_line(line: &[Coord], buffer: f64, z: u8) -> Vec<Tile> {
+    let mut tiles = BTreeSet::new();
+    let mut add = |coord: Coord| {
+        tiles.extend(tiles_in_bbox(&BBox::from_coord(coord).buffer(buffer), z));
+    };
+    if let Some(&first) = line.first() {
+        add(first);
+    }
+    // Sample each segment densely enough that neighbouring buffers overlap.
+    let tile_width = metres_per_pixel(0.0, z as f64) * TILE_SIZE;
+    let step = buffer.max(1.0).min(tile_width / 2.0);
+    line.windows(2).for_each(|pair| {
+        let (a, b) = (pair[0], pair[1]);
+        let samples = (a.haversine_distance(&b) / step).ceil().max(1.0) as usize;
+        (1..=samples).for_each(|i| {
+            let t = i as f64 / samples as f64;
+            add(Coord::new(
+                a.lat + t * (b.lat - a.lat),
+                a.lon + t * (b.lon - a.lon),
+            ));
+        });
+    });
+    tiles.into_iter().collect()
+}
+
+/// Number of tiles needed for a corridor of `buffer` metres around `line` at every zoom in `zooms`.
+pub fn count_tiles_along_line(line: &[Coord], buffer: f64, zooms: RangeInclusive<u8>) -> usize {
+    zooms.map(|z| tiles_along_line(line, buffer, z).len()).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_tile_from_coord() {
+        // Cambridge at zoom 13
+        let tile = Tile::from_coord(Coord::new(52.2053, 0.1218), 13);
+        assert_eq!((tile.x, tile.y), (4098, 2698));
+        assert_eq!(
+            Tile::from_coord(Coord::new(0.0, 0.0), 0),
+            Tile { z: 0, x: 0, y: 0 }
+        );
+        // Edges of the world clamp into range
+        let corner = Tile::from_coord(Coord::new(-90.0, 180.0), 2);
+        assert_eq!((corner.x, corner.y), (3, 3));
+    }
+
+    #[test]
+    fn test_tile_bbox() {
+        let coord = Coord::new(52.2053, 0.1218);
+        let tile = Tile::from_coord(coord, 15);
+        let bbox = tile.bbox();
+        assert!(bbox.contains(&coord));
+        assert!(bbox.north > bbox.south && bbox.east > bbox.west);
+        assert_eq!(Tile::from_coord(bbox.center(), 15), tile);
+        let world = Tile { z: 0, x: 0, y: 0 }.bbox();
+        assert!((world.north - MAX_LATITUDE).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_tile_url() {
+        let tile = Tile {
+            z: 13,
+            x: 4098,
+            y: 2698,
+        };
+        assert_eq!(
+            tile.url(OSM_TILE_URL),
+            "https://b.tile.openstreetmap.org/13/4098/2698.png"
+        );
+    }
+
+    #[test]
+    fn test_tiles_in_bbox() {
+        let tile = Tile::from_coord(Coord::new(52.2053, 0.1218), 14);
+        let bbox = tile.bbox();
+        // A box just inside one tile needs only that tile
+        let inner = BBox::new(
+            Coord::new(bbox.south + 1e-6, bbox.west + 1e-6),
+            Coord::new(bbox.north - 1e-6, bbox.east - 1e-6),
+        );
+        assert_eq!(tiles_in_bbox(&inner, 14), vec![tile]);
+        assert_eq!(tiles_in_bbox(&inner, 15).len(), 4);
+        assert_eq!(count_tiles_in_bbox(&inner, 14..=16), 1 + 4 + 16);
+    }
+
+    #[test]
+    fn test_tiles_along_line() {
+        let line = [Coord::new(52.0, 0.0), Coord::new(52.0, 0.5)];
+        let corridor = tiles_along_line(&line, 100.0, 14);
+        let bbox = tiles_in_bbox(&BBox::from_coords(line).unwrap().buffer(100.0), 14);
+        assert!(corridor.iter().all(|tile| bbox.contains(tile)));
+        // An east-west line at this zoom crosses one or two rows of tiles
+        let columns: BTreeSet<u32> = corridor.iter().map(|t| t.x).collect();
+        assert!(corridor.len() <= columns.len() * 2);
+        assert_eq!(
+            count_tiles_along_line(&line, 100.0, 14..=14),
+            corridor.len()
+        );
+        assert!(tiles_along_line(&[], 100.0, 14).is_empty());
+    }
+
+    #[test]
+    fn test_metres_per_pixel() {
+        assert!((metres_per_pixel(0.0, 0.0) - 156_543.03).abs() < 0.01);
+        assert!((metres_per_pixel(60.0, 1.0) - 156_543.03 / 4.0).abs() < 0.01);
+    }
+}
@@ -0,0 +1,199 @@
+//! Ellipsoidal transverse Mercator projection and Helmert datum shift, following the formulas in
+//! Ordnance Survey's "A guide to coordinates systems in Great Britain".
+
+/// Reference ellipsoid given by its semi-major and semi-minor axes in metres.
+#[derive(Clone, Copy, Debug)]
+pub struct Ellipsoid {
+    pub a: f64,
+    pub b: f64,
+}
+
+impl Ellipsoid {
+    fn e_sq(&self) -> f64 {
+        1.0 - (self.b * self.b) / (self.a * self.a)
+    }
+}
+
+pub const WGS84: Ellipsoid = Ellipsoid {
+    a: 6_378_137.0,
+    b: 6_356_752.314_245,
+};
+
+pub const AIRY_1830: Ellipsoid = Ellipsoid {
+    a: 6_377_563.396,
+    b: 6_356_256.909,
+};
+
+/// Transverse Mercator projection on an ellipsoid, with its true origin and false origin.
+#[derive(Clone, Copy, Debug)]
+pub struct TransverseMercator {
+    pub ellipsoid: Ellipsoid,
+    /// Scale factor on the central meridian.
+    pub f0: f64,
+    /// Latitude of true origin, in degrees.
+    pub lat0: f64,
+    /// Longitude of true origin (the central meridian), in degrees.
+    pub lon0: f64,
+    /// Easting of true origin, in metres.
+    pub e0: f64,
+    /// Northing of true origin, in metres.
+    pub n0: f64,
+}
+
+impl TransverseMercator {
+    /// Developed meridional arc from the true origin latitude to `phi` (radians), scaled by `f0`.
+    fn meridional_arc(&self, phi: f64) -> f64 {
+        let Ellipsoid { a, b } = self.ellipsoid;
+        let n = (a - b) / (a + b);
+        let (n2, n3) = (n * n, n * n * n);
+        let phi0 = self.lat0.to_radians();
+        let (d, s) = (phi - phi0, phi + phi0);
+        b * self.f0
+            * ((1.0 + n + 1.25 * n2 + 1.25 * n3) * d
+                - (3.0 * n + 3.0 * n2 + 2.625 * n3) * d.sin() * s.cos()
+                + (1.875 * n2 + 1.875 * n3) * (2.0 * d).sin() * (2.0 * s).cos()
+                - (35.0 / 24.0) * n3 * (3.0 * d).sin() * (3.0 * s).cos())
+    }
+
+    /// Radii of curvature (nu, rho) and eta squared at latitude `phi` (radians).
+    fn curvature(&self, phi: f64) -> (f64, f64, f64) {
+        let a = self.ellipsoid.a;
+        let e_sq = self.ellipsoid.e_sq();
+        let w = 1.0 - e_sq * phi.sin().powi(2);
+        let nu = a * self.f0 / w.sqrt();
+        let rho = a * self.f0 * (1.0 - e_sq) / w.powf(1.5);
+        (nu, rho, nu / rho - 1.0)
+    }
+
+    /// Project latitude and longitude in degrees to (easting, northing) in metres.
+    pub fn forward(&self, lat: f64, lon: f64) -> (f64, f64) {
+        let phi = lat.to_radians();
+        let (nu, rho, eta_sq) = self.curvature(phi);
+        let (sin, cos, tan) = (phi.sin(), phi.cos(), phi.tan());
+        let (tan2, tan4) = (tan * tan, tan.powi(4));
+
+        let i = self.meridional_arc(phi) + self.n0;
+        let ii = nu / 2.0 * sin * cos;
+        let iii = nu / 24.0 * sin * cos.powi(3) * (5.0 - tan2 + 9.0 * eta_sq);
+        let iiia = nu / 720.0 * sin * cos.powi(5) * (61.0 - 58.0 * tan2 + tan4);
+        let iv = nu * cos;
+        let v = nu / 6.0 * cos.powi(3) * (nu / rho - tan2);
+        let vi = nu / 120.0
+            * cos.powi(5)
+            * (5.0 - 18.0 * tan2 + tan4 + 14.0 * eta_sq - 58.0 * tan2 * eta_sq);
+
+        let dl = (lon - self.lon0).to_radians();
+        let northing = i + ii * dl.powi(2) + iii * dl.powi(4) + iiia * dl.powi(6);
+        let easting = self.e0 + iv * dl + v * dl.powi(3) + vi * dl.powi(5);
+        (easting, northing)
+    }
+
+    /// Inverse of [`TransverseMercator::forward`], returning (lat, lon) in degrees.
+    pub fn inverse(&self, easting: f64, northing: f64) -> (f64, f64) {
+        let a_f0 = self.ellipsoid.a * self.f0;
+        let mut phi = (northing - self.n0) / a_f0 + self.lat0.to_radians();
+        let mut m = self.meridional_arc(phi);
+        while (northing - self.n0 - m).abs() >= 1e-5 {
+            phi += (northing - self.n0 - m) / a_f0;
+            m = self.meridional_arc(phi);
+        }
+        let (nu, rho, eta_sq) = self.curvature(phi);
+        let (tan, sec) = (phi.tan(), 1.0 / phi.cos());
+        let (tan2, tan4, tan6) = (tan * tan, tan.powi(4), tan.powi(6));
+
+        let vii = tan / (2.0 * rho * nu);
+        let viii =
+            tan / (24.0 * rho * nu.powi(3)) * (5.0 + 3.0 * tan2 + eta_sq - 9.0 * tan2 * eta_sq);
+        let ix = tan / (720.0 * rho * nu.powi(5)) * (61.0 + 90.0 * tan2 + 45.0 * tan4);
+        let x = sec / nu;
+        let xi = sec / (6.0 * nu.powi(3)) * (nu / rho + 2.0 * tan2);
+        let xii = sec / (120.0 * nu.powi(5)) * (5.0 + 28.0 * tan2 + 24.0 * tan4);
+        let xiia =
+            sec / (5040.0 * nu.powi(7)) * (61.0 + 662.0 * tan2 + 1320.0 * tan4 + 720.0 * tan6);
+
+        let de = easting - self.e0;
+        let lat = phi - vii * de.powi(2) + viii * de.powi(4) - ix * de.powi(6);
+        let lon = self.lon0.to_radians() + x * de - xi * de.powi(3) + xii * de.powi(5)
+            - xiia * de.powi(7);
+        (lat.to_degrees(), lon.to_degrees())
+    }
+}
+
+/// Seven-parameter Helmert transformation between geodetic datums.
+#[derive(Clone, Copy, Debug)]
+pub struct Helmert {
+    /// Translations in metres.
+    pub tx: f64,
+    pub ty: f64,
+    pub tz: f64,
+    /// Scale change in parts per million.
+    pub s: f64,
+    /// Rotations in arc-seconds.
+    pub rx: f64,
+    pub ry: f64,
+    pub rz: f64,
+}
+
+/// WGS84 to OSGB36, good to around five metres across Great Britain.
+pub const WGS84_TO_OSGB36: Helmert = Helmert {
+    tx: -446.448,
+    ty: 125.157,
+    tz: -542.060,
+    s: 20.4894,
+    rx: -0.1502,
+    ry: -0.2470,
+    rz: -0.8421,
+};
+
+impl Helmert {
+    pub fn inverse(&self) -> Helmert {
+        Helmert {
+            tx: -self.tx,
+            ty: -self.ty,
+            tz: -self.tz,
+            s: -self.s,
+            rx: -self.rx,
+            ry: -self.ry,
+            rz: -self.rz,
+        }
+    }
+
+    /// Move latitude and longitude in degrees from ellipsoid `from` to ellipsoid `to`, at zero height.
+    pub fn convert(&self, lat: f64, lon: f64, from: Ellipsoid, to: Ellipsoid) -> (f64, f64) {
+        let (x, y, z) = to_cartesian(lat, lon, from);
+        let s = 1.0 + self.s * 1e-6;
+        let arcsec = |r: f64| (r / 3600.0).to_radians();
+        let (rx, ry, rz) = (arcsec(self.rx), arcsec(self.ry), arcsec(self.rz));
+        let x2 = self.tx + s * x - rz * y + ry * z;
+        let y2 = self.ty + rz * x + s * y - rx * z;
+        let z2 = self.tz - ry * x + rx * y + s * z;
+        from_cartesian(x2, y2, z2, to)
+    }
+}
+
+fn to_cartesian(lat: f64, lon: f64, ellipsoid: Ellipsoid) -> (f64, f64, f64) {
+    let (phi, lambda) = (lat.to_radians(), lon.to_radians());
+    let e_sq = ellipsoid.e_sq();
+    let nu = ellipsoid.a / (1.0 - e_sq * phi.sin().powi(2)).sqrt();
+    (
+        nu * phi.cos() * lambda.cos(),
+        nu * phi.cos() * lambda.sin(),
+        (1.0 - e_sq) * nu * phi.sin(),
+    )
+}
+
+fn from_cartesian(x: f64, y: f64, z: f64, ellipsoid: Ellipsoid) -> (f64, f64) {
+    let e_sq = ellipsoid.e_sq();
+    let p = (x * x + y * y).sqrt();
+    let mut phi = z.atan2(p * (1.0 - e_sq));
+    for _ in 0..10 {
+        let nu = ellipsoid.a / (1.0 - e_sq * phi.sin().powi(2)).sqrt();
+        let next = (z + e_sq * nu * phi.sin()).atan2(p);
+        if (next - phi).abs() < 1e-12 {
+            phi = next;
+            break;
+        }
+        phi = next;
+    }
+    (phi.to_degrees(), y.atan2(x).to_degrees())
+}
@@ -0,0 +1,279 @@
+use core::fmt;
+
+use super::transverse_mercator::{TransverseMercator, WGS84};
+use super::{Coord, GridRefError};
+
+/// Latitude bands C to X, 8 degrees each from 80°S, with X stretched to 84°N.
+const BANDS: &[u8] = b"CDEFGHJKLMNPQRSTUVWX";
+/// MGRS 100 km column letters, in three sets that repeat every three zones.
+const COLUMN_SETS: [&[u8]; 3] = [b"ABCDEFGH", b"JKLMNPQR", b"STUVWXYZ"];
+/// MGRS 100 km row letters, offset by five in even zones.
+const ROWS: &[u8] = b"ABCDEFGHJKLMNPQRSTUV";
+const FALSE_NORTHING_SOUTH: f64 = 10_000_000.0;
+
+/// Universal Transverse Mercator position on WGS84.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Utm {
+    zone: u8,
+    /// Latitude band letter; bands `N` and above are in the northern hemisphere.
+    band: char,
+    easting: f64,
+    northing: f64,
+}
+
+impl fmt::Display for Utm {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}{} {:.0} {:.0}",
+            self.zone, self.band, self.easting, self.northing
+        )
+    }
+}
+
+fn projection(zone: u8, northern: bool) -> TransverseMercator {
+    TransverseMercator {
+        ellipsoid: WGS84,
+        f0: 0.9996,
+        lat0: 0.0,
+        lon0: zone as f64 * 6.0 - 183.0,
+        e0: 500_000.0,
+        n0: if northern { 0.0 } else { FALSE_NORTHING_SOUTH },
+    }
+}
+
+impl Utm {
+    /// Position `easting` and `northing` metres into `zone` (1 to 60) and latitude `band`.
+    pub fn new(zone: u8, band: char, easting: f64, northing: f64) -> Result<Utm, GridRefError> {
+        if !(1..=60).contains(&zone) || !band.is_ascii() || !BANDS.contains(&(band as u8)) {
+            return Err(GridRefError::Invalid(format!("{}{}", zone, band)));
+        }
+        Ok(Utm {
+            zone,
+            band,
+            easting,
+            northing,
+        })
+    }
+
+    pub fn zone(&self) -> u8 {
+        self.zone
+    }
+
+    pub fn band(&self) -> char {
+        self.band
+    }
+
+    pub fn easting(&self) -> f64 {
+        self.easting
+    }
+
+    pub fn northing(&self) -> f64 {
+        self.northing
+    }
+
+    fn is_northern(&self) -> bool {
+        self.band >= 'N'
+    }
+
+    pub fn to_coord(&self) -> Coord {
+        let (lat, lon) =
+            projection(self.zone, self.is_northern()).inverse(self.easting, self.northing);
+        Coord { lat, lon }
+    }
+
+    /// MGRS reference such as `17T PJ 30084 33438`, with `digits` in total (2 to 10, even).
+    pub fn to_mgrs(&self, digits: usize) -> String {
+        let column = (self.easting / 100_000.0).floor() as usize;
+        let row = (self.northing / 100_000.0).floor() as usize;
+        let column_letter = COLUMN_SETS[(self.zone as usize - 1) % 3][(column + 7) % 8];
+        let row_offset = if self.zone.is_multiple_of(2) { 5 } else { 0 };
+        let row_letter = ROWS[(row + row_offset) % 20];
+
+        let half = digits.clamp(2, 10) / 2;
+        let divisor = 10f64.powi(5 - half as i32);
+        let e = ((self.easting % 100_000.0) / divisor).floor() as u32;
+        let n = ((self.northing % 100_000.0) / divisor).floor() as u32;
+        format!(
+            "{}{} {}{} {:0w$} {:0w$}",
+            self.zone,
+            self.band,
+            char::from(column_letter),
+            char::from(row_letter),
+            e,
+            n,
+            w = half
+        )
+    }
+
+    /// South-west corner of the square named by an MGRS reference such as `17TPJ3008433439`.
+    pub fn from_mgrs(mgrs: &str) -> Result<Utm, GridRefError> {
+        let invalid = || GridRefError::Invalid(mgrs.to_string());
+        let cleaned: String = mgrs
+            .chars()
+            .filter(|c| !c.is_whitespace())
+            .collect::<String>()
+            .to_uppercase();
+        let zone_len = cleaned.chars().take_while(|c| c.is_ascii_digit()).count();
+        let zone: u8 = cleaned[..zone_len].parse().map_err(|_| invalid())?;
+        if !(1..=60).contains(&zone) {
+            return Err(invalid());
+        }
+        let rest = &cleaned.as_bytes()[zone_len..];
+        if rest.len() < 3 {
+            return Err(invalid());
+        }
+        let band_index = BANDS
+            .iter()
+            .position(|&b| b == rest[0])
+            .ok_or_else(invalid)?;
+        let column = COLUMN_SETS[(zone as usize - 1) % 3]
+            .iter()
+            .position(|&b| b == rest[1])
+            .ok_or_else(invalid)?;
+        let row = ROWS
+            .iter()
+            .position(|&b| b == rest[2])
+            .ok_or_else(invalid)?;
+
+        let numbers = &cleaned[zone_len + 3..];
+        if !numbers.len().is_multiple_of(2)
+            || numbers.len() > 10
+            || !numbers.chars().all(|c| c.is_ascii_digit())
+        {
+            return Err(invalid());
+        }
+        let (e, n) = numbers.split_at(numbers.len() / 2);
+        let metres = |digits: &str| format!("{:0<5}", digits).parse::<f64>().unwrap_or_default();
+
+        let band = char::from(BANDS[band_index]);
+        let northern = band >= 'N';
+        let row_offset = if zone.is_multiple_of(2) { 5 } else { 0 };
+        let easting = (column as f64 + 1.0) * 100_000.0 + metres(e);
+        let mut northing = ((row + 20 - row_offset) % 20) as f64 * 100_000.0 + metres(n);
+
+        // Row letters repeat every 2000 km, so lift the northing into the latitude band.
+        let band_south = -80.0 + band_index as f64 * 8.0;
+        let (_, band_min) =
+            projection(zone, northern).forward(band_south, zone as f64 * 6.0 - 183.0);
+        while northing < band_min - 100_000.0 {
+            northing += 2_000_000.0;
+        }
+        Ok(Utm {
+            zone,
+            band,
+            easting,
+            northing,
+        })
+    }
+}
+
+impl Coord {
+    /// UTM zone and band for this position, honouring the Norway and Svalbard exceptions.
+    /// Positions beyond 80°S and 84°N are covered by UPS instead, which is not supported.
+    pub fn to_utm(&self) -> Result<Utm, GridRefError> {
+        if !(-80.0..84.0).contains(&self.lat) {
+            return Err(GridRefError::OutOfRange);
+        }
+        let lon = (self.lon + 540.0).rem_euclid(360.0) - 180.0;
+        let mut zone = (((lon + 180.0) / 6.0).floor() as u8 % 60) + 1;
+        let band = char::from(BANDS[(((self.lat + 80.0) / 8.0).floor() as usize).min(19)]);
+        if band == 'V' && (3.0..12.0).contains(&lon) {
+            zone = 32;
+        }
+        if band == 'X' && (0.0..42.0).contains(&lon) {
+            zone = match lon {
+                l if l < 9.0 => 31,
+                l if l < 21.0 => 33,
+                l if l < 33.0 => 35,
+                _ => 37,
+            };
+        }
+        let (easting, northing) = projection(zone, self.lat >= 0.0).forward(self.lat, lon);
+        Ok(Utm {
+            zone,
+            band,
+            easting,
+            northing,
+        })
+    }
+
+    pub fn to_mgrs(&self, digits: usize) -> Result<String, GridRefError> {
+        Ok(self.to_utm()?.to_mgrs(digits))
+    }
+
+    pub fn from_mgrs(mgrs: &str) -> Result<Coord, GridRefError> {
+        Ok(Utm::from_mgrs(mgrs)?.to_coord())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // CN Tower, Toronto, from the Wikipedia UTM article
+    fn cn_tower() -> Coord {
+        Coord::new(43.642567, -79.387139)
+    }
+
+    #[test]
+    fn test_to_utm() {
+        let utm = cn_tower().to_utm().unwrap();
+        assert_eq!((utm.zone(), utm.band()), (17, 'T'));
+        assert!((utm.easting() - 630_084.0).abs() < 1.0);
+        assert!((utm.northing() - 4_833_439.0).abs() < 1.0);
+        assert_eq!(utm.to_string(), "17T 630084 4833439");
+        assert!(cn_tower().haversine_distance(&utm.to_coord()) < 0.01);
+
+        // Southern hemisphere uses a false northing
+        let sydney = Coord::new(-33.8688, 151.2093);
+        let utm = sydney.to_utm().unwrap();
+        assert_eq!((utm.zone(), utm.band()), (56, 'H'));
+        assert!(sydney.haversine_distance(&utm.to_coord()) < 0.01);
+
+        assert_eq!(Coord::new(60.0, 5.0).to_utm().unwrap().zone(), 32);
+        assert_eq!(Coord::new(78.0, 10.0).to_utm().unwrap().zone(), 33);
+        assert_eq!(
+            Coord::new(85.0, 0.0).to_utm(),
+            Err(GridRefError::OutOfRange)
+        );
+
+        // Positions built by hand are checked, so converting them can't fail.
+        assert!(Utm::new(0, 'T', 500_000.0, 0.0).is_err());
+        assert!(Utm::new(61, 'T', 500_000.0, 0.0).is_err());
+        assert!(Utm::new(17, 'I', 500_000.0, 0.0).is_err());
+        let utm = Utm::new(17, 'T', 630_084.0, 4_833_439.0).unwrap();
+        assert_eq!(utm.to_mgrs(4), "17T PJ 30 33");
+    }
+
+    #[test]
+    fn test_mgrs() {
+        // MGRS truncates rather than rounds
+        assert_eq!(cn_tower().to_mgrs(10).unwrap(), "17T PJ 30084 33438");
+        assert_eq!(cn_tower().to_mgrs(4).unwrap(), "17T PJ 30 33");
+        let back = Coord::from_mgrs("17TPJ3008433439").unwrap();
+        assert!(cn_tower().haversine_distance(&back) < 2.0);
+
+        // Cambridge, near the western edge of zone 31
+        let cambridge = Coord::new(52.2053, 0.1218);
+        let mgrs = cambridge.to_mgrs(10).unwrap();
+        assert!(mgrs.starts_with("31U "), "Testing zone {}", mgrs);
+        let back = Coord::from_mgrs(&mgrs).unwrap();
+        assert!(cambridge.haversine_distance(&back) < 2.0);
+
+        // Oxford, in an even zone where the row letters are offset
+        let oxford = Coord::new(51.752, -1.2577);
+        let mgrs = oxford.to_mgrs(10).unwrap();
+        assert!(mgrs.starts_with("30U "), "Testing zone {}", mgrs);
+        let back = Coord::from_mgrs(&mgrs).unwrap();
+        assert!(oxford.haversine_distance(&back) < 2.0);
+
+        let sydney = Coord::new(-33.8688, 151.2093);
+        let back = Coord::from_mgrs(&sydney.to_mgrs(10).unwrap()).unwrap();
+        assert!(sydney.haversine_distance(&back) < 2.0);
+
+        assert!(Coord::from_mgrs("17TIJ123456").is_err());
+        assert!(Coord::from_mgrs("61TPJ1234").is_err());
+        assert!(Coord::from_mgrs("17TPJ123").is_err());
+    }
+}
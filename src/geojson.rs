@@ -0,0 +1,524 @@
+//! GeoJSON ([RFC 7946](https://www.rfc-editor.org/rfc/rfc7946)) reader and writer for the
+//! track model. Line strings become tracks, or routes if marked as such, points become
+//! waypoints, and other geometry is skipped. Properties follow the names togeojson gives GPX
+//! data, so files from and for other GPX tooling agree.
+
+use core::fmt;
+use std::io::Read;
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+
+use crate::geo::Coord;
+use crate::track::{Route, Segment, Track, TrackDocument, TrackPoint, Waypoint};
+
+/// Property recording whether a line was a GPX track (`trk`) or route (`rte`).
+const GPX_TYPE: &str = "_gpxType";
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum GeoJsonError {
+    /// The text is not JSON. Line and column count from 1.
+    Syntax {
+        line: usize,
+        column: usize,
+        message: String,
+    },
+    /// JSON that is not a GeoJSON object.
+    Invalid(String),
+}
+
+impl fmt::Display for GeoJsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GeoJsonError::Syntax {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "JSON error at line {}, column {}: {}",
+                line, column, message
+            ),
+            GeoJsonError::Invalid(reason) => write!(f, "not valid GeoJSON ({})", reason),
+        }
+    }
+}
+
+impl std::error::Error for GeoJsonError {}
+
+impl From<serde_json::Error> for GeoJsonError {
+    fn from(e: serde_json::Error) -> Self {
+        GeoJsonError::Syntax {
+            line: e.line(),
+            column: e.column(),
+            message: e.to_string(),
+        }
+    }
+}
+
+pub fn read_geojson(text: &str) -> Result<TrackDocument, GeoJsonError> {
+    read_geojson_from(text.as_bytes())
+}
+
+/// [`read_geojson`], reading the text from `input`.
+pub fn read_geojson_from(input: impl Read) -> Result<TrackDocument, GeoJsonError> {
+    let value: Value = serde_json::from_reader(input)?;
+    let mut document = TrackDocument {
+        name: value
+            .get("name")
+            .and_then(Value::as_str)
+            .map(str::to_string),
+        ..TrackDocument::default()
+    };
+    read_object(&value, Properties::default(), &mut document)?;
+    Ok(document)
+}
+
+/// Properties of the enclosing Feature, which apply to every geometry inside it.
+#[derive(Clone, Copy, Default)]
+struct Properties<'a>(Option<&'a Value>);
+
+impl<'a> Properties<'a> {
+    fn get(&self, key: &str) -> Option<&'a Value> {
+        self.0.and_then(|properties| properties.get(key))
+    }
+
+    fn text(&self, key: &str) -> Option<String> {
+        self.get(key).and_then(Value::as_str).map(str::to_string)
+    }
+
+    /// Per-point times, as `coordTimes` or the newer `coordinateProperties.times`.
+    fn times(&self) -> Option<&'a Value> {
+        self.get("coordTimes").or_else(|| {
+            self.get("coordinateProperties")
+                .and_then(|properties| properties.get("times"))
+        })
+    }
+
+    /// Lines marked as GPX routes, as written by [`write_geojson`] and togeojson.
+    fn is_route(&self) -> bool {
+        self.get(GPX_TYPE).and_then(Value::as_str) == Some("rte")
+    }
+}
+
+/// Add a FeatureCollection, Feature or geometry to `document`.
+fn read_object(
+    value: &Value,
+    properties: Properties,
+    document: &mut TrackDocument,
+) -> Result<(), GeoJsonError> {
+    let kind = value
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or_else(|| GeoJsonError::Invalid("object has no type".to_string()))?;
+    match kind {
+        "FeatureCollection" => array(value, "features")?
+            .iter()
+            .try_for_each(|feature| read_object(feature, Properties::default(), document)),
+        "Feature" => {
+            let properties = Properties(value.get("properties"));
+            match value.get("geometry") {
+                Some(Value::Null) | None => Ok(()),
+                Some(geometry) => read_object(geometry, properties, document),
+            }
+        }
+        "GeometryCollection" => array(value, "geometries")?
+            .iter()
+            .try_for_each(|geometry| read_object(geometry, properties, document)),
+        "Point" => {
+            document
+                .waypoints
+                .push(waypoint(coordinates(value)?, properties)?);
+            Ok(())
+        }
+        "MultiPoint" => array(value, "coordinates")?.iter().try_for_each(|point| {
+            document.waypoints.push(waypoint(point, properties)?);
+            Ok(())
+        }),
+        "LineString" => {
+            let segment = segment(coordinates(value)?, properties.times())?;
+            if properties.is_route() {
+                document.routes.push(Route {
+                    name: properties.text("name"),
+                    description: properties.text("desc"),
+                    points: segment.points,
+                });
+            } else {
+                document.tracks.push(track(vec![segment], properties));
+            }
+            Ok(())
+        }
+        "MultiLineString" => {
+            let times = properties.times().and_then(Value::as_array);
+            let segments = array(value, "coordinates")?
+                .iter()
+                .enumerate()
+                .map(|(i, line)| segment(line, times.and_then(|times| times.get(i))))
+                .collect::<Result<_, GeoJsonError>>()?;
+            document.tracks.push(track(segments, properties));
+            Ok(())
+        }
+        // Areas have no place in the track model.
+        "Polygon" | "MultiPolygon" => Ok(()),
+        other => Err(GeoJsonError::Invalid(format!("unknown type {}", other))),
+    }
+}
+
+fn array<'a>(value: &'a Value, key: &str) -> Result<&'a Vec<Value>, GeoJsonError> {
+    value
+        .get(key)
+        .and_then(Value::as_array)
+        .ok_or_else(|| GeoJsonError::Invalid(format!("{} is not an array", key)))
+}
+
+fn coordinates(value: &Value) -> Result<&Value, GeoJsonError> {
+    value
+        .get("coordinates")
+        .ok_or_else(|| GeoJsonError::Invalid("geometry has no coordinates".to_string()))
+}
+
+/// A GeoJSON position, `[longitude, latitude]` with an optional elevation.
+fn position(value: &Value) -> Result<(Coord, Option<f64>), GeoJsonError> {
+    let not_a_position = || GeoJsonError::Invalid(format!("{} is not a position", value));
+    let numbers = value
+        .as_array()
+        .ok_or_else(not_a_position)?
+        .iter()
+        .map(|number| number.as_f64().ok_or_else(not_a_position))
+        .collect::<Result<Vec<f64>, _>>()?;
+    match numbers[..] {
+        [lon, lat] => Ok((Coord::new(lat, lon), None)),
+        [lon, lat, elevation, ..] => Ok((Coord::new(lat, lon), Some(elevation))),
+        _ => Err(not_a_position()),
+    }
+}
+
+/// An RFC 3339 time, or `None` for `null` or anything else that isn't one.
+fn time(value: Option<&Value>) -> Option<OffsetDateTime> {
+    OffsetDateTime::parse(value?.as_str()?, &Rfc3339).ok()
+}
+
+/// A line's positions, with `times` matched to them by index.
+fn segment(value: &Value, times: Option<&Value>) -> Result<Segment, GeoJsonError> {
+    let times = times.and_then(Value::as_array);
+    let points = value
+        .as_array()
+        .ok_or_else(|| GeoJsonError::Invalid("line coordinates are not an array".to_string()))?
+        .iter()
+        .enumerate()
+        .map(|(i, value)| {
+            let (coord, elevation) = position(value)?;
+            Ok(TrackPoint {
+                coord,
+                elevation,
+                time: time(times.and_then(|times| times.get(i))),
+                ..TrackPoint::default()
+            })
+        })
+        .collect::<Result<_, GeoJsonError>>()?;
+    Ok(Segment { points })
+}
+
+fn track(segments: Vec<Segment>, properties: Properties) -> Track {
+    Track {
+        name: properties.text("name"),
+        description: properties.text("desc"),
+        kind: properties.text("type"),
+        segments,
+    }
+}
+
+fn waypoint(value: &Value, properties: Properties) -> Result<Waypoint, GeoJsonError> {
+    let (coord, elevation) = position(value)?;
+    Ok(Waypoint {
+        coord,
+        elevation,
+        time: time(properties.get("time")),
+        name: properties.text("name"),
+        description: properties.text("desc"),
+        symbol: properties.text("sym"),
+    })
+}
+
+fn position_json(coord: Coord, elevation: Option<f64>) -> Value {
+    match elevation {
+        Some(elevation) => json!([coord.lon, coord.lat, elevation]),
+        None => json!([coord.lon, coord.lat]),
+    }
+}
+
+fn line_json(points: &[TrackPoint]) -> Value {
+    points
+        .iter()
+        .map(|point| position_json(point.coord, point.elevation))
+        .collect()
+}
+
+fn time_json(time: Option<OffsetDateTime>) -> Value {
+    time.and_then(|time| time.format(&Rfc3339).ok())
+        .map_or(Value::Null, Value::String)
+}
+
+/// `coordTimes` for `points`, or `None` if none of them has a time.
+fn times_json(points: &[TrackPoint]) -> Option<Value> {
+    points
+        .iter()
+        .any(|point| point.time.is_some())
+        .then(|| points.iter().map(|point| time_json(point.time)).collect())
+}
+
+/// Feature properties, leaving out the ones that have no value.
+fn properties_json(entries: Vec<(&str, Option<Value>)>) -> Value {
+    Value::Object(
+        entries
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.to_string(), value?)))
+            .collect(),
+    )
+}
+
+fn feature(geometry: Value, properties: Value) -> Value {
+    json!({ "type": "Feature", "properties": properties, "geometry": geometry })
+}
+
+/// Serialise `document` as a GeoJSON FeatureCollection. Tracks are LineStrings, or
+/// MultiLineStrings when they have several segments; routes are LineStrings marked with
+/// `_gpxType: "rte"`, and waypoints are Points. Per-point times go in `coordTimes`.
+pub fn write_geojson(document: &TrackDocument) -> String {
+    let text = |text: &Option<String>| text.clone().map(Value::String);
+    let tracks = document.tracks.iter().map(|track| {
+        let (geometry, times) = match &track.segments[..] {
+            [segment] => (
+                json!({ "type": "LineString", "coordinates": line_json(&segment.points) }),
+                times_json(&segment.points),
+            ),
+            segments => {
+                let lines: Vec<Value> = segments.iter().map(|s| line_json(&s.points)).collect();
+                let times = segments
+                    .iter()
+                    .any(|s| s.points.iter().any(|point| point.time.is_some()))
+                    .then(|| {
+                        segments
+                            .iter()
+                            .map(|s| times_json(&s.points).unwrap_or_else(|| json!([])))
+                            .collect()
+                    });
+                (
+                    json!({ "type": "MultiLineString", "coordinates": lines }),
+                    times,
+                )
+            }
+        };
+        let properties = properties_json(vec![
+            ("name", text(&track.name)),
+            ("desc", text(&track.description)),
+            ("type", text(&track.kind)),
+            (GPX_TYPE, Some(json!("trk"))),
+            ("coordTimes", times),
+        ]);
+        feature(geometry, properties)
+    });
+    let routes = document.routes.iter().map(|route| {
+        let geometry = json!({ "type": "LineString", "coordinates": line_json(&route.points) });
+        let properties = properties_json(vec![
+            ("name", text(&route.name)),
+            ("desc", text(&route.description)),
+            (GPX_TYPE, Some(json!("rte"))),
+            ("coordTimes", times_json(&route.points)),
+        ]);
+        feature(geometry, properties)
+    });
+    let waypoints = document.waypoints.iter().map(|waypoint| {
+        let geometry = json!({
+            "type": "Point",
+            "coordinates": position_json(waypoint.coord, waypoint.elevation),
+        });
+        let properties = properties_json(vec![
+            ("name", text(&waypoint.name)),
+            ("desc", text(&waypoint.description)),
+            ("sym", text(&waypoint.symbol)),
+            ("time", waypoint.time.map(|time| time_json(Some(time)))),
+        ]);
+        feature(geometry, properties)
+    });
+    let mut collection = json!({
+        "type": "FeatureCollection",
+        "features": tracks.chain(routes).chain(waypoints).collect::<Vec<_>>(),
+    });
+    if let Some(name) = &document.name {
+        collection["name"] = json!(name);
+    }
+    collection.to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    #[test]
+    fn test_read_geojson() {
+        let text = r#"{
+            "type": "FeatureCollection",
+            "features": [
+                {
+                    "type": "Feature",
+                    "properties": { "name": "Morning ride" },
+                    "geometry": {
+                        "type": "LineString",
+                        "coordinates": [[0.1218, 52.2053, 12.0], [0.1300, 52.2100, 15.5]]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": { "name": "Cafe" },
+                    "geometry": { "type": "Point", "coordinates": [0.125, 52.207] }
+                },
+                {
+                    "type": "Feature",
+                    "properties": null,
+                    "geometry": {
+                        "type": "MultiLineString",
+                        "coordinates": [[[0.0, 52.0], [0.1, 52.1]], [[0.2, 52.2], [0.3, 52.3]]]
+                    }
+                }
+            ]
+        }"#;
+        let document = read_geojson(text).unwrap();
+        assert_eq!(document.tracks.len(), 2);
+        assert_eq!(document.tracks[0].name.as_deref(), Some("Morning ride"));
+        let first = &document.tracks[0].segments[0].points[0];
+        assert_eq!(first.coord, Coord::new(52.2053, 0.1218));
+        assert_eq!(first.elevation, Some(12.0));
+        assert_eq!(document.tracks[1].segments.len(), 2);
+        assert_eq!(document.waypoints.len(), 1);
+        assert_eq!(document.waypoints[0].name.as_deref(), Some("Cafe"));
+        assert_eq!(document.waypoints[0].coord, Coord::new(52.207, 0.125));
+
+        // A bare geometry is accepted too.
+        let line = read_geojson(r#"{"type": "LineString", "coordinates": [[0, 1], [2, 3]]}"#);
+        assert_eq!(line.unwrap().tracks[0].segments[0].points.len(), 2);
+    }
+
+    #[test]
+    fn test_read_properties() {
+        let text = r#"{
+            "type": "FeatureCollection",
+            "name": "Plans",
+            "features": [
+                {
+                    "type": "Feature",
+                    "properties": {
+                        "name": "Ride", "desc": "Out and back", "type": "cycling",
+                        "coordTimes": [["2024-05-01T09:30:00Z", null], ["2024-05-01T10:00:00Z"]]
+                    },
+                    "geometry": {
+                        "type": "MultiLineString",
+                        "coordinates": [[[0.0, 52.0], [0.1, 52.1]], [[0.2, 52.2]]]
+                    }
+                },
+                {
+                    "type": "Feature",
+                    "properties": {
+                        "name": "Planned", "_gpxType": "rte",
+                        "coordinateProperties": { "times": ["2024-05-01T11:00:00Z"] }
+                    },
+                    "geometry": { "type": "LineString", "coordinates": [[0.3, 52.3]] }
+                },
+                {
+                    "type": "Feature",
+                    "properties": { "name": "Cafe", "sym": "Restaurant", "time": "2024-05-01T10:15:00Z" },
+                    "geometry": { "type": "Point", "coordinates": [0.125, 52.207, 10] }
+                }
+            ]
+        }"#;
+        let document = read_geojson(text).unwrap();
+        assert_eq!(document.name.as_deref(), Some("Plans"));
+
+        let track = &document.tracks[0];
+        assert_eq!(track.description.as_deref(), Some("Out and back"));
+        assert_eq!(track.kind.as_deref(), Some("cycling"));
+        let times: Vec<_> = track.points().map(|point| point.time).collect();
+        assert_eq!(
+            times,
+            vec![
+                Some(datetime!(2024-05-01 09:30 UTC)),
+                None,
+                Some(datetime!(2024-05-01 10:00 UTC))
+            ]
+        );
+
+        assert_eq!(document.routes.len(), 1);
+        assert_eq!(
+            document.routes[0].points[0].time,
+            Some(datetime!(2024-05-01 11:00 UTC))
+        );
+
+        let waypoint = &document.waypoints[0];
+        assert_eq!(waypoint.symbol.as_deref(), Some("Restaurant"));
+        assert_eq!(waypoint.elevation, Some(10.0));
+        assert_eq!(waypoint.time, Some(datetime!(2024-05-01 10:15 UTC)));
+    }
+
+    #[test]
+    fn test_geojson_round_trip() {
+        let mut document = crate::track::tests::sample_document();
+        let start = datetime!(2024-05-01 09:30 UTC);
+        document.tracks[0].segments[0]
+            .points
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, point)| point.time = Some(start + time::Duration::seconds(i as i64)));
+        // A second, untimed segment makes the track a MultiLineString.
+        let untimed = Segment {
+            points: document.tracks[0].segments[0].points[..3]
+                .iter()
+                .map(|point| TrackPoint {
+                    time: None,
+                    ..point.clone()
+                })
+                .collect(),
+        };
+        document.tracks[0].segments.push(untimed);
+        document.tracks[0].kind = Some("cycling".to_string());
+        document.routes.push(Route {
+            name: Some("Planned".to_string()),
+            description: Some("The long way round".to_string()),
+            points: document.tracks[0].segments[1].points.clone(),
+        });
+        document.waypoints.push(Waypoint {
+            coord: Coord::new(52.207, 0.125),
+            elevation: Some(10.0),
+            time: Some(start),
+            name: Some("Cafe".to_string()),
+            description: Some("Cake".to_string()),
+            symbol: Some("Restaurant".to_string()),
+        });
+        document.name = Some("Sample".to_string());
+
+        let read = read_geojson(&write_geojson(&document)).unwrap();
+        assert_eq!(read, document);
+    }
+
+    #[test]
+    fn test_read_geojson_errors() {
+        assert!(matches!(
+            read_geojson("{\n  \"type\": }"),
+            Err(GeoJsonError::Syntax { line: 2, .. })
+        ));
+        assert!(matches!(
+            read_geojson(r#"{"features": []}"#),
+            Err(GeoJsonError::Invalid(_))
+        ));
+        assert!(matches!(
+            read_geojson(r#"{"type": "Point", "coordinates": [1]}"#),
+            Err(GeoJsonError::Invalid(_))
+        ));
+        // A stray value is not skipped, which would shift the latitude into the longitude.
+        assert!(matches!(
+            read_geojson(r#"{"type": "Point", "coordinates": ["x", 1, 2]}"#),
+            Err(GeoJsonError::Invalid(_))
+        ));
+    }
+}
@@ -0,0 +1,511 @@
+//! KML reader and writer, and KMZ unpacking. Placemarks with a `LineString` or `gx:Track`
+//! become tracks, and placemarks with a `Point` become waypoints.
+
+use core::fmt;
+use std::io::{Cursor, Read};
+
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use xml::common::Position;
+use xml::reader::{EventReader, XmlEvent};
+use xml::writer::{EmitterConfig, XmlEvent as WriteEvent};
+
+use crate::geo::Coord;
+use crate::track::{Segment, Track, TrackDocument, TrackPoint, Waypoint};
+use crate::xml_writer::{write_event, write_text_element, XmlWriter};
+
+const KML_NS: &str = "http://www.opengis.net/kml/2.2";
+const GX_NS: &str = "http://www.google.com/kml/ext/2.2";
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum KmlError {
+    /// The file is not well-formed XML. Line and column count from 1.
+    Xml {
+        line: u64,
+        column: u64,
+        message: String,
+    },
+    /// Well-formed XML that is not KML, or has coordinates that cannot be read.
+    Invalid(String),
+    /// A KMZ that cannot be unzipped, or has no KML file inside.
+    Kmz(String),
+}
+
+impl fmt::Display for KmlError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KmlError::Xml {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "XML error at line {}, column {}: {}",
+                line, column, message
+            ),
+            KmlError::Invalid(reason) => write!(f, "not a valid KML file ({})", reason),
+            KmlError::Kmz(reason) => write!(f, "not a valid KMZ file ({})", reason),
+        }
+    }
+}
+
+impl std::error::Error for KmlError {}
+
+impl From<xml::reader::Error> for KmlError {
+    fn from(e: xml::reader::Error) -> Self {
+        let position = e.position();
+        KmlError::Xml {
+            line: position.row + 1,
+            column: position.column + 1,
+            message: e.msg().to_string(),
+        }
+    }
+}
+
+/// What has been read of the `Placemark` being parsed.
+#[derive(Default)]
+struct Placemark {
+    name: Option<String>,
+    description: Option<String>,
+    lines: Vec<Segment>,
+    points: Vec<TrackPoint>,
+}
+
+pub fn read_kml(input: impl Read) -> Result<TrackDocument, KmlError> {
+    let mut document = TrackDocument::default();
+    // Local names of the open elements, outermost first.
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut placemark: Option<Placemark> = None;
+    // `when` and `gx:coord` values of the `gx:Track` being parsed.
+    let mut track_times: Vec<Option<OffsetDateTime>> = Vec::new();
+    let mut track_points: Vec<TrackPoint> = Vec::new();
+
+    for event in EventReader::new(input) {
+        match event? {
+            XmlEvent::StartElement { name, .. } => {
+                let name = name.local_name;
+                if path.is_empty() && name != "kml" {
+                    return Err(KmlError::Invalid(format!("root element is {}", name)));
+                }
+                match name.as_str() {
+                    "Placemark" => placemark = Some(Placemark::default()),
+                    "Track" => {
+                        track_times.clear();
+                        track_points.clear();
+                    }
+                    _ => {}
+                }
+                path.push(name);
+                text.clear();
+            }
+            XmlEvent::Characters(chars) | XmlEvent::CData(chars) => text.push_str(&chars),
+            XmlEvent::EndElement { .. } => {
+                let name = path.pop().unwrap_or_default();
+                let parent = path.last().map(String::as_str).unwrap_or_default();
+                let value = text.trim();
+                match (name.as_str(), parent, placemark.as_mut()) {
+                    ("name", "Document" | "Folder", _) if document.name.is_none() => {
+                        document.name = Some(value.to_string());
+                    }
+                    ("name", "Placemark", Some(placemark)) => {
+                        placemark.name = Some(value.to_string())
+                    }
+                    ("description", "Placemark", Some(placemark)) => {
+                        placemark.description = Some(value.to_string())
+                    }
+                    ("coordinates", "LineString", Some(placemark)) => {
+                        placemark.lines.push(Segment {
+                            points: read_coordinates(value)?,
+                        })
+                    }
+                    ("coordinates", "Point", Some(placemark)) => {
+                        placemark.points.extend(read_coordinates(value)?)
+                    }
+                    // Times that can't be read, such as a bare year, leave the point untimed.
+                    ("when", "Track", _) => {
+                        track_times.push(OffsetDateTime::parse(value, &Rfc3339).ok())
+                    }
+                    ("coord", "Track", _) => track_points.push(read_gx_coord(value)?),
+                    ("Track", _, Some(placemark)) => {
+                        let mut points = std::mem::take(&mut track_points);
+                        points
+                            .iter_mut()
+                            .zip(track_times.drain(..))
+                            .for_each(|(point, time)| point.time = time);
+                        placemark.lines.push(Segment { points });
+                    }
+                    ("Placemark", _, _) => {
+                        let placemark = placemark.take().unwrap_or_default();
+                        document
+                            .waypoints
+                            .extend(placemark.points.into_iter().map(|point| Waypoint {
+                                coord: point.coord,
+                                elevation: point.elevation,
+                                name: placemark.name.clone(),
+                                description: placemark.description.clone(),
+                                ..Waypoint::default()
+                            }));
+                        let segments: Vec<Segment> = placemark
+                            .lines
+                            .into_iter()
+                            .filter(|segment| !segment.points.is_empty())
+                            .collect();
+                        if !segments.is_empty() {
+                            document.tracks.push(Track {
+                                name: placemark.name,
+                                description: placemark.description,
+                                segments,
+                                ..Track::default()
+                            });
+                        }
+                    }
+                    _ => {}
+                }
+                text.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok(document)
+}
+
+/// Unzip a KMZ and read the KML inside.
+pub fn read_kmz(bytes: &[u8]) -> Result<TrackDocument, KmlError> {
+    read_kml(unzip_kmz(bytes)?.as_slice())
+}
+
+/// The KML inside a KMZ: `doc.kml` if present, otherwise the first `.kml` file.
+pub fn unzip_kmz(bytes: &[u8]) -> Result<Vec<u8>, KmlError> {
+    let mut archive =
+        zip::ZipArchive::new(Cursor::new(bytes)).map_err(|e| KmlError::Kmz(e.to_string()))?;
+    let name = archive
+        .file_names()
+        .filter(|name| name.to_ascii_lowercase().ends_with(".kml"))
+        .min_by_key(|name| *name != "doc.kml")
+        .map(str::to_string)
+        .ok_or_else(|| KmlError::Kmz("no KML file inside".to_string()))?;
+    let mut kml = Vec::new();
+    archive
+        .by_name(&name)
+        .and_then(|mut file| Ok(file.read_to_end(&mut kml)?))
+        .map_err(|e| KmlError::Kmz(e.to_string()))?;
+    Ok(kml)
+}
+
+/// Tuples of `longitude,latitude[,altitude]` separated by whitespace.
+fn read_coordinates(text: &str) -> Result<Vec<TrackPoint>, KmlError> {
+    text.split_whitespace()
+        .map(|tuple| read_position(tuple.split(','), tuple))
+        .collect()
+}
+
+/// A `gx:coord`, which is `longitude latitude altitude` separated by spaces.
+fn read_gx_coord(text: &str) -> Result<TrackPoint, KmlError> {
+    read_position(text.split_whitespace(), text)
+}
+
+fn read_position<'a>(
+    parts: impl Iterator<Item = &'a str>,
+    text: &str,
+) -> Result<TrackPoint, KmlError> {
+    let numbers = parts
+        .map(|part| part.parse::<f64>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| KmlError::Invalid(format!("{} is not a coordinate", text)))?;
+    match numbers[..] {
+        [lon, lat] => Ok(TrackPoint {
+            coord: Coord::new(lat, lon),
+            ..TrackPoint::default()
+        }),
+        [lon, lat, elevation, ..] => Ok(TrackPoint {
+            coord: Coord::new(lat, lon),
+            elevation: Some(elevation),
+            ..TrackPoint::default()
+        }),
+        _ => Err(KmlError::Invalid(format!("{} is not a coordinate", text))),
+    }
+}
+
+/// A CSS `#rrggbb` colour as KML's `aabbggrr`.
+fn kml_color(color: &str, alpha: u8) -> String {
+    let hex = color.trim_start_matches('#');
+    match (hex.get(0..2), hex.get(2..4), hex.get(4..6)) {
+        (Some(r), Some(g), Some(b)) => format!("{:02x}{}{}{}", alpha, b, g, r),
+        _ => format!("{:02x}ffffff", alpha),
+    }
+}
+
+fn coordinates(points: &[TrackPoint]) -> String {
+    points
+        .iter()
+        .map(|point| match point.elevation {
+            Some(elevation) => format!("{},{},{}", point.coord.lon, point.coord.lat, elevation),
+            None => format!("{},{}", point.coord.lon, point.coord.lat),
+        })
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn write_style(writer: &mut XmlWriter, id: &str, color: &str) {
+    write_event(writer, WriteEvent::start_element("Style").attr("id", id));
+    write_event(writer, WriteEvent::start_element("LineStyle"));
+    write_text_element(writer, "color", color);
+    write_text_element(writer, "width", "4");
+    write_event(writer, WriteEvent::end_element());
+    write_event(writer, WriteEvent::end_element());
+}
+
+fn write_placemark_start(
+    writer: &mut XmlWriter,
+    name: &Option<String>,
+    description: &Option<String>,
+    style: &str,
+) {
+    write_event(writer, WriteEvent::start_element("Placemark"));
+    if let Some(name) = name {
+        write_text_element(writer, "name", name);
+    }
+    if let Some(description) = description {
+        write_text_element(writer, "description", description);
+    }
+    write_text_element(writer, "styleUrl", style);
+}
+
+fn write_line_string(writer: &mut XmlWriter, points: &[TrackPoint]) {
+    write_event(writer, WriteEvent::start_element("LineString"));
+    write_text_element(writer, "coordinates", &coordinates(points));
+    write_event(writer, WriteEvent::end_element());
+}
+
+/// A segment whose points all have times, so it can be written as a `gx:Track`.
+fn write_gx_track(writer: &mut XmlWriter, points: &[TrackPoint]) {
+    write_event(writer, WriteEvent::start_element("gx:Track"));
+    points
+        .iter()
+        .filter_map(|point| point.time)
+        .for_each(|time| {
+            let time = time.format(&Rfc3339).unwrap_or_default();
+            write_text_element(writer, "when", &time);
+        });
+    points.iter().for_each(|point| {
+        let coord = format!(
+            "{} {} {}",
+            point.coord.lon,
+            point.coord.lat,
+            point.elevation.unwrap_or(0.0)
+        );
+        write_text_element(writer, "gx:coord", &coord);
+    });
+    write_event(writer, WriteEvent::end_element());
+}
+
+/// Serialise `document` as KML 2.2, drawing its lines in `color` (CSS `#rrggbb`). Tracks
+/// with a time on every point are written as `gx:Track` so the times are kept.
+pub fn write_kml(document: &TrackDocument, color: &str) -> Vec<u8> {
+    let mut writer = EmitterConfig::new()
+        .perform_indent(true)
+        .create_writer(Vec::new());
+    write_event(
+        &mut writer,
+        WriteEvent::start_element("kml")
+            .default_ns(KML_NS)
+            .ns("gx", GX_NS),
+    );
+    write_event(&mut writer, WriteEvent::start_element("Document"));
+    if let Some(name) = &document.name {
+        write_text_element(&mut writer, "name", name);
+    }
+    write_style(&mut writer, "track", &kml_color(color, 0xff));
+    // Routes are planned rather than ridden, so they are drawn fainter.
+    write_style(&mut writer, "route", &kml_color(color, 0x99));
+
+    document.tracks.iter().for_each(|track| {
+        write_placemark_start(&mut writer, &track.name, &track.description, "#track");
+        let timed = track.points().all(|point| point.time.is_some());
+        let multiple = track.segments.len() > 1;
+        if multiple {
+            let container = if timed {
+                "gx:MultiTrack"
+            } else {
+                "MultiGeometry"
+            };
+            write_event(&mut writer, WriteEvent::start_element(container));
+        }
+        track.segments.iter().for_each(|segment| match timed {
+            true => write_gx_track(&mut writer, &segment.points),
+            false => write_line_string(&mut writer, &segment.points),
+        });
+        if multiple {
+            write_event(&mut writer, WriteEvent::end_element());
+        }
+        write_event(&mut writer, WriteEvent::end_element());
+    });
+    document.routes.iter().for_each(|route| {
+        write_placemark_start(&mut writer, &route.name, &route.description, "#route");
+        write_line_string(&mut writer, &route.points);
+        write_event(&mut writer, WriteEvent::end_element());
+    });
+    document.waypoints.iter().for_each(|waypoint| {
+        write_event(&mut writer, WriteEvent::start_element("Placemark"));
+        if let Some(name) = &waypoint.name {
+            write_text_element(&mut writer, "name", name);
+        }
+        if let Some(description) = &waypoint.description {
+            write_text_element(&mut writer, "description", description);
+        }
+        write_event(&mut writer, WriteEvent::start_element("Point"));
+        let point = TrackPoint {
+            coord: waypoint.coord,
+            elevation: waypoint.elevation,
+            ..TrackPoint::default()
+        };
+        write_text_element(&mut writer, "coordinates", &coordinates(&[point]));
+        write_event(&mut writer, WriteEvent::end_element());
+        write_event(&mut writer, WriteEvent::end_element());
+    });
+
+    write_event(&mut writer, WriteEvent::end_element());
+    write_event(&mut writer, WriteEvent::end_element());
+    writer.into_inner()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::Write;
+    use time::macros::datetime;
+
+    /// Shaped like a Google My Maps export.
+    const MY_MAPS: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<kml xmlns="http://www.opengis.net/kml/2.2">
+  <Document>
+    <name>Weekend rides</name>
+    <Folder>
+      <name>Layer 1</name>
+      <Placemark>
+        <name>Gog Magog loop</name>
+        <description><![CDATA[Gravel, <b>muddy</b> in winter]]></description>
+        <styleUrl>#line-1267FF-5000</styleUrl>
+        <LineString>
+          <tessellate>1</tessellate>
+          <coordinates>
+            0.1218,52.2053,0
+            0.1300,52.2100,0
+            0.1400,52.2000,0
+          </coordinates>
+        </LineString>
+      </Placemark>
+      <Placemark>
+        <name>Cafe</name>
+        <Point><coordinates>0.125,52.207,0</coordinates></Point>
+      </Placemark>
+    </Folder>
+  </Document>
+</kml>"#;
+
+    const GX_TRACK: &str = r#"<kml xmlns="http://www.opengis.net/kml/2.2" xmlns:gx="http://www.google.com/kml/ext/2.2">
+  <Placemark>
+    <name>Recorded</name>
+    <gx:Track>
+      <when>2024-05-01T09:30:00Z</when>
+      <when>2024-05-01T09:30:05Z</when>
+      <gx:coord>0.1218 52.2053 12.5</gx:coord>
+      <gx:coord>0.1225 52.2058 13</gx:coord>
+    </gx:Track>
+  </Placemark>
+</kml>"#;
+
+    #[test]
+    fn test_read_kml() {
+        let document = read_kml(MY_MAPS.as_bytes()).unwrap();
+        assert_eq!(document.name.as_deref(), Some("Weekend rides"));
+        assert_eq!(document.tracks.len(), 1);
+        let track = &document.tracks[0];
+        assert_eq!(track.name.as_deref(), Some("Gog Magog loop"));
+        assert_eq!(
+            track.description.as_deref(),
+            Some("Gravel, <b>muddy</b> in winter")
+        );
+        assert_eq!(track.segments[0].points.len(), 3);
+        assert_eq!(
+            track.segments[0].points[0].coord,
+            Coord::new(52.2053, 0.1218)
+        );
+        assert_eq!(document.waypoints.len(), 1);
+        assert_eq!(document.waypoints[0].name.as_deref(), Some("Cafe"));
+    }
+
+    #[test]
+    fn test_read_gx_track() {
+        let document = read_kml(GX_TRACK.as_bytes()).unwrap();
+        let points = &document.tracks[0].segments[0].points;
+        assert_eq!(points.len(), 2);
+        assert_eq!(points[1].coord, Coord::new(52.2058, 0.1225));
+        assert_eq!(points[1].elevation, Some(13.0));
+        assert_eq!(points[1].time, Some(datetime!(2024-05-01 09:30:05 UTC)));
+    }
+
+    #[test]
+    fn test_read_kmz() {
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        let options = zip::write::FileOptions::default();
+        zip.start_file("images/icon.png", options).unwrap();
+        zip.write_all(b"not really a png").unwrap();
+        zip.start_file("doc.kml", options).unwrap();
+        zip.write_all(MY_MAPS.as_bytes()).unwrap();
+        let kmz = zip.finish().unwrap().into_inner();
+
+        let document = read_kmz(&kmz).unwrap();
+        assert_eq!(document.tracks[0].name.as_deref(), Some("Gog Magog loop"));
+        assert!(matches!(read_kmz(b"PK not a zip"), Err(KmlError::Kmz(_))));
+    }
+
+    #[test]
+    fn test_kml_round_trip() {
+        let mut document = crate::track::tests::sample_document();
+        document.waypoints.push(Waypoint {
+            coord: Coord::new(52.207, 0.125),
+            elevation: Some(10.0),
+            name: Some("Fish & Chips".to_string()),
+            ..Waypoint::default()
+        });
+        let kml = write_kml(&document, "#3388ff");
+        let text = String::from_utf8(kml.clone()).unwrap();
+        assert!(text.contains("<color>ffff8833</color>"));
+        assert!(text.contains("<LineString>"));
+
+        let read = read_kml(kml.as_slice()).unwrap();
+        assert_eq!(read.tracks[0].name, document.tracks[0].name);
+        assert_eq!(read.tracks[0].segments, document.tracks[0].segments);
+        assert_eq!(read.waypoints[0].name.as_deref(), Some("Fish & Chips"));
+        assert_eq!(read.waypoints[0].coord, Coord::new(52.207, 0.125));
+
+        // With a time on every point the track is written as a gx:Track, keeping the times.
+        let start = datetime!(2024-05-01 09:30 UTC);
+        document.tracks[0].segments[0]
+            .points
+            .iter_mut()
+            .enumerate()
+            .for_each(|(i, point)| point.time = Some(start + time::Duration::seconds(i as i64)));
+        let kml = write_kml(&document, "#3388ff");
+        assert!(String::from_utf8(kml.clone())
+            .unwrap()
+            .contains("<gx:Track>"));
+        let read = read_kml(kml.as_slice()).unwrap();
+        assert_eq!(read.tracks[0].segments, document.tracks[0].segments);
+    }
+
+    #[test]
+    fn test_read_kml_errors() {
+        assert!(matches!(
+            read_kml(b"<gpx version=\"1.1\"></gpx>".as_slice()),
+            Err(KmlError::Invalid(_))
+        ));
+        let bad = MY_MAPS.replace("0.1300,52.2100,0", "0.1300;52.2100");
+        assert!(matches!(
+            read_kml(bad.as_bytes()),
+            Err(KmlError::Invalid(_))
+        ));
+    }
+}
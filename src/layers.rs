@@ -0,0 +1,386 @@
+use std::rc::Rc;
+
+use log::error;
+use time::OffsetDateTime;
+use web_sys::HtmlSelectElement;
+use yew::prelude::*;
+
+use crate::export::{download, export_file_name, limit_points, write_gpx, GpxOutput, POINT_LIMITS};
+use crate::geo::SpatialIndex;
+use crate::geojson::write_geojson;
+use crate::kml::write_kml;
+use crate::privacy::PrivacySettings;
+use crate::track::{TrackDocument, TrackPointId};
+
+/// Line colours given to loaded files in turn, chosen to stay distinct on the OSM base map.
+const FILE_COLORS: [&str; 8] = [
+    "#3388ff", "#e6550d", "#31a354", "#756bb1", "#d62728", "#17becf", "#bcbd22", "#e377c2",
+];
+
+/// Edits of one file that can be undone; older ones are forgotten.
+const UNDO_LIMIT: usize = 50;
+
+/// One imported file, drawn in its own layer group.
+#[derive(Clone, Debug)]
+pub struct LoadedFile {
+    /// Unique for the session, so a removed file's id is never reused.
+    pub id: usize,
+    pub name: String,
+    pub color: &'static str,
+    pub visible: bool,
+    pub tracks: Rc<TrackDocument>,
+    /// Index of the points of `tracks`, for finding the one nearest a click.
+    pub points: Rc<SpatialIndex<TrackPointId>>,
+    /// Earlier versions of `tracks`, most recent last.
+    pub undo: Vec<Rc<TrackDocument>>,
+    /// Versions undone, most recently undone last.
+    pub redo: Vec<Rc<TrackDocument>>,
+}
+
+// Loaded tracks are never changed in place, so comparing the pointer is enough to tell
+// whether a file needs redrawing, and avoids walking every point on each render.
+impl PartialEq for LoadedFile {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+            && self.name == other.name
+            && self.color == other.color
+            && self.visible == other.visible
+            && Rc::ptr_eq(&self.tracks, &other.tracks)
+            && self.undo.len() == other.undo.len()
+            && self.redo.len() == other.redo.len()
+    }
+}
+
+/// Every loaded file, in the order they were opened.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct LoadedFiles {
+    pub files: Vec<LoadedFile>,
+    next_id: usize,
+    /// File the map should fit, and a count that changes on every request
+    /// so that zooming to the same file twice is still seen as a change.
+    pub focus: Option<(usize, u32)>,
+}
+
+pub enum FileAction {
+    Add(String, TrackDocument),
+    ToggleVisible(usize),
+    ZoomTo(usize),
+    Remove(usize),
+    /// Swap a file's contents for an edited copy, such as a cleaned one.
+    Replace(usize, TrackDocument),
+    Undo(usize),
+    Redo(usize),
+}
+
+impl LoadedFile {
+    /// Swap in `tracks`, returning the previous ones.
+    fn set_tracks(&mut self, tracks: Rc<TrackDocument>) -> Rc<TrackDocument> {
+        self.points = Rc::new(tracks.point_index());
+        std::mem::replace(&mut self.tracks, tracks)
+    }
+}
+
+impl LoadedFiles {
+    pub fn get(&self, id: usize) -> Option<&LoadedFile> {
+        self.files.iter().find(|file| file.id == id)
+    }
+
+    fn focus_on(&mut self, id: usize) {
+        let count = self.focus.map_or(0, |(_, count)| count.wrapping_add(1));
+        self.focus = Some((id, count));
+    }
+}
+
+impl Reducible for LoadedFiles {
+    type Action = FileAction;
+
+    fn reduce(self: Rc<Self>, action: FileAction) -> Rc<Self> {
+        let mut next = (*self).clone();
+        match action {
+            FileAction::Add(name, tracks) => {
+                let id = next.next_id;
+                next.next_id += 1;
+                next.files.push(LoadedFile {
+                    id,
+                    name,
+                    color: FILE_COLORS[id % FILE_COLORS.len()],
+                    visible: true,
+                    points: Rc::new(tracks.point_index()),
+                    tracks: Rc::new(tracks),
+                    undo: Vec::new(),
+                    redo: Vec::new(),
+                });
+                next.focus_on(id);
+            }
+            FileAction::ToggleVisible(id) => {
+                if let Some(file) = next.files.iter_mut().find(|file| file.id == id) {
+                    file.visible = !file.visible;
+                }
+            }
+            FileAction::ZoomTo(id) => {
+                if let Some(file) = next.files.iter_mut().find(|file| file.id == id) {
+                    // Zooming to a hidden file would show an empty map.
+                    file.visible = true;
+                    next.focus_on(id);
+                }
+            }
+            FileAction::Remove(id) => next.files.retain(|file| file.id != id),
+            FileAction::Replace(id, tracks) => {
+                if let Some(file) = next.files.iter_mut().find(|file| file.id == id) {
+                    let previous = file.set_tracks(Rc::new(tracks));
+                    file.undo.push(previous);
+                    if file.undo.len() > UNDO_LIMIT {
+                        file.undo.remove(0);
+                    }
+                    file.redo.clear();
+                }
+            }
+            FileAction::Undo(id) => {
+                if let Some(file) = next.files.iter_mut().find(|file| file.id == id) {
+                    if let Some(previous) = file.undo.pop() {
+                        let undone = file.set_tracks(previous);
+                        file.redo.push(undone);
+                    }
+                }
+            }
+            FileAction::Redo(id) => {
+                if let Some(file) = next.files.iter_mut().find(|file| file.id == id) {
+                    if let Some(undone) = file.redo.pop() {
+                        let redone = file.set_tracks(undone);
+                        file.undo.push(redone);
+                    }
+                }
+            }
+        }
+        Rc::new(next)
+    }
+}
+
+#[derive(Properties, PartialEq)]
+pub struct LayerListProps {
+    pub files: Vec<LoadedFile>,
+    pub on_action: Callback<FileAction>,
+    /// Called with a file's id to open the cleaning panel for it.
+    pub on_clean: Callback<usize>,
+    /// Zones whose points are left out of exported files.
+    pub privacy: PrivacySettings,
+}
+
+/// `file` without the points inside privacy zones, ready to leave the browser.
+fn shareable(file: &LoadedFile, privacy: &PrivacySettings) -> LoadedFile {
+    LoadedFile {
+        tracks: Rc::new(privacy.apply(&file.tracks)),
+        ..file.clone()
+    }
+}
+
+/// Offer `file` as a GPX download, with at most `max_points` points in each line if given.
+fn export_gpx(
+    file: &LoadedFile,
+    output: GpxOutput,
+    max_points: Option<usize>,
+) -> Result<(), String> {
+    let name = export_file_name(&file.name, "gpx");
+    let limited = max_points.map(|max_points| limit_points(&file.tracks, max_points));
+    let tracks = limited.as_ref().unwrap_or(&file.tracks);
+    write_gpx(tracks, output, OffsetDateTime::now_utc())
+        .map_err(|e| format!("{}", e))
+        .and_then(|bytes| {
+            download(&name, "application/gpx+xml", &bytes).map_err(|e| format!("{:?}", e))
+        })
+        .map_err(|e| format!("Could not export {}: {}", name, e))
+}
+
+/// Offer `file` as a GeoJSON download.
+fn export_geojson(file: &LoadedFile) -> Result<(), String> {
+    let name = export_file_name(&file.name, "geojson");
+    let geojson = write_geojson(&file.tracks);
+    download(&name, "application/geo+json", geojson.as_bytes())
+        .map_err(|e| format!("Could not export {}: {:?}", name, e))
+}
+
+/// Offer `file` as a KML download, styled in the file's colour.
+fn export_kml(file: &LoadedFile) -> Result<(), String> {
+    let name = export_file_name(&file.name, "kml");
+    let kml = write_kml(&file.tracks, file.color);
+    download(&name, "application/vnd.google-earth.kml+xml", &kml)
+        .map_err(|e| format!("Could not export {}: {:?}", name, e))
+}
+
+/// Sidebar listing the loaded files, with controls to show, zoom to, export, clean and remove
+/// each one.
+#[function_component(LayerList)]
+pub fn layer_list(props: &LayerListProps) -> Html {
+    let output = use_state(|| GpxOutput::AsLoaded);
+    let max_points = use_state(|| None::<usize>);
+    // Why the last export failed, shown until the next one succeeds.
+    let export_error = use_state(|| None::<String>);
+    if props.files.is_empty() {
+        return html! {};
+    }
+    let on_output_change = {
+        let output = output.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            if let Some(&picked) = GpxOutput::ALL.get(select.selected_index() as usize) {
+                output.set(picked);
+            }
+        })
+    };
+    let on_max_points_change = {
+        let max_points = max_points.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            // The first option is no limit.
+            let index = select.selected_index() as usize;
+            max_points.set(
+                index
+                    .checked_sub(1)
+                    .and_then(|i| POINT_LIMITS.get(i).copied()),
+            );
+        })
+    };
+    let report = {
+        let export_error = export_error.clone();
+        move |result: Result<(), String>| {
+            if let Err(e) = &result {
+                error!("{}", e);
+            }
+            export_error.set(result.err());
+        }
+    };
+    let export = |file: &LoadedFile| {
+        let (file, privacy, report) = (file.clone(), props.privacy.clone(), report.clone());
+        let (output, max_points) = (*output, *max_points);
+        Callback::from(move |_: MouseEvent| {
+            report(export_gpx(&shareable(&file, &privacy), output, max_points))
+        })
+    };
+    let export_with = |file: &LoadedFile, export: fn(&LoadedFile) -> Result<(), String>| {
+        let (file, privacy, report) = (file.clone(), props.privacy.clone(), report.clone());
+        Callback::from(move |_: MouseEvent| report(export(&shareable(&file, &privacy))))
+    };
+    let action = |make: fn(usize) -> FileAction, id: usize| {
+        let on_action = props.on_action.clone();
+        Callback::from(move |_: MouseEvent| on_action.emit(make(id)))
+    };
+    let clean = |id: usize| props.on_clean.reform(move |_: MouseEvent| id);
+    html! {
+        <>
+        <label class="export-output">
+            { "Export lines as " }
+            <select onchange={on_output_change}>
+                { for GpxOutput::ALL.iter().map(|option| html! {
+                    <option selected={*option == *output}>{ option.label() }</option>
+                }) }
+            </select>
+        </label>
+        <label class="export-output">
+            { "with at most " }
+            <select onchange={on_max_points_change}>
+                <option selected={max_points.is_none()}>{ "any number of" }</option>
+                { for POINT_LIMITS.iter().map(|limit| html! {
+                    <option selected={*max_points == Some(*limit)}>{ limit }</option>
+                }) }
+            </select>
+            { " points per line" }
+        </label>
+        <ul class="layer-list">
+            { for props.files.iter().map(|file| html! {
+                <li key={file.id}>
+                    <input
+                        type="checkbox"
+                        title="Show on map"
+                        checked={file.visible}
+                        onclick={action(FileAction::ToggleVisible, file.id)}
+                    />
+                    <span class="layer-swatch" style={format!("background: {}", file.color)}></span>
+                    <span class="layer-name">{ &file.name }</span>
+                    <button onclick={action(FileAction::ZoomTo, file.id)}>{ "Zoom to" }</button>
+                    <button onclick={export(file)}>{ "Export GPX" }</button>
+                    <button onclick={export_with(file, export_kml)}>{ "Export KML" }</button>
+                    <button onclick={export_with(file, export_geojson)}>{ "Export GeoJSON" }</button>
+                    <button onclick={clean(file.id)}>{ "Clean" }</button>
+                    <button
+                        onclick={action(FileAction::Undo, file.id)}
+                        disabled={file.undo.is_empty()}
+                    >{ "Undo" }</button>
+                    <button
+                        onclick={action(FileAction::Redo, file.id)}
+                        disabled={file.redo.is_empty()}
+                    >{ "Redo" }</button>
+                    <button onclick={action(FileAction::Remove, file.id)}>{ "Remove" }</button>
+                </li>
+            }) }
+        </ul>
+        if let Some(error) = &*export_error {
+            <p class="export-error">{ error }</p>
+        }
+        </>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::tests::sample_document;
+
+    #[test]
+    fn test_loaded_files() {
+        let files = Rc::new(LoadedFiles::default())
+            .reduce(FileAction::Add("a.gpx".to_string(), sample_document()))
+            .reduce(FileAction::Add(
+                "b.gpx".to_string(),
+                TrackDocument::default(),
+            ));
+        assert_eq!(files.files.len(), 2);
+        assert_ne!(files.files[0].color, files.files[1].color);
+        assert_eq!(files.focus, Some((1, 1)));
+
+        let files = files
+            .reduce(FileAction::ToggleVisible(0))
+            .reduce(FileAction::Remove(1));
+        assert_eq!(files.files.len(), 1);
+        assert!(!files.get(0).unwrap().visible);
+        assert!(files.get(1).is_none());
+
+        // Zooming to a hidden file shows it again, and asking twice is still a new request.
+        let files = files.reduce(FileAction::ZoomTo(0));
+        assert!(files.get(0).unwrap().visible);
+        assert_eq!(files.focus, Some((0, 2)));
+        assert_eq!(
+            files.clone().reduce(FileAction::ZoomTo(0)).focus,
+            Some((0, 3))
+        );
+
+        // Ids are not reused after a removal.
+        let files = files.reduce(FileAction::Add(
+            "c.gpx".to_string(),
+            TrackDocument::default(),
+        ));
+        assert_eq!(files.files[1].id, 2);
+
+        // Replacing a file's tracks keeps its place, colour and id.
+        let colour = files.get(0).unwrap().color;
+        let files = files.reduce(FileAction::Replace(0, TrackDocument::default()));
+        let replaced = files.get(0).unwrap();
+        assert!(replaced.tracks.is_empty());
+        assert_eq!((replaced.color, files.files[0].id), (colour, 0));
+
+        // Replacing can be undone and redone, and a new edit forgets what was undone.
+        let files = files.reduce(FileAction::Undo(0));
+        assert_eq!(*files.get(0).unwrap().tracks, sample_document());
+        assert_eq!(
+            files.get(0).unwrap().points.len(),
+            sample_document().point_index().len()
+        );
+        let files = files.reduce(FileAction::Redo(0));
+        assert!(files.get(0).unwrap().tracks.is_empty());
+        assert!(files.get(0).unwrap().points.is_empty());
+        let files = files
+            .reduce(FileAction::Undo(0))
+            .reduce(FileAction::Replace(0, TrackDocument::default()));
+        let file = files.get(0).unwrap();
+        assert_eq!((file.undo.len(), file.redo.len()), (1, 0));
+    }
+}
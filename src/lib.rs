@@ -0,0 +1,31 @@
+//! WasMyRoute: the map app, its file importers and exporters, and the track model they share.
+//! The app binary renders [`app::App`]; the import worker binary parses files off the main
+//! thread with the same code.
+
+pub mod app;
+pub mod clean;
+pub mod clean_panel;
+pub mod columns;
+pub mod csv;
+pub mod edit;
+pub mod edit_panel;
+pub mod export;
+pub mod extensions;
+pub mod fit;
+pub mod geo;
+pub mod geojson;
+pub mod kml;
+pub mod layers;
+pub mod map;
+pub mod model;
+pub mod nmea;
+pub mod osm;
+pub mod position;
+pub mod privacy;
+pub mod privacy_panel;
+pub mod progress;
+pub mod route;
+pub mod tcx;
+pub mod track;
+pub mod worker;
+pub mod xml_writer;
@@ -1,13 +1,5 @@
-mod geo;
-mod map;
-mod model;
-mod route;
-
-mod app;
-
-use app::App;
 use log::{info, Level};
-use model::Model;
+use wasmyroute::app::App;
 
 fn main() {
     use wasm_bindgen_test::wasm_bindgen_test_configure;
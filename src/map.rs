@@ -1,24 +1,53 @@
-use gpx::Gpx;
-use leaflet::{LatLng, LayerGroup, Map, MapOptions, Polyline, PolylineOptions, TileLayer};
+use leaflet::{
+    Circle, CircleMarker, CircleOptions, LatLng, LayerGroup, Map, MapOptions, Marker, PathOptions,
+    Polyline, PolylineOptions, TileLayer,
+};
 use log::info;
 use web_sys::js_sys::Array;
+use web_sys::wasm_bindgen::JsValue;
 use yew::prelude::*;
 
-use crate::geo::Coord;
-use crate::Model;
+use crate::clean_panel::CleanPreview;
+use crate::geo::tiles::{metres_per_pixel, OSM_TILE_URL};
+use crate::geo::{douglas_peucker, Coord};
+use crate::layers::{LoadedFile, LoadedFiles};
+use crate::model::Model;
+use crate::privacy::PrivacyZone;
+use crate::track::{TrackPointId, Waypoint};
+
+/// How near, in pixels, a click must be to a track point to select it.
+const CLICK_TOLERANCE: f64 = 12.0;
 
 #[derive(Properties, PartialEq)]
 pub struct MainMapProps {
     pub pos: Coord,
-    pub gpx: Gpx,
+    pub files: LoadedFiles,
+    /// Cleaning result to draw over the files.
+    #[prop_or_default]
+    pub preview: Option<CleanPreview>,
+    /// Called with the file id and position of the track point nearest a click on a track.
+    #[prop_or_default]
+    pub on_track_click: Callback<(usize, TrackPointId)>,
+    /// Points to highlight, such as those selected for editing.
+    #[prop_or_default]
+    pub selected: Vec<Coord>,
+    #[prop_or_default]
+    pub zones: Vec<PrivacyZone>,
 }
 
 #[function_component(MainMap)]
 pub fn main_map(props: &MainMapProps) -> Html {
     info!("1 Rendering MainMap, props.pos {:?}", props.pos);
     let model_state = use_state(Model::default);
+    // The files currently drawn, each with its layer group, shared with the zoom handler so it can
+    // redraw them at a new level of detail.
+    let drawn_files = use_mut_ref(Vec::<(LoadedFile, LayerGroup)>::new);
+    // The map's click handler is registered once, so it reads the latest callback from here.
+    let on_track_click = use_mut_ref(Callback::<(usize, TrackPointId)>::default);
+    *on_track_click.borrow_mut() = props.on_track_click.clone();
     {
         let model = model_state.clone();
+        let drawn_files = drawn_files.clone();
         let pos = props.pos;
         // use_effect_with hook with empty dependencies ensure this effect runs only once.
         use_effect_with((), move |_| {
@@ -30,17 +59,54 @@ pub fn main_map(props: &MainMapProps) -> Html {
             options.set_zoom(1.0);
             let map = Map::new("map", &options);
 
-            let gpx_lg = LayerGroup::new();
-            gpx_lg.add_to(&map);
             let position_lg = LayerGroup::new();
             position_lg.add_to(&map);
 
             add_tile_layer(&map);
 
+            let preview_lg = LayerGroup::new();
+            preview_lg.add_to(&map);
+            let selection_lg = LayerGroup::new();
+            selection_lg.add_to(&map);
+            let zones_lg = LayerGroup::new();
+            zones_lg.add_to(&map);
+
+            // Redraw tracks simplified to suit the new zoom level.
+            {
+                let zoomed_map = map.clone();
+                let drawn_files = drawn_files.clone();
+                map.on_zoom_end(Box::new(move |_| {
+                    drawn_files
+                        .borrow()
+                        .iter()
+                        .filter(|(file, _)| file.visible)
+                        .for_each(|(file, gpx_lg)| draw_gpx_tracks(&zoomed_map, gpx_lg, file));
+                }));
+            }
+
+            // Select the track point nearest a click, if it is near enough.
+            {
+                let clicked_map = map.clone();
+                let drawn_files = drawn_files.clone();
+                map.on_mouse_click(Box::new(move |e| {
+                    let latlng = e.lat_lng();
+                    let coord = Coord::new(latlng.lat(), latlng.lng());
+                    let tolerance =
+                        CLICK_TOLERANCE * metres_per_pixel(coord.lat, clicked_map.get_zoom());
+                    let drawn_files = drawn_files.borrow();
+                    let files = drawn_files.iter().map(|(file, _)| file);
+                    if let Some(clicked) = nearest_track_point(files, &coord, tolerance) {
+                        on_track_click.borrow().emit(clicked);
+                    }
+                }));
+            }
+
             let mut new_model = (*model).clone();
             new_model.map = Some(map);
             new_model.position_lg = Some(position_lg);
-            new_model.gpx_lg = Some(gpx_lg);
+            new_model.preview_lg = Some(preview_lg);
+            new_model.selection_lg = Some(selection_lg);
+            new_model.zones_lg = Some(zones_lg);
             let zoom: u8 = 18;
             new_model.zoomlevel = zoom;
             model.set(new_model);
@@ -49,38 +115,81 @@ pub fn main_map(props: &MainMapProps) -> Html {
         });
     }
     {
-        let pos = props.pos;
         let model = model_state.clone();
-        let new_gpx = props.gpx.clone();
-        use_effect_with((pos, new_gpx.clone()), move |_| {
+        use_effect_with(props.pos, move |pos| {
             info!("5 use_effect - borrowing Map...");
-            let mut new_model = (*model).clone();
-            new_model.gpx = Some(new_gpx);
-            info!("6. check gpx: {:?}", new_model.clone().gpx.unwrap());
-            pan_to_position(&model, pos);
-            draw_gpx_route(&new_model);
-            //FIXME the map state is not updated correctly after draw_gpx_route. It is emptied as the clone of model state is not up-to-date.
-            // model.set(new_model);
+            pan_to_position(&model, *pos);
+            || {}
+        });
+    }
+    {
+        let model = model_state.clone();
+        let files = props.files.clone();
+        let drawn_files = drawn_files.clone();
+        use_effect_with(files.files.clone(), move |_| {
+            if let Some(map) = &model.map {
+                sync_file_layers(map, &mut drawn_files.borrow_mut(), &files);
+            }
+            || {}
+        });
+    }
+    {
+        let model = model_state.clone();
+        let files = props.files.clone();
+        use_effect_with(files.focus, move |focus| {
+            let file = focus.and_then(|(id, _)| files.get(id));
+            if let (Some(map), Some(file)) = (&model.map, file) {
+                if let Some(bbox) = file.tracks.bbox() {
+                    map.fly_to_bounds(&bbox.into());
+                }
+            }
+            || {}
+        });
+    }
+    {
+        let model = model_state.clone();
+        use_effect_with(props.preview.clone(), move |preview| {
+            if let Some(preview_lg) = &model.preview_lg {
+                draw_clean_preview(preview_lg, preview.as_ref());
+            }
+            || {}
+        });
+    }
+    {
+        let model = model_state.clone();
+        use_effect_with(props.selected.clone(), move |selected| {
+            if let Some(selection_lg) = &model.selection_lg {
+                draw_selection(selection_lg, selected);
+            }
+            || {}
+        });
+    }
+    {
+        let model = model_state.clone();
+        // Zones loaded from storage arrive before the map exists, so draw them once it does.
+        let ready = model.zones_lg.is_some();
+        use_effect_with((props.zones.clone(), ready), move |(zones, _)| {
+            if let Some(zones_lg) = &model.zones_lg {
+                draw_privacy_zones(zones_lg, zones);
+            }
             || {}
         });
     }
     html! {
     <>
         <div id="map"></div>
-        <div>{ format!("pos: {:?}", props.pos) }</div>
     </>
     }
 }
 
 fn add_tile_layer(map: &Map) {
-    TileLayer::new("https://{s}.tile.openstreetmap.org/{z}/{x}/{y}.png").add_to(map);
+    TileLayer::new(OSM_TILE_URL).add_to(map);
 }
 
 pub fn pan_to_position(model: &Model, position: Coord) {
     info!("pan_to_position...");
-    if model.map.is_some() {
+    if let Some(map) = &model.map {
         let zoom: u8 = model.zoomlevel;
-        let map = model.map.as_ref().unwrap();
         // map.set_view(&position.into(), zoom.into());
         map.fly_to(&position.into(), zoom.into());
     } else {
@@ -88,28 +197,259 @@ pub fn pan_to_position(model: &Model, position: Coord) {
     }
 }
 
-pub fn draw_gpx_route(model: &Model) {
-    info!("draw_gpx_route...");
-    if let (Some(map), Some(gpx_lg)) = (&model.map, &model.gpx_lg) {
-        gpx_lg.clear_layers();
-        info!("gpx layer group cleared");
-
-        if let Some(model_gpx) = &model.gpx {
-            model_gpx.tracks.iter().for_each(|track| {
-                track.segments.iter().for_each(|segment| {
-                    let latlngs = segment.points.iter().fold(Array::new(), |acc, point| {
-                        acc.push(&LatLng::new(point.point().y(), point.point().x()));
-                        acc
-                    });
-                    let gpx_route =
-                        &Polyline::new_with_options(&latlngs, &PolylineOptions::default());
-                    gpx_lg.add_layer(gpx_route);
-                    map.fly_to_bounds(&gpx_route.get_bounds());
-                    gpx_lg.add_to(map);
-                });
-            });
-        } else {
-            info!("draw_gpx_route: map or gpx_lg is None");
+/// Bring the layer groups on `map` in line with `files`: remove groups for files that were
+/// removed, draw new or changed files, and show or hide each group.
+fn sync_file_layers(
+    map: &Map,
+    drawn_files: &mut Vec<(LoadedFile, LayerGroup)>,
+    files: &LoadedFiles,
+) {
+    let mut previous = std::mem::take(drawn_files);
+    previous
+        .iter()
+        .filter(|(drawn, _)| files.get(drawn.id).is_none())
+        .for_each(|(_, gpx_lg)| {
+            gpx_lg.remove();
+        });
+    files.files.iter().for_each(|file| {
+        let existing = previous
+            .iter()
+            .position(|(drawn, _)| drawn.id == file.id)
+            .map(|i| previous.swap_remove(i));
+        let gpx_lg = match existing {
+            Some((drawn, gpx_lg)) => {
+                if drawn != *file {
+                    draw_gpx_route(map, &gpx_lg, file);
+                }
+                gpx_lg
+            }
+            None => {
+                let gpx_lg = LayerGroup::new();
+                draw_gpx_route(map, &gpx_lg, file);
+                gpx_lg
+            }
+        };
+        drawn_files.push((file.clone(), gpx_lg));
+    });
+}
+
+/// Draw `file` into its layer group and show or hide the group to match `file.visible`.
+pub fn draw_gpx_route(map: &Map, gpx_lg: &LayerGroup, file: &LoadedFile) {
+    info!("draw_gpx_route: {}", file.name);
+    if file.visible {
+        draw_gpx_tracks(map, gpx_lg, file);
+        gpx_lg.add_to(map);
+    } else {
+        gpx_lg.remove();
+    }
+}
+
+/// Replace the contents of `gpx_lg` with `file`, simplified to about one pixel at the current zoom.
+/// Tracks and routes are drawn in the file's colour, routes dashed, and waypoints as markers with a popup.
+fn draw_gpx_tracks(map: &Map, gpx_lg: &LayerGroup, file: &LoadedFile) {
+    gpx_lg.clear_layers();
+    info!("gpx layer group cleared");
+    let tolerance = metres_per_pixel(map.get_center().lat(), map.get_zoom());
+    let simplified = |coords: &[Coord]| {
+        douglas_peucker(coords, tolerance)
+            .into_iter()
+            .fold(Array::new(), |acc, i| {
+                acc.push(&LatLng::from(coords[i]));
+                acc
+            })
+    };
+    let tracks = &file.tracks;
+    tracks.tracks.iter().for_each(|track| {
+        track.segments.iter().for_each(|segment| {
+            let options = PolylineOptions::default();
+            options.set_color(file.color.to_string());
+            let gpx_route = &Polyline::new_with_options(&simplified(&segment.coords()), &options);
+            gpx_lg.add_layer(gpx_route);
+        });
+    });
+    tracks.routes.iter().for_each(|route| {
+        let coords: Vec<Coord> = route.points.iter().map(|point| point.coord).collect();
+        let options = PolylineOptions::default();
+        options.set_color(file.color.to_string());
+        options.set_dash_array("8 6".to_string());
+        let gpx_route = &Polyline::new_with_options(&simplified(&coords), &options);
+        gpx_lg.add_layer(gpx_route);
+    });
+    tracks.waypoints.iter().for_each(|waypoint| {
+        let marker = Marker::new(&waypoint.coord.into());
+        marker.bind_popup_with_options(
+            &JsValue::from_str(&waypoint_popup(waypoint)),
+            &JsValue::UNDEFINED,
+        );
+        gpx_lg.add_layer(&marker);
+    });
+}
+
+/// Replace the contents of `preview_lg` with the cleaned tracks of `preview`, drawn in black
+/// over the file, and a red dot where each removed point was.
+fn draw_clean_preview(preview_lg: &LayerGroup, preview: Option<&CleanPreview>) {
+    preview_lg.clear_layers();
+    let Some(CleanPreview(cleaned)) = preview else {
+        return;
+    };
+    cleaned.document.tracks.iter().for_each(|track| {
+        track.segments.iter().for_each(|segment| {
+            let options = PolylineOptions::default();
+            options.set_color("#000".to_string());
+            options.set_weight(2.0);
+            let coords = segment.coords().into_iter().map(LatLng::from);
+            preview_lg.add_layer(&Polyline::new_with_options(&coords.collect(), &options));
+        });
+    });
+    let options = PathOptions::default();
+    options.set_color("#d62728".to_string());
+    options.set_fill_opacity(1.0);
+    cleaned.removed.iter().for_each(|coord| {
+        let marker = CircleMarker::new_with_options(&(*coord).into(), &options);
+        marker.set_radius(3.0);
+        preview_lg.add_layer(&marker);
+    });
+}
+
+/// Replace the contents of `selection_lg` with a ring around each of `selected`.
+fn draw_selection(selection_lg: &LayerGroup, selected: &[Coord]) {
+    selection_lg.clear_layers();
+    let options = PathOptions::default();
+    options.set_color("#000".to_string());
+    options.set_fill_color("#fff".to_string());
+    options.set_fill_opacity(1.0);
+    options.set_weight(3.0);
+    selected.iter().for_each(|coord| {
+        let marker = CircleMarker::new_with_options(&(*coord).into(), &options);
+        marker.set_radius(6.0);
+        selection_lg.add_layer(&marker);
+    });
+}
+
+/// Replace the contents of `zones_lg` with a dashed grey circle for each of `zones`. The
+/// circles let clicks through, so tracks inside them can still be selected.
+fn draw_privacy_zones(zones_lg: &LayerGroup, zones: &[PrivacyZone]) {
+    zones_lg.clear_layers();
+    zones.iter().for_each(|zone| {
+        let options = CircleOptions::default();
+        options.set_radius(zone.radius);
+        options.set_color("#555".to_string());
+        options.set_dash_array("4 4".to_string());
+        options.set_fill_opacity(0.1);
+        options.set_interactive(false);
+        zones_lg.add_layer(&Circle::new_with_options(&zone.centre.into(), &options));
+    });
+}
+
+/// The track point of a visible file nearest `coord`, if one is within `tolerance` metres,
+/// with the id of its file.
+pub fn nearest_track_point<'a>(
+    files: impl IntoIterator<Item = &'a LoadedFile>,
+    coord: &Coord,
+    tolerance: f64,
+) -> Option<(usize, TrackPointId)> {
+    files
+        .into_iter()
+        .filter(|file| file.visible)
+        .filter_map(|file| {
+            let (distance, id) = file.points.nearest(coord, 1).into_iter().next()?;
+            Some((distance, (file.id, *id)))
+        })
+        .filter(|(distance, _)| *distance <= tolerance)
+        .min_by(|(a, _), (b, _)| a.total_cmp(b))
+        .map(|(_, clicked)| clicked)
+}
+
+/// Popup HTML for a waypoint: its name, description, symbol and elevation, whichever are present.
+fn waypoint_popup(waypoint: &Waypoint) -> String {
+    let mut lines = Vec::new();
+    if let Some(name) = &waypoint.name {
+        lines.push(format!("<b>{}</b>", escape_html(name)));
+    }
+    if let Some(description) = &waypoint.description {
+        lines.push(escape_html(description));
+    }
+    if let Some(symbol) = &waypoint.symbol {
+        lines.push(format!("Symbol: {}", escape_html(symbol)));
+    }
+    if let Some(elevation) = waypoint.elevation {
+        lines.push(format!("Elevation: {:.0} m", elevation));
+    }
+    if lines.is_empty() {
+        lines.push(format!(
+            "{:.5}, {:.5}",
+            waypoint.coord.lat, waypoint.coord.lon
+        ));
+    }
+    lines.join("<br>")
+}
+
+/// Text from a GPX file is untrusted, so escape it before Leaflet sets it as popup HTML.
+fn escape_html(text: &str) -> String {
+    text.chars().fold(String::new(), |mut acc, c| {
+        match c {
+            '&' => acc.push_str("&amp;"),
+            '<' => acc.push_str("&lt;"),
+            '>' => acc.push_str("&gt;"),
+            '"' => acc.push_str("&quot;"),
+            '\'' => acc.push_str("&#39;"),
+            c => acc.push(c),
         }
+        acc
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::layers::FileAction;
+
+    #[test]
+    fn test_waypoint_popup() {
+        let waypoint = Waypoint {
+            coord: Coord::new(52.2053, 0.1218),
+            elevation: Some(12.4),
+            name: Some("Fish & Chips".to_string()),
+            description: Some("<script>alert(1)</script>".to_string()),
+            symbol: Some("Restaurant".to_string()),
+            ..Waypoint::default()
+        };
+        assert_eq!(
+            waypoint_popup(&waypoint),
+            "<b>Fish &amp; Chips</b><br>&lt;script&gt;alert(1)&lt;/script&gt;<br>Symbol: Restaurant<br>Elevation: 12 m"
+        );
+        let unnamed = Waypoint {
+            coord: Coord::new(52.2053, 0.1218),
+            ..Waypoint::default()
+        };
+        assert_eq!(waypoint_popup(&unnamed), "52.20530, 0.12180");
+    }
+
+    #[test]
+    fn test_nearest_track_point() {
+        let files = std::rc::Rc::new(LoadedFiles::default())
+            .reduce(FileAction::Add(
+                "a.gpx".to_string(),
+                crate::track::tests::sample_document(),
+            ))
+            .reduce(FileAction::Add(
+                "b.gpx".to_string(),
+                crate::track::tests::sample_document(),
+            ))
+            .reduce(FileAction::ToggleVisible(0));
+        let first = files.get(1).unwrap().tracks.tracks[0].segments[0].points[0].coord;
+        let near = first.destination(90.0, 5.0);
+
+        // The hidden file is skipped, even though its point is as near.
+        let expected = TrackPointId {
+            track: 0,
+            segment: 0,
+            point: 0,
+        };
+        assert_eq!(
+            nearest_track_point(&files.files, &near, 10.0),
+            Some((1, expected))
+        );
+        assert_eq!(nearest_track_point(&files.files, &near, 1.0), None);
     }
 }
@@ -2,8 +2,11 @@
 pub struct Model {
     pub zoomlevel: u8,
     pub map: Option<leaflet::Map>,
-    pub gpx: Option<gpx::Gpx>,
     pub position_lg: Option<leaflet::LayerGroup>,
-    pub gpx_lg: Option<leaflet::LayerGroup>,
+    /// Edits previewed before they are applied, drawn over the files.
+    pub preview_lg: Option<leaflet::LayerGroup>,
+    /// Track points selected for editing.
+    pub selection_lg: Option<leaflet::LayerGroup>,
+    pub zones_lg: Option<leaflet::LayerGroup>,
 }
 impl Model {}
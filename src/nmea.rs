@@ -0,0 +1,322 @@
+//! NMEA 0183 reader for logs from standalone GPS receivers. Position fixes from `GGA` and
+//! `RMC` sentences become the points of one track, which starts a new segment whenever the
+//! receiver reports that it has lost its fix.
+
+use core::fmt;
+
+use serde::{Deserialize, Serialize};
+use time::{Date, Month, PrimitiveDateTime, Time};
+
+use crate::geo::Coord;
+use crate::progress::Progress;
+use crate::track::{Segment, Track, TrackDocument, TrackPoint};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum NmeaError {
+    /// A sentence's checksum does not match its content. Lines count from 1.
+    Checksum { line: usize },
+    /// A sentence has a field that cannot be read.
+    Invalid { line: usize, message: String },
+}
+
+impl fmt::Display for NmeaError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            NmeaError::Checksum { line } => {
+                write!(f, "NMEA sentence at line {} has a bad checksum", line)
+            }
+            NmeaError::Invalid { line, message } => {
+                write!(
+                    f,
+                    "NMEA sentence at line {} is not valid ({})",
+                    line, message
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for NmeaError {}
+
+/// A position fix, merged from every sentence reporting the same time of day.
+struct Fix {
+    time: Time,
+    date: Option<Date>,
+    coord: Coord,
+    elevation: Option<f64>,
+    /// The receiver lost its fix between the previous one and this.
+    after_gap: bool,
+}
+
+/// What one `GGA` or `RMC` sentence says.
+enum Sentence {
+    Fix {
+        time: Time,
+        date: Option<Date>,
+        coord: Coord,
+        elevation: Option<f64>,
+    },
+    NoFix,
+}
+
+/// Whether `text` looks like an NMEA log: its first line is a sentence such as `$GPGGA`.
+pub fn is_nmea(text: &str) -> bool {
+    let first = text.trim_start().lines().next().unwrap_or_default();
+    first.starts_with('$')
+        && first
+            .get(1..6)
+            .is_some_and(|address| address.bytes().all(|b| b.is_ascii_alphanumeric()))
+        && first.get(6..7) == Some(",")
+}
+
+pub fn read_nmea(text: &str) -> Result<TrackDocument, NmeaError> {
+    read_nmea_with_progress(text, &mut |_| {})
+}
+
+/// [`read_nmea`], passing the fraction of `text` read so far to `on_progress`.
+pub fn read_nmea_with_progress(
+    text: &str,
+    on_progress: &mut dyn FnMut(f64),
+) -> Result<TrackDocument, NmeaError> {
+    let mut progress = Progress::new(text.len(), on_progress);
+    let mut position = 0;
+    let mut fixes: Vec<Fix> = Vec::new();
+    let mut lost = false;
+    for (i, line) in text.split_inclusive('\n').enumerate() {
+        position += line.len();
+        progress.update(position);
+        let Some(sentence) = read_sentence(line.trim(), i + 1)? else {
+            continue;
+        };
+        match sentence {
+            Sentence::NoFix => lost = true,
+            Sentence::Fix {
+                time,
+                date,
+                coord,
+                elevation,
+            } => match fixes.last_mut() {
+                // GGA and RMC sentences for the same moment describe one fix.
+                Some(last) if last.time == time => {
+                    last.date = last.date.or(date);
+                    last.elevation = last.elevation.or(elevation);
+                }
+                _ => {
+                    fixes.push(Fix {
+                        time,
+                        date,
+                        coord,
+                        elevation,
+                        after_gap: lost,
+                    });
+                    lost = false;
+                }
+            },
+        }
+    }
+
+    let mut segments: Vec<Segment> = Vec::new();
+    let mut date = fixes.iter().find_map(|fix| fix.date);
+    let mut previous: Option<Time> = None;
+    for fix in &fixes {
+        // GGA has no date, so carry on from the last RMC, rolling over at midnight.
+        match fix.date {
+            Some(fix_date) => date = Some(fix_date),
+            None if previous.is_some_and(|previous| fix.time < previous) => {
+                date = date.and_then(|date| date.next_day());
+            }
+            None => {}
+        }
+        previous = Some(fix.time);
+        if fix.after_gap || segments.is_empty() {
+            segments.push(Segment::default());
+        }
+        segments.last_mut().unwrap().points.push(TrackPoint {
+            coord: fix.coord,
+            elevation: fix.elevation,
+            time: date.map(|date| PrimitiveDateTime::new(date, fix.time).assume_utc()),
+            ..TrackPoint::default()
+        });
+    }
+    segments.retain(|segment| !segment.points.is_empty());
+
+    let mut document = TrackDocument::default();
+    if !segments.is_empty() {
+        document.tracks.push(Track {
+            segments,
+            ..Track::default()
+        });
+    }
+    Ok(document)
+}
+
+/// Read a `GGA` or `RMC` sentence, or `None` for any other line.
+fn read_sentence(line: &str, number: usize) -> Result<Option<Sentence>, NmeaError> {
+    let Some(body) = line.strip_prefix('$') else {
+        return Ok(None);
+    };
+    let body = match body.rsplit_once('*') {
+        Some((body, checksum)) => {
+            let expected = u8::from_str_radix(checksum.trim(), 16)
+                .map_err(|_| NmeaError::Checksum { line: number })?;
+            if body.bytes().fold(0, |sum, b| sum ^ b) != expected {
+                return Err(NmeaError::Checksum { line: number });
+            }
+            body
+        }
+        None => body,
+    };
+    let fields: Vec<&str> = body.split(',').collect();
+    let invalid = |message: &str| NmeaError::Invalid {
+        line: number,
+        message: message.to_string(),
+    };
+    // Skip the two-letter talker, such as GP for GPS or GN for several constellations.
+    let kind = fields[0].get(2..).unwrap_or_default();
+    let field = |i: usize| fields.get(i).copied().unwrap_or_default();
+    let sentence = match kind {
+        "GGA" => {
+            if matches!(field(6), "" | "0") {
+                return Ok(Some(Sentence::NoFix));
+            }
+            let elevation = match field(9) {
+                "" => None,
+                altitude => Some(
+                    altitude
+                        .parse()
+                        .map_err(|_| invalid("altitude is not a number"))?,
+                ),
+            };
+            Sentence::Fix {
+                time: time_of_day(field(1)).ok_or_else(|| invalid("bad time"))?,
+                date: None,
+                coord: position(fields.get(2..6).unwrap_or_default())
+                    .ok_or_else(|| invalid("bad position"))?,
+                elevation,
+            }
+        }
+        "RMC" => {
+            if field(2) != "A" {
+                return Ok(Some(Sentence::NoFix));
+            }
+            Sentence::Fix {
+                time: time_of_day(field(1)).ok_or_else(|| invalid("bad time"))?,
+                date: Some(date(field(9)).ok_or_else(|| invalid("bad date"))?),
+                coord: position(fields.get(3..7).unwrap_or_default())
+                    .ok_or_else(|| invalid("bad position"))?,
+                elevation: None,
+            }
+        }
+        _ => return Ok(None),
+    };
+    Ok(Some(sentence))
+}
+
+/// `hhmmss.sss` in UTC.
+fn time_of_day(field: &str) -> Option<Time> {
+    let (whole, fraction) = field.split_once('.').unwrap_or((field, ""));
+    if whole.len() != 6 || !whole.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| whole[range].parse::<u8>().ok();
+    let nanoseconds = match fraction {
+        "" => 0.0,
+        fraction => format!("0.{}", fraction).parse::<f64>().ok()? * 1e9,
+    };
+    Time::from_hms_nano(
+        number(0..2)?,
+        number(2..4)?,
+        number(4..6)?,
+        nanoseconds as u32,
+    )
+    .ok()
+}
+
+/// `ddmmyy`, taking two-digit years from 1980 to 2079 as GPS receivers do.
+fn date(field: &str) -> Option<Date> {
+    if field.len() != 6 || !field.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let number = |range: std::ops::Range<usize>| field[range].parse::<u8>().ok();
+    let year = match number(4..6)? as i32 {
+        year if year >= 80 => 1900 + year,
+        year => 2000 + year,
+    };
+    let month = Month::try_from(number(2..4)?).ok()?;
+    Date::from_calendar_date(year, month, number(0..2)?).ok()
+}
+
+/// Latitude `ddmm.mmmm`, `N` or `S`, then longitude `dddmm.mmmm`, `E` or `W`.
+fn position(fields: &[&str]) -> Option<Coord> {
+    let angle = |value: &str, negative: &str, positive: &str, hemisphere: &str| {
+        let value: f64 = value.parse().ok()?;
+        let degrees = (value / 100.0).trunc();
+        let angle = degrees + (value - degrees * 100.0) / 60.0;
+        match hemisphere {
+            h if h == positive => Some(angle),
+            h if h == negative => Some(-angle),
+            _ => None,
+        }
+    };
+    let lat = angle(fields.first()?, "S", "N", fields.get(1)?)?;
+    let lon = angle(fields.get(2)?, "W", "E", fields.get(3)?)?;
+    ((-90.0..=90.0).contains(&lat) && (-180.0..=180.0).contains(&lon)).then(|| Coord::new(lat, lon))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    const LOG: &str = "\
+$GPGGA,235958.00,5212.3000,N,00007.5000,E,1,08,0.9,12.5,M,47.0,M,,*5D
+$GPRMC,235958.00,A,5212.3000,N,00007.5000,E,5.0,90.0,300424,,,A*66
+$GPGSV,3,1,11,01,40,083,46,02,17,308,41,12,07,344,39,14,22,228,45*7C
+$GPGGA,235959.00,5212.3010,N,00007.5100,E,1,08,0.9,12.8,M,47.0,M,,*51
+$GPGGA,000000.00,,,,,0,00,,,M,,M,,*48
+$GPGGA,000001.50,5212.3020,S,00007.5200,W,1,08,0.9,13.0,M,47.0,M,,*52
+";
+
+    #[test]
+    fn test_read_nmea() {
+        assert!(is_nmea(LOG));
+        let document = read_nmea(LOG).unwrap();
+        let track = &document.tracks[0];
+        assert_eq!(track.segments.len(), 2);
+
+        let first = &track.segments[0].points;
+        assert_eq!(first.len(), 2);
+        let coord = first[0].coord;
+        assert!((coord.lat - 52.205).abs() < 1e-9, "{:?}", coord);
+        assert!((coord.lon - 0.125).abs() < 1e-9, "{:?}", coord);
+        assert_eq!(first[0].elevation, Some(12.5));
+        assert_eq!(first[0].time, Some(datetime!(2024-04-30 23:59:58 UTC)));
+        assert_eq!(first[1].time, Some(datetime!(2024-04-30 23:59:59 UTC)));
+
+        let after_gap = &track.segments[1].points[0];
+        assert!(after_gap.coord.lat < 0.0 && after_gap.coord.lon < 0.0);
+        assert_eq!(after_gap.time, Some(datetime!(2024-05-01 00:00:01.5 UTC)));
+    }
+
+    #[test]
+    fn test_read_nmea_without_date() {
+        let log = "$GPGGA,120000,5212.3000,N,00007.5000,E,1,08,0.9,12.5,M,47.0,M,,";
+        let document = read_nmea(log).unwrap();
+        assert_eq!(document.tracks[0].segments[0].points[0].time, None);
+        assert!(read_nmea("$GPGSV,3,1,11\n").unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_read_nmea_errors() {
+        let bad_checksum = "$GPGGA,235958.00,5212.3000,N,00007.5000,E,1,08,0.9,12.5,M,47.0,M,,*00";
+        assert_eq!(
+            read_nmea(&format!("\n{}", bad_checksum)),
+            Err(NmeaError::Checksum { line: 2 })
+        );
+        assert!(matches!(
+            read_nmea("$GPRMC,235958.00,A,5212.3000,N,00007.5000,E,5.0,90.0,310224,,"),
+            Err(NmeaError::Invalid { line: 1, .. })
+        ));
+        assert!(!is_nmea("lat,lon\n52.2,0.1"));
+    }
+}
@@ -1,11 +1,41 @@
+use std::collections::HashMap;
+
 use serde::Deserialize;
 
-#[derive(Debug, Deserialize)]
+use crate::geo::{Coord, SpatialIndex};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(from = "OsmFile")]
 pub struct OsmDocument {
+    nodes: Vec<OsmNode>,
+    pub ways: Vec<OsmWay>,
+    /// Position of each node in `nodes`, by id, as ways refer to nodes by id.
+    node_ids: HashMap<String, usize>,
+}
+
+/// An OSM extract as written, before its nodes are indexed.
+#[derive(Deserialize)]
+struct OsmFile {
     #[serde(rename = "node", default)]
-    pub nodes: Vec<OsmNode>,
+    nodes: Vec<OsmNode>,
     #[serde(rename = "way", default)]
-    pub ways: Vec<OsmWay>,
+    ways: Vec<OsmWay>,
+}
+
+impl From<OsmFile> for OsmDocument {
+    fn from(file: OsmFile) -> Self {
+        let node_ids = file
+            .nodes
+            .iter()
+            .enumerate()
+            .map(|(i, node)| (node.id.clone(), i))
+            .collect();
+        OsmDocument {
+            nodes: file.nodes,
+            ways: file.ways,
+            node_ids,
+        }
+    }
 }
 
 #[derive(Debug, Deserialize)]
@@ -38,33 +68,70 @@ pub struct OsmTag {
 
 impl OsmDocument {
     pub fn new() -> OsmDocument {
-        OsmDocument {
-            nodes: vec![],
-            ways: vec![],
-        }
+        OsmDocument::default()
     }
 
-    fn node(&self, id: &str) -> &OsmNode {
-        self.nodes
-            .iter()
-            .find(|node| node.id == id)
-            .unwrap_or_else(|| panic!("Didn't find a node with id {}", id))
+    pub fn nodes(&self) -> &[OsmNode] {
+        &self.nodes
+    }
+
+    /// The node with `id`, if the extract has it.
+    pub fn node(&self, id: &str) -> Option<&OsmNode> {
+        self.node_ids.get(id).map(|&i| &self.nodes[i])
+    }
+
+    /// Spatial index over all nodes, for nearest-node and radius lookups.
+    pub fn node_index(&self) -> SpatialIndex<&OsmNode> {
+        SpatialIndex::new(
+            self.nodes
+                .iter()
+                .map(|node| (Coord::new(node.lat, node.lon), node))
+                .collect(),
+        )
     }
 }
 
 impl OsmWay {
-    pub fn points<'a>(&'a self, osm: &'a OsmDocument) -> Vec<&OsmNode> {
+    /// The way's nodes, in order. Extracts cut at a boundary keep ways that cross it but not
+    /// the nodes outside, so those are skipped.
+    pub fn points<'a>(&'a self, osm: &'a OsmDocument) -> Vec<&'a OsmNode> {
         self.nds
             .iter()
-            .map(move |nd| osm.node(&nd.node_ref))
+            .filter_map(move |nd| osm.node(&nd.node_ref))
             .collect()
     }
 
-    pub fn start<'a>(&'a self, osm: &'a OsmDocument) -> Option<&OsmNode> {
+    pub fn start<'a>(&'a self, osm: &'a OsmDocument) -> Option<&'a OsmNode> {
         self.points(osm).first().copied()
     }
 
-    pub fn end<'a>(&'a self, osm: &'a OsmDocument) -> Option<&OsmNode> {
+    pub fn end<'a>(&'a self, osm: &'a OsmDocument) -> Option<&'a OsmNode> {
         self.points(osm).last().copied()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_way_points() {
+        let osm: OsmDocument = serde_json::from_str(
+            r#"{
+                "node": [
+                    {"id": "1", "lat": 52.2, "lon": 0.12},
+                    {"id": "2", "lat": 52.3, "lon": 0.13}
+                ],
+                "way": [{"id": "10", "nd": [{"ref": "1"}, {"ref": "3"}, {"ref": "2"}]}]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(osm.node("2").map(|node| node.lat), Some(52.3));
+        assert!(osm.node("3").is_none());
+
+        let way = &osm.ways[0];
+        let ids: Vec<_> = way.points(&osm).iter().map(|node| &node.id).collect();
+        assert_eq!(ids, ["1", "2"]);
+        assert_eq!(way.end(&osm).map(|node| node.id.as_str()), Some("2"));
+    }
+}
@@ -0,0 +1,112 @@
+use web_sys::HtmlSelectElement;
+use yew::prelude::*;
+
+use crate::geo::Coord;
+
+/// Ways of writing a position that the user can choose between.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum CoordFormat {
+    Decimal,
+    Dms,
+    OsGrid,
+    Utm,
+    Mgrs,
+    PlusCode,
+}
+
+impl CoordFormat {
+    const ALL: [CoordFormat; 6] = [
+        CoordFormat::Decimal,
+        CoordFormat::Dms,
+        CoordFormat::OsGrid,
+        CoordFormat::Utm,
+        CoordFormat::Mgrs,
+        CoordFormat::PlusCode,
+    ];
+
+    fn label(&self) -> &'static str {
+        match self {
+            CoordFormat::Decimal => "Decimal degrees",
+            CoordFormat::Dms => "Degrees, minutes, seconds",
+            CoordFormat::OsGrid => "OS grid reference",
+            CoordFormat::Utm => "UTM",
+            CoordFormat::Mgrs => "MGRS",
+            CoordFormat::PlusCode => "Plus Code",
+        }
+    }
+
+    pub fn format(&self, pos: &Coord) -> String {
+        let result = match self {
+            CoordFormat::Decimal => Ok(format!("{:.6}, {:.6}", pos.lat, pos.lon)),
+            CoordFormat::Dms => Ok(format!(
+                "{} {}",
+                dms(pos.lat, 'N', 'S'),
+                dms(pos.lon, 'E', 'W')
+            )),
+            CoordFormat::OsGrid => pos.to_os_grid_ref(10),
+            CoordFormat::Utm => pos.to_utm().map(|utm| utm.to_string()),
+            CoordFormat::Mgrs => pos.to_mgrs(10),
+            CoordFormat::PlusCode => Ok(pos.to_plus_code()),
+        };
+        result.unwrap_or_else(|e| format!("Not available: {}", e))
+    }
+}
+
+fn dms(degrees: f64, positive: char, negative: char) -> String {
+    let hemisphere = if degrees < 0.0 { negative } else { positive };
+    let total_seconds = (degrees.abs() * 3600.0 * 10.0).round() / 10.0;
+    let d = (total_seconds / 3600.0).floor();
+    let m = ((total_seconds - d * 3600.0) / 60.0).floor();
+    let s = total_seconds - d * 3600.0 - m * 60.0;
+    format!("{}°{:02}′{:04.1}″{}", d, m, s, hemisphere)
+}
+
+#[derive(Properties, PartialEq)]
+pub struct PositionDisplayProps {
+    pub pos: Coord,
+}
+
+/// Current position, written in the format picked from a drop-down.
+#[function_component(PositionDisplay)]
+pub fn position_display(props: &PositionDisplayProps) -> Html {
+    let format = use_state(|| CoordFormat::Decimal);
+    let onchange = {
+        let format = format.clone();
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            if let Some(&picked) = CoordFormat::ALL.get(select.selected_index() as usize) {
+                format.set(picked);
+            }
+        })
+    };
+    html! {
+        <div class="position">
+            <select id="coord-format" {onchange}>
+                { for CoordFormat::ALL.iter().map(|f| html! {
+                    <option selected={*f == *format}>{ f.label() }</option>
+                }) }
+            </select>
+            <span>{ format.format(&props.pos) }</span>
+        </div>
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_format() {
+        let pos = Coord::new(52.135072, 0.129808);
+        assert_eq!(CoordFormat::Decimal.format(&pos), "52.135072, 0.129808");
+        assert_eq!(CoordFormat::Dms.format(&pos), "52°08′06.3″N 0°07′47.3″E");
+        assert!(CoordFormat::OsGrid.format(&pos).starts_with("TL "));
+        assert!(CoordFormat::OsGrid
+            .format(&Coord::new(48.85, 2.35))
+            .starts_with("Not available"));
+        assert_eq!(
+            CoordFormat::Dms.format(&Coord::new(-33.8688, 151.2093)),
+            "33°52′07.7″S 151°12′33.5″E"
+        );
+    }
+}
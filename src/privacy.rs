@@ -0,0 +1,410 @@
+//! Privacy zones: circles around places such as home, whose points are taken out of files
+//! before they are exported, so a shared ride doesn't lead back to someone's front door.
+//! The zones are kept in the browser's local storage and never leave it.
+
+use gloo_utils::window;
+use log::error;
+use serde::{Deserialize, Serialize};
+
+use crate::geo::Coord;
+use crate::track::{Route, Segment, TrackDocument, TrackPoint};
+
+/// Local storage key for [`PrivacySettings`].
+const STORAGE_KEY: &str = "wasmyroute.privacy";
+
+/// Halvings of a line crossing a zone's edge when finding where it crosses; plenty for
+/// centimetres on any line between two recorded points.
+const EDGE_STEPS: u32 = 40;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct PrivacyZone {
+    pub name: String,
+    pub centre: Coord,
+    /// Metres.
+    pub radius: f64,
+}
+
+impl PrivacyZone {
+    pub fn contains(&self, coord: &Coord) -> bool {
+        self.centre.haversine_distance(coord) <= self.radius
+    }
+}
+
+/// What happens to a line where it enters a zone.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub enum ZoneMode {
+    /// The points inside are removed, so the line stops at the last point recorded outside.
+    #[default]
+    Remove,
+    /// The line is cut where it crosses the zone's edge.
+    Truncate,
+}
+
+impl ZoneMode {
+    pub const ALL: [ZoneMode; 2] = [ZoneMode::Remove, ZoneMode::Truncate];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ZoneMode::Remove => "Remove points inside",
+            ZoneMode::Truncate => "Cut lines at the edge",
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PrivacySettings {
+    pub zones: Vec<PrivacyZone>,
+    pub mode: ZoneMode,
+}
+
+impl PrivacySettings {
+    /// The settings saved in local storage, or none if nothing was saved.
+    pub fn load() -> PrivacySettings {
+        let saved = window()
+            .local_storage()
+            .ok()
+            .flatten()
+            .and_then(|storage| storage.get_item(STORAGE_KEY).ok().flatten());
+        saved
+            .and_then(|json| match serde_json::from_str(&json) {
+                Ok(settings) => Some(settings),
+                Err(e) => {
+                    error!("Ignoring unreadable privacy zones: {}", e);
+                    None
+                }
+            })
+            .unwrap_or_default()
+    }
+
+    /// Keep the settings in local storage for the next visit.
+    pub fn save(&self) {
+        let storage = match window().local_storage() {
+            Ok(Some(storage)) => storage,
+            _ => {
+                error!("Local storage is unavailable; privacy zones will not be kept");
+                return;
+            }
+        };
+        let json = serde_json::to_string(self).expect("privacy settings are plain data");
+        if let Err(e) = storage.set_item(STORAGE_KEY, &json) {
+            error!("Could not save privacy zones: {:?}", e);
+        }
+    }
+
+    fn contains(&self, coord: &Coord) -> bool {
+        self.zones.iter().any(|zone| zone.contains(coord))
+    }
+
+    /// A copy of `document` with nothing inside a zone: tracks and routes are split where
+    /// they pass through one, and waypoints inside are dropped.
+    pub fn apply(&self, document: &TrackDocument) -> TrackDocument {
+        let mut document = document.clone();
+        if self.zones.is_empty() {
+            return document;
+        }
+        for track in &mut document.tracks {
+            track.segments = track
+                .segments
+                .iter()
+                .flat_map(|segment| self.outside(&segment.points))
+                .map(|points| Segment { points })
+                .collect();
+        }
+        document.tracks.retain(|track| !track.segments.is_empty());
+        document.routes = document
+            .routes
+            .iter()
+            .flat_map(|route| {
+                self.outside(&route.points).into_iter().map(|points| Route {
+                    points,
+                    ..route.clone()
+                })
+            })
+            .collect();
+        document
+            .waypoints
+            .retain(|waypoint| !self.contains(&waypoint.coord));
+        document
+    }
+
+    /// The runs of `points` outside every zone, cut at the zones' edges if truncating.
+    fn outside(&self, points: &[TrackPoint]) -> Vec<Vec<TrackPoint>> {
+        let touched = points.iter().any(|point| self.contains(&point.coord))
+            || points
+                .windows(2)
+                .any(|pair| !self.crossings(&pair[0], &pair[1]).is_empty());
+        if !touched {
+            return vec![points.to_vec()];
+        }
+        let truncate = self.mode == ZoneMode::Truncate;
+        let contains = |coord: &Coord| self.contains(coord);
+        let mut runs = Vec::new();
+        let mut run = Vec::new();
+        for (i, point) in points.iter().enumerate() {
+            let previous = i.checked_sub(1).map(|i| &points[i]);
+            let inside = self.contains(&point.coord);
+            if let Some(previous) = previous {
+                if inside != self.contains(&previous.coord) {
+                    if truncate {
+                        let (from, to) = if inside {
+                            (previous, point)
+                        } else {
+                            (point, previous)
+                        };
+                        run.push(edge(contains, from, to));
+                    }
+                } else if !inside {
+                    // Both ends are outside, but the line between them may still cross a zone.
+                    for (entry, exit) in self.crossings(previous, point) {
+                        if truncate {
+                            run.push(entry);
+                        }
+                        runs.push(std::mem::take(&mut run));
+                        if truncate {
+                            run.push(exit);
+                        }
+                    }
+                }
+            }
+            if inside {
+                if !run.is_empty() {
+                    runs.push(std::mem::take(&mut run));
+                }
+            } else {
+                run.push(point.clone());
+            }
+        }
+        if !run.is_empty() {
+            runs.push(run);
+        }
+        // A lone point left beside a zone is not a line, and would only mark where it is.
+        runs.retain(|run| run.len() > 1);
+        runs
+    }
+
+    /// Where the line from `a` to `b`, both outside every zone, enters and leaves the zones
+    /// it passes through, in order along it.
+    fn crossings(&self, a: &TrackPoint, b: &TrackPoint) -> Vec<(TrackPoint, TrackPoint)> {
+        let mut crossings: Vec<(f64, TrackPoint, TrackPoint)> = self
+            .zones
+            .iter()
+            .filter_map(|zone| {
+                let closest = closest_approach(&a.coord, &b.coord, &zone.centre);
+                let middle = interpolate(a, b, closest);
+                if !zone.contains(&middle.coord) {
+                    return None;
+                }
+                let contains = |coord: &Coord| zone.contains(coord);
+                Some((
+                    closest,
+                    edge(contains, a, &middle),
+                    edge(contains, b, &middle),
+                ))
+            })
+            .collect();
+        crossings.sort_by(|x, y| x.0.total_cmp(&y.0));
+        crossings
+            .into_iter()
+            .map(|(_, entry, exit)| (entry, exit))
+            .collect()
+    }
+}
+
+/// The point where the line from `outside` to `inside` enters the area where `contains` holds.
+fn edge(
+    contains: impl Fn(&Coord) -> bool,
+    outside: &TrackPoint,
+    inside: &TrackPoint,
+) -> TrackPoint {
+    let (mut low, mut high) = (0.0, 1.0);
+    for _ in 0..EDGE_STEPS {
+        let middle = (low + high) / 2.0;
+        match contains(&interpolate(outside, inside, middle).coord) {
+            true => high = middle,
+            false => low = middle,
+        }
+    }
+    interpolate(outside, inside, low)
+}
+
+/// The fraction of the way from `a` to `b` that passes closest to `centre`, treating the
+/// short line between them as straight on a flat map around `centre`.
+fn closest_approach(a: &Coord, b: &Coord, centre: &Coord) -> f64 {
+    let scale = centre.lat.to_radians().cos();
+    let (x, y) = ((a.lon - centre.lon) * scale, a.lat - centre.lat);
+    let (dx, dy) = ((b.lon - a.lon) * scale, b.lat - a.lat);
+    let length = dx * dx + dy * dy;
+    if length == 0.0 {
+        return 0.0;
+    }
+    (-(x * dx + y * dy) / length).clamp(0.0, 1.0)
+}
+
+/// The point `fraction` of the way from `a` to `b`, with its elevation and time in proportion.
+/// Lines between recorded points are short, so the coordinates are interpolated linearly.
+fn interpolate(a: &TrackPoint, b: &TrackPoint, fraction: f64) -> TrackPoint {
+    let between = |a: f64, b: f64| a + (b - a) * fraction;
+    TrackPoint {
+        coord: Coord::new(
+            between(a.coord.lat, b.coord.lat),
+            between(a.coord.lon, b.coord.lon),
+        ),
+        elevation: a.elevation.zip(b.elevation).map(|(a, b)| between(a, b)),
+        time: a.time.zip(b.time).map(|(a, b)| a + (b - a) * fraction),
+        ..TrackPoint::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::track::{Track, Waypoint};
+
+    const HOME: Coord = Coord {
+        lat: 52.2,
+        lon: 0.12,
+    };
+
+    /// A ride leaving home heading north, 20 m between points.
+    fn ride() -> TrackDocument {
+        let points = (0..10)
+            .map(|i| TrackPoint {
+                coord: HOME.destination(0.0, i as f64 * 20.0),
+                elevation: Some(10.0 + i as f64),
+                ..TrackPoint::default()
+            })
+            .collect();
+        TrackDocument {
+            tracks: vec![Track {
+                segments: vec![Segment { points }],
+                ..Track::default()
+            }],
+            waypoints: vec![Waypoint {
+                coord: HOME,
+                name: Some("Home".to_string()),
+                ..Waypoint::default()
+            }],
+            ..TrackDocument::default()
+        }
+    }
+
+    fn settings(mode: ZoneMode) -> PrivacySettings {
+        PrivacySettings {
+            zones: vec![PrivacyZone {
+                name: "Home".to_string(),
+                centre: HOME,
+                radius: 50.0,
+            }],
+            mode,
+        }
+    }
+
+    #[test]
+    fn test_remove() {
+        let stripped = settings(ZoneMode::Remove).apply(&ride());
+        let points: Vec<_> = stripped.tracks[0].points().collect();
+        // Points 0 to 2 are within 50 m.
+        assert_eq!(points.len(), 7);
+        assert!(points
+            .iter()
+            .all(|point| HOME.haversine_distance(&point.coord) > 50.0));
+        assert!(stripped.waypoints.is_empty());
+
+        assert_eq!(PrivacySettings::default().apply(&ride()), ride());
+
+        // Lines nowhere near a zone are kept whole, however short.
+        let mut document = ride();
+        document.tracks[0].segments.push(Segment {
+            points: vec![TrackPoint {
+                coord: HOME.destination(90.0, 1000.0),
+                ..TrackPoint::default()
+            }],
+        });
+        let stripped = settings(ZoneMode::Remove).apply(&document);
+        assert_eq!(stripped.tracks[0].segments.len(), 2);
+        assert_eq!(
+            stripped.tracks[0].segments[1],
+            document.tracks[0].segments[1]
+        );
+    }
+
+    #[test]
+    fn test_truncate() {
+        let stripped = settings(ZoneMode::Truncate).apply(&ride());
+        let points: Vec<_> = stripped.tracks[0].points().collect();
+        assert_eq!(points.len(), 8);
+        assert!((HOME.haversine_distance(&points[0].coord) - 50.0).abs() < 0.01);
+        assert!((points[0].elevation.unwrap() - 12.5).abs() < 0.01);
+        assert_eq!(points[1], &ride().tracks[0].segments[0].points[3]);
+    }
+
+    #[test]
+    fn test_pass_through() {
+        // Riding past home splits the track, and a route passing it is split likewise.
+        let mut document = ride();
+        let past = HOME.destination(180.0, 100.0);
+        let points = &mut document.tracks[0].segments[0].points;
+        points.splice(
+            0..0,
+            (0..5).map(|i| TrackPoint {
+                coord: past.destination(0.0, i as f64 * 20.0),
+                ..TrackPoint::default()
+            }),
+        );
+        document.routes.push(Route {
+            name: Some("Commute".to_string()),
+            points: points.clone(),
+            ..Route::default()
+        });
+
+        let stripped = settings(ZoneMode::Remove).apply(&document);
+        assert_eq!(stripped.tracks[0].segments.len(), 2);
+        assert_eq!(stripped.routes.len(), 2);
+        assert_eq!(stripped.routes[1].name.as_deref(), Some("Commute"));
+    }
+
+    #[test]
+    fn test_cross_between_points() {
+        // Two route points either side of home, with nothing recorded inside the zone.
+        let document = TrackDocument {
+            routes: vec![Route {
+                points: [180.0, 0.0]
+                    .map(|bearing| TrackPoint {
+                        coord: HOME.destination(bearing, 100.0),
+                        ..TrackPoint::default()
+                    })
+                    .to_vec(),
+                ..Route::default()
+            }],
+            ..TrackDocument::default()
+        };
+
+        let truncated = settings(ZoneMode::Truncate).apply(&document);
+        assert_eq!(truncated.routes.len(), 2);
+        let ends = [
+            &truncated.routes[0].points[1],
+            &truncated.routes[1].points[0],
+        ];
+        ends.iter().for_each(|point| {
+            assert!((HOME.haversine_distance(&point.coord) - 50.0).abs() < 0.01);
+        });
+        assert_eq!(truncated.routes[0].points[0], document.routes[0].points[0]);
+        assert_eq!(truncated.routes[1].points[1], document.routes[0].points[1]);
+
+        // Without the edges, what is left of each side is a lone point.
+        assert!(settings(ZoneMode::Remove)
+            .apply(&document)
+            .routes
+            .is_empty());
+    }
+
+    #[test]
+    fn test_settings_round_trip() {
+        let settings = settings(ZoneMode::Truncate);
+        let json = serde_json::to_string(&settings).unwrap();
+        assert_eq!(
+            serde_json::from_str::<PrivacySettings>(&json).unwrap(),
+            settings
+        );
+    }
+}
@@ -0,0 +1,125 @@
+use web_sys::{HtmlInputElement, HtmlSelectElement};
+use yew::prelude::*;
+
+use crate::geo::Coord;
+use crate::privacy::{PrivacySettings, PrivacyZone, ZoneMode};
+
+/// Radius offered for a new zone, in metres.
+const DEFAULT_RADIUS: f64 = 200.0;
+
+#[derive(Properties, PartialEq)]
+pub struct PrivacyPanelProps {
+    pub settings: PrivacySettings,
+    pub on_change: Callback<PrivacySettings>,
+    /// The device's position, offered as the centre of a new zone.
+    pub pos: Coord,
+    /// The track point selected on the map, also offered as a centre.
+    pub selected: Option<Coord>,
+}
+
+/// List of privacy zones with controls to add and remove them, and to choose how exports
+/// leave them out.
+#[function_component(PrivacyPanel)]
+pub fn privacy_panel(props: &PrivacyPanelProps) -> Html {
+    let name = use_state(String::new);
+    let radius = use_state(|| DEFAULT_RADIUS);
+    let on_name_input = {
+        let name = name.clone();
+        Callback::from(move |e: InputEvent| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            name.set(input.value());
+        })
+    };
+    let on_radius_change = {
+        let radius = radius.clone();
+        Callback::from(move |e: Event| {
+            let input: HtmlInputElement = e.target_unchecked_into();
+            let value = input.value_as_number();
+            if value.is_finite() && value > 0.0 {
+                radius.set(value);
+            }
+        })
+    };
+    let change = |change: Box<dyn Fn(&mut PrivacySettings)>| {
+        let (settings, on_change) = (props.settings.clone(), props.on_change.clone());
+        Callback::from(move |_: MouseEvent| {
+            let mut changed = settings.clone();
+            change(&mut changed);
+            on_change.emit(changed);
+        })
+    };
+    let add_at = |centre: Coord| {
+        let zone_name = match name.trim() {
+            "" => format!("Zone {}", props.settings.zones.len() + 1),
+            named => named.to_string(),
+        };
+        let (name, radius) = (name.clone(), *radius);
+        let add = change(Box::new(move |settings| {
+            settings.zones.push(PrivacyZone {
+                name: zone_name.clone(),
+                centre,
+                radius,
+            })
+        }));
+        Callback::from(move |e: MouseEvent| {
+            add.emit(e);
+            name.set(String::new());
+        })
+    };
+    let on_mode_change = {
+        let (settings, on_change) = (props.settings.clone(), props.on_change.clone());
+        Callback::from(move |e: Event| {
+            let select: HtmlSelectElement = e.target_unchecked_into();
+            if let Some(&mode) = ZoneMode::ALL.get(select.selected_index() as usize) {
+                on_change.emit(PrivacySettings {
+                    mode,
+                    ..settings.clone()
+                });
+            }
+        })
+    };
+
+    html! {
+        <div class="privacy-panel">
+            <h3>{ "Privacy zones" }</h3>
+            <p>{ "Exported files leave out everything inside these circles. \
+                They are kept in this browser only." }</p>
+            <ul>
+                { for props.settings.zones.iter().enumerate().map(|(i, zone)| html! {
+                    <li>
+                        { format!("{}: {:.0} m around {:.5}, {:.5}",
+                            zone.name, zone.radius, zone.centre.lat, zone.centre.lon) }
+                        <button onclick={change(Box::new(move |settings| {
+                            settings.zones.remove(i);
+                        }))}>{ "Remove" }</button>
+                    </li>
+                }) }
+            </ul>
+            <label>
+                { "Name" }
+                <input type="text" value={(*name).clone()} oninput={on_name_input}/>
+            </label>
+            <label>
+                { "Radius (m)" }
+                <input
+                    type="number"
+                    min="1"
+                    value={radius.to_string()}
+                    onchange={on_radius_change}
+                />
+            </label>
+            <button onclick={add_at(props.pos)}>{ "Add at my position" }</button>
+            if let Some(selected) = props.selected {
+                <button onclick={add_at(selected)}>{ "Add at selected point" }</button>
+            }
+            <label>
+                { "In exports " }
+                <select onchange={on_mode_change}>
+                    { for ZoneMode::ALL.iter().map(|mode| html! {
+                        <option selected={*mode == props.settings.mode}>{ mode.label() }</option>
+                    }) }
+                </select>
+            </label>
+        </div>
+    }
+}
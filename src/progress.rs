@@ -0,0 +1,79 @@
+//! Reporting how far an import has got through its file, so a large one can show a progress
+//! bar. Parsers that take a reader are given a [`ProgressReader`]; those that walk a slice
+//! themselves keep a [`Progress`] up to date.
+
+use std::io::Read;
+
+/// Passes the fraction of a file read so far to `on_progress` each time it passes another
+/// whole percent.
+pub struct Progress<'a> {
+    total: usize,
+    percent: usize,
+    on_progress: &'a mut dyn FnMut(f64),
+}
+
+impl<'a> Progress<'a> {
+    /// Progress through a file of `total` bytes.
+    pub fn new(total: usize, on_progress: &'a mut dyn FnMut(f64)) -> Self {
+        Progress {
+            total,
+            percent: 0,
+            on_progress,
+        }
+    }
+
+    /// Note that the first `position` bytes have been read.
+    pub fn update(&mut self, position: usize) {
+        // In floating point, as `position * 100` could overflow on 32-bit targets.
+        let percent = (position as f64 * 100.0 / self.total.max(1) as f64) as usize;
+        if percent > self.percent {
+            self.percent = percent;
+            (self.on_progress)(percent as f64 / 100.0);
+        }
+    }
+}
+
+/// Reads a byte slice, reporting its progress through it.
+pub struct ProgressReader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+    progress: Progress<'a>,
+}
+
+impl<'a> ProgressReader<'a> {
+    pub fn new(bytes: &'a [u8], on_progress: &'a mut dyn FnMut(f64)) -> Self {
+        ProgressReader {
+            bytes,
+            position: 0,
+            progress: Progress::new(bytes.len(), on_progress),
+        }
+    }
+}
+
+impl Read for ProgressReader<'_> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        let read = (&self.bytes[self.position..]).read(buf)?;
+        self.position += read;
+        self.progress.update(self.position);
+        Ok(read)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_progress_reader() {
+        let bytes = vec![0; 1000];
+        let mut fractions = Vec::new();
+        let mut on_progress = |fraction| fractions.push(fraction);
+        let mut reader = ProgressReader::new(&bytes, &mut on_progress);
+        let mut buf = [0; 15];
+        while reader.read(&mut buf).unwrap() > 0 {}
+        // Reads of 1.5% report each whole percent passed, once.
+        assert_eq!(fractions.len(), 67);
+        assert_eq!(fractions[..3], [0.01, 0.03, 0.04]);
+        assert_eq!(fractions.last(), Some(&1.0));
+    }
+}
@@ -1,23 +1,90 @@
 use gpx::{read, Gpx};
-use js_sys::JsString;
-use log::{error, info};
+use log::{error, info, warn};
+use xml::common::Position;
 
 use core::fmt;
+use serde::{Deserialize, Serialize};
 use std::rc::Rc;
 use web_sys::{
+    js_sys::{Array, Uint8Array},
     wasm_bindgen::{closure::Closure, JsCast, JsValue},
-    Event, File, FileList, FileReader, HtmlInputElement,
+    ClipboardEvent, DragEvent, Element, Event, File, FileList, FileReader, HtmlInputElement,
 };
 use yew::prelude::*;
-pub struct GpxFile;
+
+use crate::columns::ColumnDialog;
+use crate::csv::{is_csv_header, ColumnMapping, CsvError, CsvTable};
+use crate::extensions::add_point_extensions;
+use crate::fit::{read_fit_with_progress, FitError};
+use crate::geojson::{read_geojson_from, GeoJsonError};
+use crate::kml::{read_kml, unzip_kmz, KmlError};
+use crate::nmea::{is_nmea, read_nmea_with_progress, NmeaError};
+use crate::progress::ProgressReader;
+use crate::tcx::{read_tcx, TcxError};
+use crate::track::TrackDocument;
+use crate::worker::{ImportEvent, ImportWorker};
+
+/// Name shown in the layer list for tracks pasted from the clipboard.
+const PASTED_NAME: &str = "Pasted text";
+
+pub struct GpxFile {
+    /// Outcome of each file in the last selection, drop or paste: a summary of what was
+    /// imported, or why it could not be.
+    results: Vec<(String, Result<String, GpxError>)>,
+    /// Files are being dragged over the drop zone.
+    dragging: bool,
+    /// Document-wide paste handler, kept so it can be removed when the component is.
+    paste_listener: Option<Closure<dyn FnMut(ClipboardEvent)>>,
+    /// CSV files waiting for the user to choose their columns, first shown first.
+    tables: Vec<(String, Rc<CsvTable>)>,
+    /// Files still being read or parsed.
+    loading: Vec<Loading>,
+    /// Identifies the next import, so messages about it can find it in `loading`.
+    next_import: usize,
+}
+
+/// A file being read or parsed.
+struct Loading {
+    id: usize,
+    name: String,
+    /// Fraction of the file parsed so far.
+    progress: f64,
+    /// Reads the file, until its bytes arrive.
+    reader: Option<Rc<FileReader>>,
+    /// Parses the bytes, which are kept to parse here instead if the worker cannot run.
+    worker: Option<(ImportWorker, Vec<u8>)>,
+}
+
+/// What reading a file produced.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum Imported {
+    /// Tracks, routes and waypoints, ready to draw.
+    Document(TrackDocument),
+    /// A table whose columns the user has yet to map.
+    Table(CsvTable),
+}
 
 #[derive(Properties, PartialEq)]
 pub struct GpxFileProps {
-    pub on_gpx_update: Callback<Option<Gpx>>,
+    /// Called with the file name and contents of each file that was imported.
+    pub on_gpx_update: Callback<(String, TrackDocument)>,
+    /// Content that files can be dropped onto, usually the map.
+    #[prop_or_default]
+    pub children: Html,
 }
 
 pub enum Msg {
     Files(Vec<File>),
+    Pasted(String),
+    /// The bytes of an import, or why they could not be read.
+    Read(usize, Result<Vec<u8>, GpxError>),
+    /// News from the worker parsing an import.
+    Worker(usize, ImportEvent),
+    /// Stop an import that is still being read or parsed.
+    Cancel(usize),
+    /// The columns chosen for the first waiting table, or `None` to skip it.
+    Mapped(Option<ColumnMapping>),
+    Dragging(bool),
 }
 
 impl Component for GpxFile {
@@ -25,54 +92,429 @@ impl Component for GpxFile {
     type Properties = GpxFileProps;
 
     fn create(_ctx: &Context<Self>) -> Self {
-        Self
+        Self {
+            results: Vec::new(),
+            dragging: false,
+            paste_listener: None,
+            tables: Vec::new(),
+            loading: Vec::new(),
+            next_import: 0,
+        }
     }
 
     fn view(&self, ctx: &Context<Self>) -> Html {
+        let link = ctx.link();
         html! {
             <>
+            <div
+                class="drop-zone"
+                ondragenter={link.batch_callback(|e: DragEvent| {
+                    Self::has_files(&e).then_some(Msg::Dragging(true))
+                })}
+            >
+                { ctx.props().children.clone() }
+                if self.dragging {
+                    // Covers the map while dragging, so it receives every drag event itself.
+                    <div
+                        class="drop-overlay"
+                        ondragover={Callback::from(|e: DragEvent| e.prevent_default())}
+                        ondragleave={link.callback(|_: DragEvent| Msg::Dragging(false))}
+                        ondrop={link.batch_callback(|e: DragEvent| {
+                            e.prevent_default();
+                            let files = e.data_transfer().and_then(|data| data.files());
+                            vec![Msg::Dragging(false), Self::upload_files(files)]
+                        })}
+                    >
+                        <p>{ "Drop GPX, TCX, FIT, KML, GeoJSON, NMEA or CSV files to open them" }</p>
+                    </div>
+                }
+            </div>
             <input
                     id="file-upload"
                     type="file"
                     // accept=".gpx"
-                    multiple={false}
-                    onchange={ctx.link().callback(move |e: Event| {
+                    multiple={true}
+                    onchange={link.callback(move |e: Event| {
                         let input: HtmlInputElement = e.target_unchecked_into();
                         Self::upload_files(input.files())
                     })}
                 />
+            if let Some((name, table)) = self.tables.first() {
+                <ColumnDialog
+                    key={name.clone()}
+                    name={name.clone()}
+                    table={table.clone()}
+                    on_done={link.callback(Msg::Mapped)}
+                />
+            }
+            <ul class="import-progress">
+                { for self.loading.iter().map(|loading| {
+                    let id = loading.id;
+                    html! {
+                        <li key={id}>
+                            { format!("Opening {} ", loading.name) }
+                            // Without a value, the bar shows that the file is still being read.
+                            if loading.worker.is_some() {
+                                <progress max="1" value={loading.progress.to_string()} />
+                            } else {
+                                <progress />
+                            }
+                            <button onclick={link.callback(move |_: MouseEvent| Msg::Cancel(id))}>
+                                { "Cancel" }
+                            </button>
+                        </li>
+                    }
+                }) }
+            </ul>
+            <ul class="import-results">
+                { for self.results.iter().map(|(name, result)| match result {
+                    Ok(summary) => html! {
+                        <li class="import-ok">{ format!("Opened {}: {}", name, summary) }</li>
+                    },
+                    Err(error) => html! {
+                        <li class="import-error">{ format!("Could not open {}: {}", name, error) }</li>
+                    },
+                }) }
+            </ul>
             </>
         }
     }
+
+    fn rendered(&mut self, ctx: &Context<Self>, first_render: bool) {
+        if !first_render {
+            return;
+        }
+        let on_paste = ctx.link().batch_callback(|e: ClipboardEvent| {
+            // Leave pastes into form fields alone.
+            let target = e
+                .target()
+                .and_then(|target| target.dyn_into::<Element>().ok());
+            if target.is_some_and(|target| {
+                matches!(target.tag_name().as_str(), "INPUT" | "TEXTAREA" | "SELECT")
+            }) {
+                return vec![];
+            }
+            let Some(data) = e.clipboard_data() else {
+                return vec![];
+            };
+            match data.files().filter(|files| files.length() > 0) {
+                Some(files) => vec![Self::upload_files(Some(files))],
+                None => data
+                    .get_data("text")
+                    .ok()
+                    .filter(|text| !text.trim().is_empty())
+                    .map(Msg::Pasted)
+                    .into_iter()
+                    .collect(),
+            }
+        });
+        let listener =
+            Closure::wrap(Box::new(move |e: ClipboardEvent| on_paste.emit(e))
+                as Box<dyn FnMut(ClipboardEvent)>);
+        if let Err(e) = gloo_utils::document()
+            .add_event_listener_with_callback("paste", listener.as_ref().unchecked_ref())
+        {
+            error!("Could not listen for paste events: {:?}", e);
+        }
+        self.paste_listener = Some(listener);
+    }
+
+    fn destroy(&mut self, _ctx: &Context<Self>) {
+        if let Some(listener) = self.paste_listener.take() {
+            let _ = gloo_utils::document()
+                .remove_event_listener_with_callback("paste", listener.as_ref().unchecked_ref());
+        }
+    }
+
     fn update(&mut self, ctx: &Context<Self>, msg: Self::Message) -> bool {
         match msg {
             Msg::Files(files) => {
                 info!("Files uploaded: {:?}", files);
+                self.results.clear();
                 files.iter().for_each(|file| {
-                    if let Err(e) =
-                        Self::read_gpx_file(file.clone(), ctx.props().on_gpx_update.clone())
-                    {
-                        error!("Error reading GPX file: {:?}", e);
+                    let id = self.begin(file.name());
+                    let on_read = ctx.link().callback(move |bytes| Msg::Read(id, bytes));
+                    match Self::read_gpx_file(file.clone(), on_read.clone()) {
+                        Ok(reader) => self.loading.last_mut().unwrap().reader = Some(reader),
+                        Err(e) => on_read.emit(Err(e)),
                     }
                 });
                 true
             }
+            Msg::Pasted(text) => {
+                info!("Text pasted, {} bytes", text.len());
+                self.results.clear();
+                let id = self.begin(PASTED_NAME.to_string());
+                ctx.link()
+                    .send_message(Msg::Read(id, Ok(text.into_bytes())));
+                true
+            }
+            Msg::Read(id, Err(e)) => self.finish(ctx, id, Err(e)),
+            Msg::Read(id, Ok(bytes)) => {
+                let Some(loading) = self.loading.iter_mut().find(|loading| loading.id == id) else {
+                    return false;
+                };
+                loading.reader = None;
+                let on_event = ctx.link().callback(move |event| Msg::Worker(id, event));
+                match ImportWorker::start(&loading.name, &bytes, on_event) {
+                    Ok(worker) => {
+                        loading.worker = Some((worker, bytes));
+                        true
+                    }
+                    Err(e) => {
+                        warn!("Could not start import worker, parsing here: {:?}", e);
+                        let result = Self::import(&loading.name, &bytes);
+                        self.finish(ctx, id, result)
+                    }
+                }
+            }
+            Msg::Worker(id, event) => {
+                let Some(loading) = self.loading.iter_mut().find(|loading| loading.id == id) else {
+                    return false;
+                };
+                match event {
+                    ImportEvent::Progress(progress) => {
+                        loading.progress = progress;
+                        true
+                    }
+                    ImportEvent::Done(result) => self.finish(ctx, id, result),
+                    ImportEvent::Failed(reason) => {
+                        warn!("Import worker failed, parsing here: {}", reason);
+                        let result = match loading.worker.take() {
+                            Some((_, bytes)) => Self::import(&loading.name, &bytes),
+                            None => Err(GpxError::Read(reason)),
+                        };
+                        self.finish(ctx, id, result)
+                    }
+                }
+            }
+            Msg::Cancel(id) => {
+                let Some(index) = self.loading.iter().position(|loading| loading.id == id) else {
+                    return false;
+                };
+                // Dropping the worker terminates it.
+                let loading = self.loading.remove(index);
+                if let Some(reader) = loading.reader {
+                    reader.abort();
+                }
+                info!("Import of {} cancelled", loading.name);
+                self.results.push((loading.name, Err(GpxError::Cancelled)));
+                true
+            }
+            Msg::Mapped(mapping) => {
+                if self.tables.is_empty() {
+                    return false;
+                }
+                let (name, table) = self.tables.remove(0);
+                if let Some(mapping) = mapping {
+                    let result = table
+                        .to_document(&mapping)
+                        .map_err(GpxError::Csv)
+                        .and_then(Self::non_empty);
+                    self.loaded(ctx, name, result);
+                }
+                true
+            }
+            Msg::Dragging(dragging) => {
+                let changed = self.dragging != dragging;
+                self.dragging = dragging;
+                changed
+            }
         }
     }
 }
-// Define a custom error type that wraps JsValue
-#[derive(Debug)]
-struct JsValueError(JsValue);
 
-impl fmt::Display for JsValueError {
+/// What an import found, such as "1 track, 3 waypoints, 705 points".
+fn summarise(tracks: &TrackDocument) -> String {
+    let count = |n: usize, what: &str| match n {
+        1 => format!("1 {}", what),
+        n => format!("{} {}s", n, what),
+    };
+    let points = tracks
+        .tracks
+        .iter()
+        .map(|track| track.points().count())
+        .chain(tracks.routes.iter().map(|route| route.points.len()))
+        .sum();
+    let mut parts = Vec::new();
+    if !tracks.tracks.is_empty() {
+        parts.push(count(tracks.tracks.len(), "track"));
+    }
+    if !tracks.routes.is_empty() {
+        parts.push(count(tracks.routes.len(), "route"));
+    }
+    if !tracks.waypoints.is_empty() {
+        parts.push(count(tracks.waypoints.len(), "waypoint"));
+    }
+    if points > 0 {
+        parts.push(count(points, "point"));
+    }
+    parts.join(", ")
+}
+
+/// File formats that can be imported.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum FileFormat {
+    Gpx,
+    Tcx,
+    Fit,
+    Kml,
+    Kmz,
+    GeoJson,
+    Nmea,
+    Csv,
+}
+
+impl FileFormat {
+    /// Format of a file, from its extension or, if that is missing or unknown, its content.
+    pub fn detect(name: &str, bytes: &[u8]) -> FileFormat {
+        let extension = name
+            .rsplit_once('.')
+            .map(|(_, extension)| extension.to_ascii_lowercase());
+        match extension.as_deref() {
+            Some("gpx") => FileFormat::Gpx,
+            Some("tcx") => FileFormat::Tcx,
+            Some("fit") => FileFormat::Fit,
+            Some("kml") => FileFormat::Kml,
+            Some("kmz") => FileFormat::Kmz,
+            Some("geojson" | "json") => FileFormat::GeoJson,
+            Some("nmea" | "nma") => FileFormat::Nmea,
+            Some("csv" | "tsv") => FileFormat::Csv,
+            _ => Self::sniff(bytes),
+        }
+    }
+
+    fn sniff(bytes: &[u8]) -> FileFormat {
+        if bytes.get(8..12) == Some(b".FIT") {
+            return FileFormat::Fit;
+        }
+        // A KMZ is a zip archive.
+        if bytes.starts_with(b"PK\x03\x04") {
+            return FileFormat::Kmz;
+        }
+        let start = String::from_utf8_lossy(&bytes[..bytes.len().min(1024)]);
+        let start = start.trim_start_matches('\u{feff}').trim_start();
+        if start.starts_with('{') {
+            FileFormat::GeoJson
+        } else if start.contains("<TrainingCenterDatabase") {
+            FileFormat::Tcx
+        } else if start.contains("<kml") {
+            FileFormat::Kml
+        } else if is_nmea(start) {
+            FileFormat::Nmea
+        } else if is_csv_header(start.lines().next().unwrap_or_default()) {
+            FileFormat::Csv
+        } else {
+            FileFormat::Gpx
+        }
+    }
+}
+
+/// Reasons a file could not be imported.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum GpxError {
+    /// The browser could not read the file.
+    Read(String),
+    /// The file is not UTF-8 text; `offset` is the first invalid byte.
+    NotUtf8 { offset: usize },
+    /// The file is not well-formed XML. Line and column count from 1.
+    Xml {
+        line: u64,
+        column: u64,
+        message: String,
+    },
+    /// The `<gpx>` element has no `version`, or one other than 1.0 or 1.1.
+    InvalidVersion,
+    /// Well-formed XML that does not follow the GPX schema.
+    Invalid(String),
+    /// The text looked like GeoJSON but could not be read as it.
+    GeoJson(GeoJsonError),
+    /// The file looked like TCX but could not be read as it.
+    Tcx(TcxError),
+    /// The file looked like FIT but could not be read as it.
+    Fit(FitError),
+    /// The file looked like KML or KMZ but could not be read as it.
+    Kml(KmlError),
+    /// The file looked like an NMEA log but could not be read as it.
+    Nmea(NmeaError),
+    /// The file looked like CSV but could not be read as it.
+    Csv(CsvError),
+    /// The file has no tracks, routes or waypoints.
+    Empty,
+    /// The user stopped the import.
+    Cancelled,
+}
+
+impl fmt::Display for GpxError {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "JsValueError: {:?}", self.0)
+        match self {
+            GpxError::Read(reason) => write!(f, "the file could not be read ({})", reason),
+            GpxError::NotUtf8 { offset } => {
+                write!(f, "the file is not UTF-8 text (invalid byte at {})", offset)
+            }
+            GpxError::Xml {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "XML error at line {}, column {}: {}",
+                line, column, message
+            ),
+            GpxError::InvalidVersion => write!(f, "only GPX versions 1.0 and 1.1 are supported"),
+            GpxError::Invalid(reason) => write!(f, "not a valid GPX file ({})", reason),
+            GpxError::GeoJson(e) => e.fmt(f),
+            GpxError::Tcx(e) => e.fmt(f),
+            GpxError::Fit(e) => e.fmt(f),
+            GpxError::Kml(e) => e.fmt(f),
+            GpxError::Nmea(e) => e.fmt(f),
+            GpxError::Csv(e) => e.fmt(f),
+            GpxError::Empty => write!(f, "the file has no tracks, routes or waypoints"),
+            GpxError::Cancelled => write!(f, "the import was cancelled"),
+        }
     }
 }
 
-impl std::error::Error for JsValueError {}
+impl std::error::Error for GpxError {}
+
+impl From<JsValue> for GpxError {
+    fn from(value: JsValue) -> Self {
+        GpxError::Read(value.as_string().unwrap_or_else(|| format!("{:?}", value)))
+    }
+}
+
+impl From<xml::reader::Error> for GpxError {
+    fn from(e: xml::reader::Error) -> Self {
+        let position = e.position();
+        GpxError::Xml {
+            line: position.row + 1,
+            column: position.column + 1,
+            message: e.msg().to_string(),
+        }
+    }
+}
+
+impl From<gpx::errors::GpxError> for GpxError {
+    fn from(e: gpx::errors::GpxError) -> Self {
+        match e {
+            gpx::errors::GpxError::XmlParseError(e) => GpxError::from(e),
+            gpx::errors::GpxError::UnknownVersionError(_)
+            | gpx::errors::GpxError::InvalidElementLacksAttribute("version", "gpx") => {
+                GpxError::InvalidVersion
+            }
+            e => GpxError::Invalid(e.to_string()),
+        }
+    }
+}
 
 impl GpxFile {
+    /// Whether a drag carries files, rather than text or a link.
+    fn has_files(e: &DragEvent) -> bool {
+        e.data_transfer()
+            .map(|data| Array::from(&data.types()).includes(&JsValue::from_str("Files"), 0))
+            .unwrap_or(false)
+    }
+
     fn upload_files(files: Option<FileList>) -> Msg {
         let mut result = Vec::new();
 
@@ -80,60 +522,98 @@ impl GpxFile {
             let files = js_sys::try_iter(&files)
                 .unwrap()
                 .unwrap()
-                .map(|v| web_sys::File::from(v.unwrap()))
-                .map(File::from);
+                .map(|v| web_sys::File::from(v.unwrap()));
             result.extend(files);
         }
         Msg::Files(result)
     }
-    /// Read the GPX file and parse it into a Rust struct
-    // TODO: return Result<Gpx, Box<dyn Error>> to handle errors
-    fn read_gpx_file(
-        file: File,
-        on_gpx_update: Callback<Option<Gpx>>,
-    ) -> Result<Rc<FileReader>, Box<dyn std::error::Error>> {
-        let file_reader = match FileReader::new() {
-            Ok(file_reader) => Rc::new(file_reader),
-            Err(e) => {
-                error!("Error creating FileReader: {:?}", e);
-                return Err(Box::new(JsValueError(e)));
-            }
+
+    /// Track a new import of `name`, returning its id.
+    fn begin(&mut self, name: String) -> usize {
+        let id = self.next_import;
+        self.next_import += 1;
+        self.loading.push(Loading {
+            id,
+            name,
+            progress: 0.0,
+            reader: None,
+            worker: None,
+        });
+        id
+    }
+
+    /// Settle import `id` with `result`: a table goes to the column dialog first. Imports
+    /// that were cancelled are ignored.
+    fn finish(
+        &mut self,
+        ctx: &Context<Self>,
+        id: usize,
+        result: Result<Imported, GpxError>,
+    ) -> bool {
+        let Some(index) = self.loading.iter().position(|loading| loading.id == id) else {
+            return false;
         };
+        let name = self.loading.remove(index).name;
+        match result {
+            Ok(Imported::Table(table)) => self.tables.push((name, Rc::new(table))),
+            Ok(Imported::Document(document)) => self.loaded(ctx, name, Ok(document)),
+            Err(e) => self.loaded(ctx, name, Err(e)),
+        }
+        true
+    }
 
-        // Start reading the file as text
-        // As described in https://developer.mozilla.org/en-US/docs/Web/API/FileReader/readAsText,
-        // When the read operation is complete, the readyState property is changed to DONE,
-        // the loadend event is triggered, and the result property contains the contents of the file as a text string.
-        if let Err(e) = file_reader.clone().read_as_text(&file) {
-            error!("Error reading file as text: {:?}", e);
-            return Err(Box::new(JsValueError(e)));
+    /// Record the outcome of an import, passing what it found to the app.
+    fn loaded(
+        &mut self,
+        ctx: &Context<Self>,
+        name: String,
+        result: Result<TrackDocument, GpxError>,
+    ) {
+        match result {
+            Ok(tracks) => {
+                self.results.push((name.clone(), Ok(summarise(&tracks))));
+                ctx.props().on_gpx_update.emit((name, tracks));
+            }
+            Err(e) => {
+                error!("Error importing {}: {:?}", name, e);
+                self.results.push((name, Err(e)));
+            }
         }
+    }
 
-        // Clone the FileReader and model for use inside the closure
-        let file_reader_rc: Rc<FileReader> = file_reader.clone();
-        // let mut gpx = Gpx::default();
+    /// Read the file's bytes, passing them to `on_loaded`. Parsing is left to the caller, so
+    /// that it can happen in a worker.
+    fn read_gpx_file(
+        file: File,
+        on_loaded: Callback<Result<Vec<u8>, GpxError>>,
+    ) -> Result<Rc<FileReader>, GpxError> {
+        let file_reader = Rc::new(FileReader::new()?);
 
-        // Clone the application and message mapper from the orders for use in the callback closures.
-        // let (app, msg_mapper) = (orders.clone_app(), orders.msg_mapper());
+        // Read raw bytes rather than text, so that non-UTF-8 files can be reported
+        // instead of being silently decoded with replacement characters.
+        // When the read operation is complete, the loadend event is triggered
+        // and the result property contains an ArrayBuffer of the file's contents.
+        file_reader.read_as_array_buffer(&file)?;
+
+        // Clone the FileReader for use inside the closure
+        let file_reader_rc: Rc<FileReader> = file_reader.clone();
+        let name = file.name();
 
         let gpx_file_callback = move |_event| {
             let file_reader = file_reader_rc.clone();
-            match file_reader.result() {
-                Ok(result) => {
-                    if let Some(text) = result.dyn_ref::<JsString>() {
-                        let gpx = Self::parse_gpx(text.into());
-                        info!("GPX file read successfully.");
-                        on_gpx_update.emit(gpx);
-                    } else {
-                        error!("Error reading file content as string.");
+            let result = file_reader
+                .result()
+                .map_err(GpxError::from)
+                .and_then(|buffer| {
+                    if buffer.is_null() {
+                        return Err(GpxError::Read("no content".to_string()));
                     }
-                    // map::draw_gpx_route(&model_clone);
-                }
-                Err(e) => {
-                    // TODO: rethrow the error
-                    error!("Error reading file: {:?}", e);
-                }
+                    Ok(Uint8Array::new(&buffer).to_vec())
+                });
+            if result.is_ok() {
+                info!("{} read successfully.", name);
             }
+            on_loaded.emit(result);
         };
         // Create a closure to capture the FileReader and perform actions once the file is read
         let onloadend_closure = Closure::wrap(Box::new(gpx_file_callback) as Box<dyn FnMut(Event)>);
@@ -149,37 +629,154 @@ impl GpxFile {
         Ok(file_reader)
     }
 
-    pub fn parse_gpx(text: String) -> Option<Gpx> {
-        let data = text.as_bytes();
-        match read(data) {
-            Ok(gpx) => {
-                info!("parse_gpx: Successfully parsed GPX string data.");
-                gpx.tracks.iter().for_each(|track| {
-                    info!(
-                        "Track name: {:?}",
-                        track.name.as_ref().unwrap_or(&"N/A".to_string())
-                    );
-                    info!(
-                        "Track type: {:?}",
-                        track.type_.as_ref().unwrap_or(&"N/A".to_string())
-                    );
-                    info!("Number of track segment: {:?}", track.segments.len());
-                    track.segments.iter().for_each(|segment| {
-                        info!("Number of point in this : {:?}", segment.points.len());
-                    });
-                });
-                Some(gpx)
+    /// Read a file or pasted text called `name`, leaving CSV as a table for the user to
+    /// choose its columns.
+    pub fn import(name: &str, bytes: &[u8]) -> Result<Imported, GpxError> {
+        Self::import_with_progress(name, bytes, &mut |_| {})
+    }
+
+    /// [`GpxFile::import`], passing the fraction of the file parsed so far to `on_progress`
+    /// as parsing goes on.
+    pub fn import_with_progress(
+        name: &str,
+        bytes: &[u8],
+        on_progress: &mut dyn FnMut(f64),
+    ) -> Result<Imported, GpxError> {
+        if FileFormat::detect(name, bytes) != FileFormat::Csv {
+            return Self::import_document(name, bytes, on_progress).map(Imported::Document);
+        }
+        let text = std::str::from_utf8(bytes).map_err(|e| GpxError::NotUtf8 {
+            offset: e.valid_up_to(),
+        })?;
+        let table = CsvTable::parse_with_progress(text, on_progress).map_err(GpxError::Csv)?;
+        if table.rows.is_empty() {
+            return Err(GpxError::Empty);
+        }
+        Ok(Imported::Table(table))
+    }
+
+    /// Parse a file or pasted text called `name` into the track model, in the format
+    /// given by [`FileFormat::detect`]. CSV columns are guessed from the headers.
+    pub fn import_bytes(name: &str, bytes: &[u8]) -> Result<TrackDocument, GpxError> {
+        Self::import_document(name, bytes, &mut |_| {})
+    }
+
+    fn import_document(
+        name: &str,
+        bytes: &[u8],
+        on_progress: &mut dyn FnMut(f64),
+    ) -> Result<TrackDocument, GpxError> {
+        let text = || {
+            std::str::from_utf8(bytes).map_err(|e| GpxError::NotUtf8 {
+                offset: e.valid_up_to(),
+            })
+        };
+        let tracks = match FileFormat::detect(name, bytes) {
+            FileFormat::Gpx => {
+                let text = text()?;
+                // The sensor readings take a second pass over the file, so each pass is
+                // half the progress.
+                let gpx = Self::parse_gpx_with_progress(text, &mut |fraction| {
+                    on_progress(fraction / 2.0)
+                })?;
+                let mut document = TrackDocument::from(&gpx);
+                add_point_extensions(
+                    &mut document,
+                    ProgressReader::new(text.as_bytes(), &mut |fraction| {
+                        on_progress(0.5 + fraction / 2.0)
+                    }),
+                );
+                document
             }
-            Err(e) => {
-                error!("parse_gpx: Failed to parse GPX string data. {:?}", e);
-                None
+            FileFormat::Tcx => {
+                text()?;
+                read_tcx(ProgressReader::new(bytes, on_progress)).map_err(GpxError::Tcx)?
+            }
+            FileFormat::Fit => read_fit_with_progress(bytes, on_progress).map_err(GpxError::Fit)?,
+            FileFormat::Kml => {
+                text()?;
+                read_kml(ProgressReader::new(bytes, on_progress)).map_err(GpxError::Kml)?
+            }
+            FileFormat::Kmz => {
+                let kml = unzip_kmz(bytes).map_err(GpxError::Kml)?;
+                read_kml(ProgressReader::new(&kml, on_progress)).map_err(GpxError::Kml)?
+            }
+            FileFormat::GeoJson => {
+                let reader = ProgressReader::new(text()?.as_bytes(), on_progress);
+                read_geojson_from(reader).map_err(GpxError::GeoJson)?
+            }
+            FileFormat::Nmea => {
+                read_nmea_with_progress(text()?, on_progress).map_err(GpxError::Nmea)?
+            }
+            FileFormat::Csv => {
+                let table =
+                    CsvTable::parse_with_progress(text()?, on_progress).map_err(GpxError::Csv)?;
+                let mapping = ColumnMapping::guess(&table.headers)
+                    .ok_or(GpxError::Csv(CsvError::NoColumns))?;
+                table.to_document(&mapping).map_err(GpxError::Csv)?
             }
+        };
+        Self::non_empty(tracks)
+    }
+
+    fn non_empty(tracks: TrackDocument) -> Result<TrackDocument, GpxError> {
+        if tracks.is_empty() {
+            return Err(GpxError::Empty);
         }
+        Ok(tracks)
+    }
+
+    /// The first well-formedness error in `text`, if there is one.
+    fn find_xml_error(text: &str) -> Option<GpxError> {
+        xml::EventReader::new(text.as_bytes())
+            .into_iter()
+            .find_map(Result::err)
+            .map(GpxError::from)
+    }
+
+    pub fn parse_gpx(text: String) -> Result<Gpx, GpxError> {
+        Self::parse_gpx_with_progress(&text, &mut |_| {})
+    }
+
+    fn parse_gpx_with_progress(
+        text: &str,
+        on_progress: &mut dyn FnMut(f64),
+    ) -> Result<Gpx, GpxError> {
+        if text.trim().is_empty() {
+            return Err(GpxError::Empty);
+        }
+        let gpx = read(ProgressReader::new(text.as_bytes(), on_progress)).map_err(|e| {
+            error!("parse_gpx: Failed to parse GPX string data. {:?}", e);
+            // The gpx crate reports most syntax errors without a position, so
+            // rescan the text to find where the XML itself is broken.
+            Self::find_xml_error(text).unwrap_or_else(|| GpxError::from(e))
+        })?;
+        info!("parse_gpx: Successfully parsed GPX string data.");
+        gpx.tracks.iter().for_each(|track| {
+            info!(
+                "Track name: {:?}",
+                track.name.as_ref().unwrap_or(&"N/A".to_string())
+            );
+            info!(
+                "Track type: {:?}",
+                track.type_.as_ref().unwrap_or(&"N/A".to_string())
+            );
+            info!("Number of track segment: {:?}", track.segments.len());
+            track.segments.iter().for_each(|segment| {
+                info!("Number of point in this : {:?}", segment.points.len());
+            });
+        });
+        if gpx.tracks.is_empty() && gpx.routes.is_empty() && gpx.waypoints.is_empty() {
+            return Err(GpxError::Empty);
+        }
+        Ok(gpx)
     }
 }
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::geojson::write_geojson;
+    use crate::kml::write_kml;
 
     #[test]
     fn test_parse_gpx() {
@@ -204,9 +801,9 @@ mod tests {
           </trk>
         </gpx>"#;
 
-        assert!(GpxFile::parse_gpx(text_gpx.to_string()).is_some());
-        assert!(GpxFile::parse_gpx("".to_string()).is_none());
-        assert!(GpxFile::parse_gpx("not a gpx".to_string()).is_none());
+        assert!(GpxFile::parse_gpx(text_gpx.to_string()).is_ok());
+        assert!(GpxFile::parse_gpx("".to_string()).is_err());
+        assert!(GpxFile::parse_gpx("not a gpx".to_string()).is_err());
         assert_eq!(
             GpxFile::parse_gpx(text_gpx.to_string()).unwrap().tracks[0]
                 .name
@@ -250,6 +847,161 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_parse_gpx_errors() {
+        assert_eq!(GpxFile::parse_gpx("".to_string()), Err(GpxError::Empty));
+        assert_eq!(GpxFile::parse_gpx(" \n ".to_string()), Err(GpxError::Empty));
+        assert_eq!(
+            GpxFile::import_bytes("bad.gpx", b"<gpx version=\"1.1\">\xff</gpx>"),
+            Err(GpxError::NotUtf8 { offset: 19 })
+        );
+        assert!(matches!(
+            GpxFile::parse_gpx("<gpx version=\"1.1\">\n  <trk>\n</gpx>".to_string()),
+            Err(GpxError::Xml {
+                line: 3,
+                column: 6,
+                ..
+            })
+        ));
+        assert_eq!(
+            GpxFile::parse_gpx("<gpx version=\"2.0\"></gpx>".to_string()),
+            Err(GpxError::InvalidVersion)
+        );
+        assert_eq!(
+            GpxFile::parse_gpx("<gpx></gpx>".to_string()),
+            Err(GpxError::InvalidVersion)
+        );
+        assert_eq!(
+            GpxFile::parse_gpx("<gpx version=\"1.1\"></gpx>".to_string()),
+            Err(GpxError::Empty)
+        );
+        assert!(matches!(
+            GpxFile::parse_gpx("<gpx version=\"1.1\"><wpt lat=\"x\" lon=\"0\"/></gpx>".to_string()),
+            Err(GpxError::Invalid(_))
+        ));
+    }
+
+    #[test]
+    fn test_import_bytes() {
+        let gpx = std::fs::read("src/data/Barton Road-Hardwick Road-Huntingdon Road.gpx").unwrap();
+        let tracks = GpxFile::import_bytes("route.gpx", &gpx).unwrap();
+        assert_eq!(summarise(&tracks), "1 track, 705 points");
+
+        let geojson = br#"
+            {"type": "Feature", "properties": {"name": "Pasted"},
+             "geometry": {"type": "LineString", "coordinates": [[0.0, 52.0], [0.1, 52.1]]}}"#;
+        let tracks = GpxFile::import_bytes(PASTED_NAME, geojson).unwrap();
+        assert_eq!(tracks.tracks[0].name.as_deref(), Some("Pasted"));
+        assert_eq!(summarise(&tracks), "1 track, 2 points");
+
+        assert!(matches!(
+            GpxFile::import_bytes(PASTED_NAME, b"{\"type\": \"Nonsense\"}"),
+            Err(GpxError::GeoJson(_))
+        ));
+        assert_eq!(
+            GpxFile::import_bytes(
+                "empty.geojson",
+                br#"{"type": "FeatureCollection", "features": []}"#
+            ),
+            Err(GpxError::Empty)
+        );
+
+        let csv = b"lat,lon\n52.2,0.1\n52.3,0.2\n";
+        let tracks = GpxFile::import_bytes("points.csv", csv).unwrap();
+        assert_eq!(summarise(&tracks), "1 track, 2 points");
+        assert!(matches!(
+            GpxFile::import("points.csv", csv),
+            Ok(Imported::Table(table)) if table.rows.len() == 2
+        ));
+        assert_eq!(
+            GpxFile::import_bytes("points.csv", b"x,y\n1,2\n"),
+            Err(GpxError::Csv(CsvError::NoColumns))
+        );
+        assert_eq!(
+            GpxFile::import("points.csv", b"x,y\n"),
+            Err(GpxError::Empty)
+        );
+
+        let nmea = b"$GPGGA,120000,5212.3000,N,00007.5000,E,1,08,0.9,12.5,M,47.0,M,,";
+        let tracks = GpxFile::import_bytes("log.nmea", nmea).unwrap();
+        assert_eq!(summarise(&tracks), "1 track, 1 point");
+    }
+
+    #[test]
+    fn test_import_progress() {
+        let gpx = std::fs::read("src/data/Barton Road-Hardwick Road-Huntingdon Road.gpx").unwrap();
+        let document = GpxFile::import_bytes("route.gpx", &gpx).unwrap();
+        let csv: String = std::iter::once("lat,lon\n".to_string())
+            .chain(
+                document.tracks[0]
+                    .points()
+                    .map(|point| format!("{},{}\n", point.coord.lat, point.coord.lon)),
+            )
+            .collect();
+        let files = [
+            ("route.gpx", gpx.clone()),
+            ("route.kml", write_kml(&document, "#3388ff")),
+            ("route.geojson", write_geojson(&document).into_bytes()),
+            ("route.csv", csv.into_bytes()),
+            ("ride.fit", std::fs::read("src/data/ride.fit").unwrap()),
+        ];
+        for (name, bytes) in files {
+            let mut progress = Vec::new();
+            let imported = GpxFile::import_with_progress(name, &bytes, &mut |fraction| {
+                progress.push(fraction)
+            });
+            assert!(imported.is_ok(), "Testing {}", name);
+            assert!(progress.len() > 10, "Testing {}: {:?}", name, progress);
+            assert!(progress.windows(2).all(|pair| pair[0] < pair[1]));
+            // The GPX parser stops at `</gpx>`, short of any trailing whitespace.
+            assert!(
+                progress.last().is_some_and(|&fraction| fraction >= 0.99),
+                "Testing {}: {:?}",
+                name,
+                progress
+            );
+        }
+
+        // Reading GPX sensor readings is the second half of the work.
+        let mut progress = Vec::new();
+        GpxFile::import_with_progress("route.gpx", &gpx, &mut |fraction| progress.push(fraction))
+            .unwrap();
+        let first_pass = progress.iter().filter(|&&fraction| fraction <= 0.5).count();
+        assert!(first_pass > 10 && progress.len() - first_pass > 10);
+    }
+
+    #[test]
+    fn test_detect_format() {
+        let tcx = b"\xef\xbb\xbf<?xml version=\"1.0\"?>\n<TrainingCenterDatabase>";
+        assert_eq!(FileFormat::detect("ride.TCX", b""), FileFormat::Tcx);
+        assert_eq!(FileFormat::detect("ride.gpx", tcx), FileFormat::Gpx);
+        assert_eq!(FileFormat::detect("ride.xml", tcx), FileFormat::Tcx);
+        assert_eq!(FileFormat::detect(PASTED_NAME, tcx), FileFormat::Tcx);
+        assert_eq!(
+            FileFormat::detect(PASTED_NAME, b"  {}"),
+            FileFormat::GeoJson
+        );
+        assert_eq!(FileFormat::detect("map.json", b""), FileFormat::GeoJson);
+        let fit = std::fs::read("src/data/ride.fit").unwrap();
+        assert_eq!(FileFormat::detect("ride", &fit), FileFormat::Fit);
+        assert_eq!(FileFormat::detect("ride.FIT", b""), FileFormat::Fit);
+        assert_eq!(FileFormat::detect("plan", b"PK\x03\x04"), FileFormat::Kmz);
+        let kml = br#"<?xml version="1.0"?><kml xmlns="http://www.opengis.net/kml/2.2">"#;
+        assert_eq!(FileFormat::detect(PASTED_NAME, kml), FileFormat::Kml);
+        assert_eq!(FileFormat::detect("plan.kml", b""), FileFormat::Kml);
+        assert_eq!(FileFormat::detect(PASTED_NAME, b"<gpx>"), FileFormat::Gpx);
+        assert_eq!(FileFormat::detect("log.nmea", b""), FileFormat::Nmea);
+        assert_eq!(
+            FileFormat::detect("log.txt", b"$GPGGA,120000,5212.3,N,00007.5,E,1,,,,,,,,"),
+            FileFormat::Nmea
+        );
+        assert_eq!(FileFormat::detect("places.CSV", b""), FileFormat::Csv);
+        assert_eq!(
+            FileFormat::detect(PASTED_NAME, b"Name\tLatitude\tLongitude\nHome\t52.2\t0.1"),
+            FileFormat::Csv
+        );
+    }
+
     use gloo_utils::format::JsValueSerdeExt;
     use wasm_bindgen_test::*;
     use web_sys::ProgressEvent;
@@ -311,7 +1063,7 @@ mod tests {
             "File reading should be initiated successfully"
         );
         assert!(
-            GpxFile::parse_gpx(file_content.to_string()).is_some(),
+            GpxFile::parse_gpx(file_content.to_string()).is_ok(),
             "Parsed GPX data should be valid"
         );
 
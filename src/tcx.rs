@@ -0,0 +1,362 @@
+//! Garmin Training Center (TCX) reader. Activities become tracks with one segment per lap
+//! track, courses become routes, and course points become waypoints.
+
+use core::fmt;
+use std::io::Read;
+
+use serde::{Deserialize, Serialize};
+use time::{format_description::well_known::Rfc3339, OffsetDateTime};
+use xml::common::Position;
+use xml::reader::{EventReader, XmlEvent};
+
+use crate::geo::Coord;
+use crate::track::{PointExtensions, Route, Segment, Track, TrackDocument, TrackPoint, Waypoint};
+
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum TcxError {
+    /// The file is not well-formed XML. Line and column count from 1.
+    Xml {
+        line: u64,
+        column: u64,
+        message: String,
+    },
+    /// Well-formed XML that is not a TCX file, or has a value that cannot be read.
+    Invalid(String),
+}
+
+impl fmt::Display for TcxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TcxError::Xml {
+                line,
+                column,
+                message,
+            } => write!(
+                f,
+                "XML error at line {}, column {}: {}",
+                line, column, message
+            ),
+            TcxError::Invalid(reason) => write!(f, "not a valid TCX file ({})", reason),
+        }
+    }
+}
+
+impl std::error::Error for TcxError {}
+
+impl From<xml::reader::Error> for TcxError {
+    fn from(e: xml::reader::Error) -> Self {
+        let position = e.position();
+        TcxError::Xml {
+            line: position.row + 1,
+            column: position.column + 1,
+            message: e.msg().to_string(),
+        }
+    }
+}
+
+/// Values collected from the children of a `Trackpoint` or `CoursePoint`.
+#[derive(Default)]
+struct PointFields {
+    lat: Option<f64>,
+    lon: Option<f64>,
+    elevation: Option<f64>,
+    time: Option<OffsetDateTime>,
+    extensions: PointExtensions,
+    name: Option<String>,
+    notes: Option<String>,
+    point_type: Option<String>,
+}
+
+impl PointFields {
+    fn coord(&self) -> Option<Coord> {
+        Some(Coord::new(self.lat?, self.lon?))
+    }
+}
+
+pub fn read_tcx(input: impl Read) -> Result<TrackDocument, TcxError> {
+    let mut document = TrackDocument::default();
+    // Local names of the open elements, outermost first.
+    let mut path: Vec<String> = Vec::new();
+    let mut text = String::new();
+    let mut activity: Option<Track> = None;
+    let mut course: Option<Route> = None;
+    let mut segment: Option<Segment> = None;
+    let mut fields: Option<PointFields> = None;
+
+    for event in EventReader::new(input) {
+        match event? {
+            XmlEvent::StartElement {
+                name, attributes, ..
+            } => {
+                let name = name.local_name;
+                if path.is_empty() && name != "TrainingCenterDatabase" {
+                    return Err(TcxError::Invalid(format!("root element is {}", name)));
+                }
+                match name.as_str() {
+                    "Activity" => {
+                        let sport = attributes.iter().find(|a| a.name.local_name == "Sport");
+                        activity = Some(Track {
+                            kind: sport.map(|a| a.value.clone()),
+                            ..Track::default()
+                        });
+                    }
+                    "Course" => course = Some(Route::default()),
+                    "Track" if activity.is_some() => segment = Some(Segment::default()),
+                    "Trackpoint" | "CoursePoint" => fields = Some(PointFields::default()),
+                    _ => {}
+                }
+                path.push(name);
+                text.clear();
+            }
+            XmlEvent::Characters(chars) | XmlEvent::CData(chars) => text.push_str(&chars),
+            XmlEvent::EndElement { .. } => {
+                let name = path.pop().unwrap_or_default();
+                let parent = path.last().map(String::as_str).unwrap_or_default();
+                let value = text.trim();
+                match (name.as_str(), parent) {
+                    ("Id", "Activity") => {
+                        if let Some(activity) = activity.as_mut() {
+                            activity.name = Some(value.to_string());
+                        }
+                    }
+                    ("Notes", "Activity") => {
+                        if let Some(activity) = activity.as_mut() {
+                            activity.description = Some(value.to_string());
+                        }
+                    }
+                    ("Name", "Course") => {
+                        if let Some(course) = course.as_mut() {
+                            course.name = Some(value.to_string());
+                        }
+                    }
+                    ("Notes", "Course") => {
+                        if let Some(course) = course.as_mut() {
+                            course.description = Some(value.to_string());
+                        }
+                    }
+                    (field, _) => {
+                        if let Some(fields) = fields.as_mut() {
+                            read_point_field(fields, field, parent, value)?;
+                        }
+                    }
+                }
+                match name.as_str() {
+                    "Trackpoint" => {
+                        let point = fields.take().and_then(|fields| {
+                            Some(TrackPoint {
+                                coord: fields.coord()?,
+                                elevation: fields.elevation,
+                                time: fields.time,
+                                extensions: fields.extensions,
+                            })
+                        });
+                        // Points without a position, such as heart rate while stopped, can't be drawn.
+                        if let Some(point) = point {
+                            match (segment.as_mut(), course.as_mut()) {
+                                (Some(segment), _) => segment.points.push(point),
+                                (None, Some(course)) => course.points.push(point),
+                                _ => {}
+                            }
+                        }
+                    }
+                    "CoursePoint" => {
+                        let waypoint = fields.take().and_then(|fields| {
+                            Some(Waypoint {
+                                coord: fields.coord()?,
+                                elevation: fields.elevation,
+                                time: fields.time,
+                                name: fields.name,
+                                description: fields.notes,
+                                symbol: fields.point_type,
+                            })
+                        });
+                        document.waypoints.extend(waypoint);
+                    }
+                    "Track" => {
+                        if let (Some(activity), Some(segment)) = (activity.as_mut(), segment.take())
+                        {
+                            if !segment.points.is_empty() {
+                                activity.segments.push(segment);
+                            }
+                        }
+                    }
+                    "Activity" => document.tracks.extend(activity.take()),
+                    "Course" => document.routes.extend(course.take()),
+                    _ => {}
+                }
+                text.clear();
+            }
+            _ => {}
+        }
+    }
+    Ok(document)
+}
+
+/// Store the text of a child element of a `Trackpoint` or `CoursePoint`.
+fn read_point_field(
+    fields: &mut PointFields,
+    name: &str,
+    parent: &str,
+    value: &str,
+) -> Result<(), TcxError> {
+    match (name, parent) {
+        ("Time", _) => {
+            let time = OffsetDateTime::parse(value, &Rfc3339)
+                .map_err(|_| TcxError::Invalid(format!("{} is not a time", value)))?;
+            fields.time = Some(time);
+        }
+        ("LatitudeDegrees", "Position") => fields.lat = Some(number(value)?),
+        ("LongitudeDegrees", "Position") => fields.lon = Some(number(value)?),
+        ("AltitudeMeters", _) => fields.elevation = Some(number(value)?),
+        ("Value", "HeartRateBpm") => fields.extensions.heart_rate = Some(number(value)?),
+        // Cycling cadence is in the trackpoint itself, running cadence in the extension.
+        ("Cadence", "Trackpoint") | ("RunCadence", "TPX") => {
+            fields.extensions.cadence = Some(number(value)?)
+        }
+        ("Watts", "TPX") => fields.extensions.power = Some(number(value)?),
+        ("Name", "CoursePoint") => fields.name = Some(value.to_string()),
+        ("Notes", "CoursePoint") => fields.notes = Some(value.to_string()),
+        ("PointType", "CoursePoint") => fields.point_type = Some(value.to_string()),
+        _ => {}
+    }
+    Ok(())
+}
+
+fn number<T: core::str::FromStr>(value: &str) -> Result<T, TcxError> {
+    value
+        .parse()
+        .map_err(|_| TcxError::Invalid(format!("{} is not a number", value)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use time::macros::datetime;
+
+    const ACTIVITY: &str = r#"<?xml version="1.0" encoding="UTF-8"?>
+<TrainingCenterDatabase xmlns="http://www.garmin.com/xmlschemas/TrainingCenterDatabase/v2"
+    xmlns:ns3="http://www.garmin.com/xmlschemas/ActivityExtension/v2">
+  <Activities>
+    <Activity Sport="Biking">
+      <Id>2024-05-01T09:30:00Z</Id>
+      <Lap StartTime="2024-05-01T09:30:00Z">
+        <TotalTimeSeconds>20</TotalTimeSeconds>
+        <Cadence>80</Cadence>
+        <Track>
+          <Trackpoint>
+            <Time>2024-05-01T09:30:00Z</Time>
+            <Position>
+              <LatitudeDegrees>52.2053</LatitudeDegrees>
+              <LongitudeDegrees>0.1218</LongitudeDegrees>
+            </Position>
+            <AltitudeMeters>12.4</AltitudeMeters>
+            <HeartRateBpm><Value>128</Value></HeartRateBpm>
+            <Cadence>85</Cadence>
+            <Extensions><ns3:TPX><ns3:Watts>210</ns3:Watts></ns3:TPX></Extensions>
+          </Trackpoint>
+          <Trackpoint>
+            <Time>2024-05-01T09:30:10Z</Time>
+            <HeartRateBpm><Value>130</Value></HeartRateBpm>
+          </Trackpoint>
+        </Track>
+      </Lap>
+      <Lap StartTime="2024-05-01T09:30:20Z">
+        <Track>
+          <Trackpoint>
+            <Time>2024-05-01T09:30:20Z</Time>
+            <Position>
+              <LatitudeDegrees>52.2060</LatitudeDegrees>
+              <LongitudeDegrees>0.1230</LongitudeDegrees>
+            </Position>
+          </Trackpoint>
+        </Track>
+      </Lap>
+    </Activity>
+  </Activities>
+</TrainingCenterDatabase>"#;
+
+    const COURSE: &str = r#"<TrainingCenterDatabase>
+  <Courses>
+    <Course>
+      <Name>Loop</Name>
+      <Lap><DistanceMeters>1000</DistanceMeters></Lap>
+      <Track>
+        <Trackpoint>
+          <Position><LatitudeDegrees>52.0</LatitudeDegrees><LongitudeDegrees>0.0</LongitudeDegrees></Position>
+        </Trackpoint>
+        <Trackpoint>
+          <Position><LatitudeDegrees>52.1</LatitudeDegrees><LongitudeDegrees>0.1</LongitudeDegrees></Position>
+        </Trackpoint>
+      </Track>
+      <CoursePoint>
+        <Name>Left</Name>
+        <Time>2024-05-01T09:31:00Z</Time>
+        <Position><LatitudeDegrees>52.05</LatitudeDegrees><LongitudeDegrees>0.05</LongitudeDegrees></Position>
+        <PointType>Left</PointType>
+        <Notes>Turn onto the bridleway</Notes>
+      </CoursePoint>
+    </Course>
+  </Courses>
+</TrainingCenterDatabase>"#;
+
+    #[test]
+    fn test_read_activity() {
+        let document = read_tcx(ACTIVITY.as_bytes()).unwrap();
+        assert_eq!(document.tracks.len(), 1);
+        let track = &document.tracks[0];
+        assert_eq!(track.kind.as_deref(), Some("Biking"));
+        assert_eq!(track.name.as_deref(), Some("2024-05-01T09:30:00Z"));
+        // One segment per lap; the point without a position is dropped.
+        assert_eq!(track.segments.len(), 2);
+        assert_eq!(track.segments[0].points.len(), 1);
+
+        let point = &track.segments[0].points[0];
+        assert_eq!(point.coord, Coord::new(52.2053, 0.1218));
+        assert_eq!(point.elevation, Some(12.4));
+        assert_eq!(point.time, Some(datetime!(2024-05-01 09:30 UTC)));
+        assert_eq!(
+            point.extensions,
+            PointExtensions {
+                heart_rate: Some(128),
+                cadence: Some(85),
+                power: Some(210),
+                temperature: None,
+            }
+        );
+    }
+
+    #[test]
+    fn test_read_course() {
+        let document = read_tcx(COURSE.as_bytes()).unwrap();
+        assert!(document.tracks.is_empty());
+        assert_eq!(document.routes.len(), 1);
+        assert_eq!(document.routes[0].name.as_deref(), Some("Loop"));
+        assert_eq!(document.routes[0].points.len(), 2);
+
+        let waypoint = &document.waypoints[0];
+        assert_eq!(waypoint.name.as_deref(), Some("Left"));
+        assert_eq!(waypoint.symbol.as_deref(), Some("Left"));
+        assert_eq!(
+            waypoint.description.as_deref(),
+            Some("Turn onto the bridleway")
+        );
+        assert_eq!(waypoint.coord, Coord::new(52.05, 0.05));
+    }
+
+    #[test]
+    fn test_read_tcx_errors() {
+        assert!(matches!(
+            read_tcx(b"<gpx version=\"1.1\"></gpx>".as_slice()),
+            Err(TcxError::Invalid(_))
+        ));
+        assert!(matches!(
+            read_tcx(b"<TrainingCenterDatabase>\n<Courses>\n</TrainingCenterDatabase>".as_slice()),
+            Err(TcxError::Xml { line: 3, .. })
+        ));
+        let bad_number = COURSE.replace("52.05", "north");
+        assert!(matches!(
+            read_tcx(bad_number.as_bytes()),
+            Err(TcxError::Invalid(_))
+        ));
+    }
+}
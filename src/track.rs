@@ -0,0 +1,274 @@
+//! The app's own track model. Importers convert into these types and the map, statistics
+//! and navigation code consume them, so nothing outside the importers depends on a file format.
+
+use gpx::Gpx;
+use serde::{Deserialize, Serialize};
+use time::OffsetDateTime;
+
+use crate::geo::{BBox, Coord, SpatialIndex};
+
+/// Everything loaded from one file: recorded tracks, planned routes and waypoints.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackDocument {
+    pub name: Option<String>,
+    pub tracks: Vec<Track>,
+    pub routes: Vec<Route>,
+    pub waypoints: Vec<Waypoint>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Track {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Activity type, such as `cycling`.
+    pub kind: Option<String>,
+    pub segments: Vec<Segment>,
+}
+
+/// Continuous run of recorded points; a track is split into segments where recording paused.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Segment {
+    pub points: Vec<TrackPoint>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackPoint {
+    pub coord: Coord,
+    /// Elevation in metres above sea level.
+    pub elevation: Option<f64>,
+    pub time: Option<OffsetDateTime>,
+    pub extensions: PointExtensions,
+}
+
+/// Sensor readings recorded alongside a point.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct PointExtensions {
+    /// Beats per minute.
+    pub heart_rate: Option<u16>,
+    /// Revolutions per minute.
+    pub cadence: Option<u16>,
+    /// Watts.
+    pub power: Option<u16>,
+    /// Degrees Celsius.
+    pub temperature: Option<f64>,
+}
+
+/// Planned route, a sequence of points to follow.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Route {
+    pub name: Option<String>,
+    pub description: Option<String>,
+    pub points: Vec<TrackPoint>,
+}
+
+/// Named point of interest.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Waypoint {
+    pub coord: Coord,
+    pub elevation: Option<f64>,
+    pub time: Option<OffsetDateTime>,
+    pub name: Option<String>,
+    pub description: Option<String>,
+    /// Symbol name, such as `Flag, Blue`.
+    pub symbol: Option<String>,
+}
+
+/// Position of a point within a document's tracks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TrackPointId {
+    pub track: usize,
+    pub segment: usize,
+    pub point: usize,
+}
+
+impl TrackDocument {
+    pub fn is_empty(&self) -> bool {
+        self.tracks.is_empty() && self.routes.is_empty() && self.waypoints.is_empty()
+    }
+
+    /// Smallest box containing every track point, route point and waypoint.
+    pub fn bbox(&self) -> Option<BBox> {
+        let track_points = self.tracks.iter().flat_map(Track::points);
+        let route_points = self.routes.iter().flat_map(|route| route.points.iter());
+        BBox::from_coords(
+            track_points
+                .chain(route_points)
+                .map(|point| point.coord)
+                .chain(self.waypoints.iter().map(|waypoint| waypoint.coord)),
+        )
+    }
+
+    /// The track point at `id`, if there is one.
+    pub fn point(&self, id: TrackPointId) -> Option<&TrackPoint> {
+        let segment = self.tracks.get(id.track)?.segments.get(id.segment)?;
+        segment.points.get(id.point)
+    }
+
+    /// Spatial index of every track point.
+    pub fn point_index(&self) -> SpatialIndex<TrackPointId> {
+        let mut items = Vec::new();
+        self.tracks.iter().enumerate().for_each(|(t, track)| {
+            track.segments.iter().enumerate().for_each(|(s, segment)| {
+                segment.points.iter().enumerate().for_each(|(p, point)| {
+                    let id = TrackPointId {
+                        track: t,
+                        segment: s,
+                        point: p,
+                    };
+                    items.push((point.coord, id));
+                });
+            });
+        });
+        SpatialIndex::new(items)
+    }
+}
+
+impl Track {
+    /// All points of all segments, in order.
+    pub fn points(&self) -> impl Iterator<Item = &TrackPoint> {
+        self.segments
+            .iter()
+            .flat_map(|segment| segment.points.iter())
+    }
+}
+
+impl Segment {
+    pub fn coords(&self) -> Vec<Coord> {
+        self.points.iter().map(|point| point.coord).collect()
+    }
+}
+
+impl PointExtensions {
+    /// Whether no readings were recorded.
+    pub fn is_empty(&self) -> bool {
+        *self == PointExtensions::default()
+    }
+}
+
+impl From<&gpx::Waypoint> for TrackPoint {
+    fn from(point: &gpx::Waypoint) -> Self {
+        TrackPoint {
+            coord: Coord::new(point.point().y(), point.point().x()),
+            elevation: point.elevation,
+            time: point.time.map(OffsetDateTime::from),
+            extensions: PointExtensions::default(),
+        }
+    }
+}
+
+impl From<&gpx::Waypoint> for Waypoint {
+    fn from(point: &gpx::Waypoint) -> Self {
+        Waypoint {
+            coord: Coord::new(point.point().y(), point.point().x()),
+            elevation: point.elevation,
+            time: point.time.map(OffsetDateTime::from),
+            name: point.name.clone(),
+            description: point.description.clone(),
+            symbol: point.symbol.clone(),
+        }
+    }
+}
+
+impl From<&Gpx> for TrackDocument {
+    fn from(gpx: &Gpx) -> Self {
+        TrackDocument {
+            name: gpx.metadata.as_ref().and_then(|m| m.name.clone()),
+            tracks: gpx
+                .tracks
+                .iter()
+                .map(|track| Track {
+                    name: track.name.clone(),
+                    description: track.description.clone(),
+                    kind: track.type_.clone(),
+                    segments: track
+                        .segments
+                        .iter()
+                        .map(|segment| Segment {
+                            points: segment.points.iter().map(TrackPoint::from).collect(),
+                        })
+                        .collect(),
+                })
+                .collect(),
+            routes: gpx
+                .routes
+                .iter()
+                .map(|route| Route {
+                    name: route.name.clone(),
+                    description: route.description.clone(),
+                    points: route.points.iter().map(TrackPoint::from).collect(),
+                })
+                .collect(),
+            waypoints: gpx.waypoints.iter().map(Waypoint::from).collect(),
+        }
+    }
+}
+
+impl From<&TrackPoint> for gpx::Waypoint {
+    fn from(point: &TrackPoint) -> Self {
+        let mut waypoint =
+            gpx::Waypoint::new(geo_types::Point::new(point.coord.lon, point.coord.lat));
+        waypoint.elevation = point.elevation;
+        waypoint.time = point.time.map(gpx::Time::from);
+        waypoint
+    }
+}
+
+impl From<&Waypoint> for gpx::Waypoint {
+    fn from(point: &Waypoint) -> Self {
+        let mut waypoint =
+            gpx::Waypoint::new(geo_types::Point::new(point.coord.lon, point.coord.lat));
+        waypoint.elevation = point.elevation;
+        waypoint.time = point.time.map(gpx::Time::from);
+        waypoint.name = point.name.clone();
+        waypoint.description = point.description.clone();
+        waypoint.symbol = point.symbol.clone();
+        waypoint
+    }
+}
+
+#[cfg(test)]
+pub mod tests {
+    use super::*;
+
+    /// A bundled GPX file converted into the track model.
+    pub fn sample_document() -> TrackDocument {
+        let gpx = gpx::read(
+            std::fs::read("src/data/Barton Road-Hardwick Road-Huntingdon Road.gpx")
+                .unwrap()
+                .as_slice(),
+        )
+        .unwrap();
+        TrackDocument::from(&gpx)
+    }
+
+    #[test]
+    fn test_from_gpx() {
+        let doc = sample_document();
+        assert_eq!(doc.tracks.len(), 1);
+        assert_eq!(doc.tracks[0].segments.len(), 1);
+        assert_eq!(doc.tracks[0].segments[0].points.len(), 705);
+        let first = &doc.tracks[0].segments[0].points[0];
+        assert!(first.elevation.is_some());
+        assert!(!doc.is_empty());
+        assert!(TrackDocument::default().is_empty());
+    }
+
+    #[test]
+    fn test_bbox() {
+        let doc = sample_document();
+        let bbox = doc.bbox().unwrap();
+        assert!(doc.tracks[0].points().all(|p| bbox.contains(&p.coord)));
+        assert_eq!(TrackDocument::default().bbox(), None);
+    }
+
+    #[test]
+    fn test_point_index() {
+        let doc = sample_document();
+        let index = doc.point_index();
+        let points = &doc.tracks[0].segments[0].points;
+        assert_eq!(index.len(), points.len());
+        let (distance, id) = index.nearest(&points[700].coord, 1)[0];
+        assert_eq!(distance, 0.0);
+        assert_eq!(points[id.point].coord, points[700].coord);
+    }
+}
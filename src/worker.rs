@@ -0,0 +1,143 @@
+//! Parsing imported files in a Web Worker, so a large file doesn't freeze the map. The page
+//! starts an [`ImportWorker`] per file and posts it the name and bytes; in the worker,
+//! [`serve`] parses them and posts back progress and then the outcome, as plain JavaScript
+//! objects that the browser copies across.
+
+use log::error;
+use serde::{Deserialize, Serialize};
+use web_sys::{
+    js_sys::{self, Array, Uint8Array},
+    wasm_bindgen::{closure::Closure, JsCast, JsValue},
+    DedicatedWorkerGlobalScope, ErrorEvent, MessageEvent, Worker,
+};
+use yew::Callback;
+
+use crate::route::{GpxError, GpxFile, Imported};
+
+/// Loader script Trunk generates for the `import_worker` binary.
+const WORKER_SCRIPT: &str = "./import_worker_loader.js";
+
+/// What an import worker reports.
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+pub enum ImportEvent {
+    /// Fraction of the file parsed so far, from 0 to 1.
+    Progress(f64),
+    /// The outcome; the worker has finished.
+    Done(Result<Imported, GpxError>),
+    /// The worker could not run, so the file should be parsed some other way.
+    Failed(String),
+}
+
+/// An import running in its own worker. Dropping it terminates the worker, which cancels
+/// the import however far it has got.
+pub struct ImportWorker {
+    worker: Worker,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+    _on_error: Closure<dyn FnMut(ErrorEvent)>,
+}
+
+impl ImportWorker {
+    /// Start parsing `bytes`, read from the file called `name`, reporting to `on_event`.
+    pub fn start(
+        name: &str,
+        bytes: &[u8],
+        on_event: Callback<ImportEvent>,
+    ) -> Result<ImportWorker, JsValue> {
+        let worker = Worker::new(WORKER_SCRIPT)?;
+
+        let on_message = {
+            let on_event = on_event.clone();
+            Closure::wrap(Box::new(move |e: MessageEvent| {
+                let event = serde_wasm_bindgen::from_value(e.data())
+                    .unwrap_or_else(|e| ImportEvent::Failed(format!("unreadable reply: {}", e)));
+                on_event.emit(event);
+            }) as Box<dyn FnMut(MessageEvent)>)
+        };
+        worker.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+        // Fires if the worker script is missing or panics.
+        let on_error = Closure::wrap(Box::new(move |e: ErrorEvent| {
+            e.prevent_default();
+            on_event.emit(ImportEvent::Failed(e.message()));
+        }) as Box<dyn FnMut(ErrorEvent)>);
+        worker.set_onerror(Some(on_error.as_ref().unchecked_ref()));
+
+        let data = Uint8Array::from(bytes);
+        worker.post_message_with_transfer(
+            &Array::of2(&JsValue::from_str(name), &data),
+            &Array::of1(&data.buffer()),
+        )?;
+        Ok(ImportWorker {
+            worker,
+            _on_message: on_message,
+            _on_error: on_error,
+        })
+    }
+}
+
+impl Drop for ImportWorker {
+    fn drop(&mut self) {
+        self.worker.terminate();
+    }
+}
+
+/// Run as an import worker: parse each file posted by [`ImportWorker::start`].
+pub fn serve() {
+    let scope: DedicatedWorkerGlobalScope = js_sys::global().unchecked_into();
+    let reply_scope = scope.clone();
+    let on_message = Closure::wrap(Box::new(move |e: MessageEvent| {
+        let post = |event: &ImportEvent| match serde_wasm_bindgen::to_value(event) {
+            Ok(value) => {
+                if let Err(e) = reply_scope.post_message(&value) {
+                    error!("Could not reply from import worker: {:?}", e);
+                }
+            }
+            Err(e) => error!("Could not encode import reply: {}", e),
+        };
+        let data = Array::from(&e.data());
+        let name = data.get(0).as_string().unwrap_or_default();
+        let bytes = Uint8Array::new(&data.get(1)).to_vec();
+        let result = GpxFile::import_with_progress(&name, &bytes, &mut |fraction| {
+            post(&ImportEvent::Progress(fraction))
+        });
+        post(&ImportEvent::Done(result));
+    }) as Box<dyn FnMut(MessageEvent)>);
+    scope.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+    // The worker lives as long as the page keeps it, so the handler must too.
+    on_message.forget();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wasm_bindgen_test::*;
+
+    /// One of each kind of event, with a document, a table and an error among the outcomes.
+    fn events() -> [ImportEvent; 4] {
+        let gpx = include_bytes!("data/Barton Road-Hardwick Road-Huntingdon Road.gpx");
+        [
+            ImportEvent::Progress(0.25),
+            ImportEvent::Done(GpxFile::import("route.gpx", gpx)),
+            ImportEvent::Done(GpxFile::import("points.csv", b"lat,lon\n52.2,0.1\n")),
+            ImportEvent::Done(GpxFile::import("broken.gpx", b"<gpx>")),
+        ]
+    }
+
+    #[test]
+    fn test_import_event_serde_round_trip() {
+        for event in events() {
+            let json = serde_json::to_string(&event).unwrap();
+            assert_eq!(serde_json::from_str::<ImportEvent>(&json).unwrap(), event);
+        }
+    }
+
+    #[wasm_bindgen_test]
+    fn test_import_event_round_trip() {
+        for event in events() {
+            let value = serde_wasm_bindgen::to_value(&event).unwrap();
+            assert_eq!(
+                serde_wasm_bindgen::from_value::<ImportEvent>(value).unwrap(),
+                event
+            );
+        }
+    }
+}
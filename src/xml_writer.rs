@@ -0,0 +1,17 @@
+//! Helpers for the XML the exporters write with xml-rs into memory.
+
+use xml::writer::{EventWriter, XmlEvent};
+
+pub type XmlWriter = EventWriter<Vec<u8>>;
+
+pub fn write_event<'a>(writer: &mut XmlWriter, event: impl Into<XmlEvent<'a>>) {
+    // Writing to a Vec can only fail on mismatched elements, which would be a bug in the caller.
+    writer.write(event).expect("XML events are balanced");
+}
+
+/// `<name>text</name>`
+pub fn write_text_element(writer: &mut XmlWriter, name: &str, text: &str) {
+    write_event(writer, XmlEvent::start_element(name));
+    write_event(writer, XmlEvent::characters(text));
+    write_event(writer, XmlEvent::end_element());
+}